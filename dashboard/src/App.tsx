@@ -3,13 +3,21 @@ import './App.css';
 import AddUserForm from './components/AddUserForm';
 import ClientsTable from './components/ClientsTable';
 import ProgressBarMax from './components/ProgressBarMax';
+import TokenField from './components/TokenField';
+import { getToken, setToken } from './api';
 import { Container } from '@mui/material';
 
 function App() {
   const [maxBytes, setMaxBytes] = React.useState<string>("100");
+  const [token, setTokenState] = React.useState<string>(getToken());
+  const handleTokenChange = (value: string) => {
+    setToken(value);
+    setTokenState(value);
+  };
   return (
     <Container>
       <ProgressBarMax value={maxBytes} onValueChange={setMaxBytes}></ProgressBarMax>
+      <TokenField value={token} onValueChange={handleTokenChange}></TokenField>
       <AddUserForm></AddUserForm>
       <ClientsTable maxBytes={maxBytes}></ClientsTable>
     </Container>
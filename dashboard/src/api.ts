@@ -0,0 +1,20 @@
+const API_URL = "http://localhost:3000";
+const TOKEN_KEY = "wghttpToken";
+
+export function getToken(): string {
+  return localStorage.getItem(TOKEN_KEY) ?? "";
+}
+
+export function setToken(token: string) {
+  localStorage.setItem(TOKEN_KEY, token);
+}
+
+// Sends the bearer token wghttp was started with, if one was entered.
+export function apiFetch(path: string, init: RequestInit = {}): Promise<Response> {
+  const headers = new Headers(init.headers);
+  const token = getToken();
+  if (token) {
+    headers.set("Authorization", "Bearer " + token);
+  }
+  return fetch(API_URL + path, { ...init, headers });
+}
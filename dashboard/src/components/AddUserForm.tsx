@@ -1,5 +1,6 @@
 import { Button, FormControl, FormLabel, TextField } from "@mui/material";
 import React from "react";
+import { apiFetch } from "../api";
 
 function AddUserForm() {
   const [name, setName] = React.useState<string>("");
@@ -11,7 +12,7 @@ function AddUserForm() {
   return <FormControl sx={{ m: 5 }}>
     <TextField label="Name" onChange={handleChange}>{name}</TextField>
     <Button onClick={() => {
-      fetch("http://localhost:3000/clients", {
+      apiFetch("/clients", {
         method: "post",
         headers: {
           'Content-Type': 'application/json'
@@ -8,6 +8,7 @@ import TableRow from '@mui/material/TableRow';
 import Paper from '@mui/material/Paper';
 import { Button, ButtonGroup, IconButton, LinearProgress, PaletteColorOptions, createTheme, linearProgressClasses, styled } from '@mui/material';
 import { Delete, SimCardDownload } from '@mui/icons-material';
+import { apiFetch } from '../api';
 
 const BorderLinearProgress = styled(LinearProgress)(({ theme }) => ({
   height: 10,
@@ -45,7 +46,7 @@ interface Client {
   id: number;
   name: string;
   ip: string;
-  last_connected: number;
+  last_connected: number | null;
   uploaded: number;
   downloaded: number;
 }
@@ -95,11 +96,25 @@ function secondsToString(seconds: number): string {
   return days + "d " + hours + "h";
 }
 
+// A link can't send the token, so the config is fetched and saved from a blob.
+function downloadConfig(id: number) {
+  apiFetch("/config/" + id).then((resp) => {
+    return resp.blob();
+  }).then((blob) => {
+    const url = URL.createObjectURL(blob);
+    const link = document.createElement("a");
+    link.href = url;
+    link.download = "client" + id + ".conf";
+    link.click();
+    setTimeout(() => URL.revokeObjectURL(url), 0);
+  });
+}
+
 function ClientsTable(props: ClientsTableProps) {
   const [clients, setClients] = React.useState<Client[]>([]);
   React.useEffect(() => {
     setInterval(() => {
-      fetch("http://localhost:3000/clients").then((resp) => {
+      apiFetch("/clients").then((resp) => {
         return resp.json();
       }).then(setClients);
     }, 1000);
@@ -127,14 +142,14 @@ function ClientsTable(props: ClientsTableProps) {
               <TableRow>
                 <TableCell>{item.name}</TableCell>
                 <TableCell>{item.ip}</TableCell>
-                <TableCell>{secondsToString(item.last_connected)} ago</TableCell>
+                <TableCell>{item.last_connected === null ? "never" : secondsToString(item.last_connected) + " ago"}</TableCell>
                 <TableCell>{uploadedBytesStr} / {props.maxBytes}GB{makeProgressBar(item.uploaded, +props.maxBytes)}</TableCell>
                 <TableCell>{downloadedBytesStr} / {props.maxBytes}GB{makeProgressBar(item.downloaded, +props.maxBytes)}</TableCell>
                 <TableCell align='right'>
                   <ButtonGroup size="small" variant="contained">
-                    <Button href={"http://localhost:3000/config/" + item.id}><SimCardDownload></SimCardDownload></Button>
+                    <Button onClick={() => downloadConfig(item.id)}><SimCardDownload></SimCardDownload></Button>
                     <Button onClick={() => {
-                      fetch("http://localhost:3000/clients/" + item.id, {
+                      apiFetch("/clients/" + item.id, {
                         method: "delete",
                       });
                     }}><Delete></Delete></Button>
@@ -0,0 +1,26 @@
+import React from 'react';
+import { TextField } from '@mui/material';
+
+interface TokenFieldProps {
+  value: string;
+  onValueChange: (value: string) => void;
+}
+
+function TokenField(props: TokenFieldProps) {
+  const handleChange = (event: React.ChangeEvent<HTMLInputElement>) => {
+    props.onValueChange(event.target.value);
+  }
+
+  return <TextField
+    label="Token"
+    type="password"
+    value={props.value}
+    onChange={handleChange}
+    sx={{ mt: 5, ml: 2 }}
+    InputLabelProps={{
+      shrink: true,
+    }}
+  />
+}
+
+export default TokenField;
@@ -1,6 +1,12 @@
 use std::path::PathBuf;
 
 use teloxide::{prelude::*, types::InputFile};
+use wglib::{
+    audit::Actor,
+    export::ExportFormat,
+    model::client::ClientUpdate,
+    qr::{ImageFormat, QR_SCALE},
+};
 
 use crate::check_admin;
 
@@ -31,7 +37,7 @@ impl CommandProcessor {
 
     pub async fn up(&self) -> ResponseResult<()> {
         check_admin!(self);
-        match wglib::actions::up(&self.device, &self.config_path) {
+        match wglib::nonblocking::up(&self.device, &self.config_path, &self.actor()).await {
             Ok(()) => {
                 self.bot
                     .send_message(self.msg.chat.id, "wg server started")
@@ -44,7 +50,7 @@ impl CommandProcessor {
 
     pub async fn down(&self) -> ResponseResult<()> {
         check_admin!(self);
-        match wglib::actions::down(&self.device) {
+        match wglib::nonblocking::down(&self.device, &self.config_path, &self.actor()).await {
             Ok(()) => {
                 self.bot
                     .send_message(self.msg.chat.id, "wg server stopped")
@@ -57,7 +63,7 @@ impl CommandProcessor {
 
     pub async fn reboot(&self) -> ResponseResult<()> {
         check_admin!(self);
-        match wglib::actions::reboot(&self.device, &self.config_path) {
+        match wglib::nonblocking::reboot(&self.device, &self.config_path, &self.actor()).await {
             Ok(()) => {
                 self.bot
                     .send_message(self.msg.chat.id, "wg server restarted")
@@ -68,16 +74,33 @@ impl CommandProcessor {
         Ok(())
     }
 
-    pub async fn add_client(&self, name: String) -> ResponseResult<()> {
-        let mut server = match wglib::Server::load_from_file(&self.config_path) {
-            Ok(server) => server,
-            Err(err) => {
-                self.report_to_admin(err).await?;
-                return Ok(());
+    pub async fn reload(&self) -> ResponseResult<()> {
+        check_admin!(self);
+        match wglib::nonblocking::reload(&self.device, &self.config_path, &self.actor()).await {
+            Ok(diff) => {
+                let text = format!(
+                    "wg server reloaded: {} added, {} removed, {} updated",
+                    diff.added.len(),
+                    diff.removed.len(),
+                    diff.updated.len()
+                );
+                self.bot.send_message(self.msg.chat.id, text).await?;
             }
-        };
+            Err(err) => self.report_to_admin(err).await?,
+        }
+        Ok(())
+    }
 
-        let id = match server.add_client(name) {
+    pub async fn add_client(&self, name: String) -> ResponseResult<()> {
+        let id = match wglib::nonblocking::add_client(
+            &self.device,
+            &self.config_path,
+            name,
+            Vec::new(),
+            &self.actor(),
+        )
+        .await
+        {
             Ok(id) => id,
             Err(err) => {
                 self.report_to_admin(err).await?;
@@ -85,7 +108,7 @@ impl CommandProcessor {
             }
         };
 
-        let conf = match server.get_client_wg_config(id) {
+        let conf = match wglib::nonblocking::get_client_wg_config(&self.config_path, id).await {
             Ok(conf) => conf,
             Err(err) => {
                 self.report_to_admin(err).await?;
@@ -93,21 +116,25 @@ impl CommandProcessor {
             }
         };
 
-        match server.dump_to_file(&self.config_path) {
-            Ok(()) => (),
-            Err(err) => {
-                self.report_to_admin(err).await?;
-                return Ok(());
-            }
-        }
-
         self.bot
             .send_document(
                 self.msg.chat.id,
-                InputFile::memory(conf).file_name("connection.conf"),
+                InputFile::memory(conf.clone()).file_name("connection.conf"),
             )
             .await?;
 
+        match wglib::qr::render_image(&conf, ImageFormat::Png, QR_SCALE) {
+            Ok(qr) => {
+                self.bot
+                    .send_photo(
+                        self.msg.chat.id,
+                        InputFile::memory(qr).file_name("connection.png"),
+                    )
+                    .await?;
+            }
+            Err(err) => self.report_to_admin(err).await?,
+        }
+
         self.bot
             .send_message(self.admin_id, format!("added client with id: {}", id))
             .await?;
@@ -117,7 +144,9 @@ impl CommandProcessor {
 
     pub async fn remove_client(&self, id: usize) -> ResponseResult<()> {
         check_admin!(self);
-        match wglib::actions::remove_client(&self.device, &self.config_path, id) {
+        match wglib::nonblocking::remove_client(&self.device, &self.config_path, id, &self.actor())
+            .await
+        {
             Ok(()) => {
                 self.bot
                     .send_message(self.admin_id, format!("removed client with id: {}", id))
@@ -128,9 +157,55 @@ impl CommandProcessor {
         Ok(())
     }
 
+    pub async fn rename_client(&self, id: usize, name: String) -> ResponseResult<()> {
+        check_admin!(self);
+        let update = ClientUpdate {
+            name: Some(name),
+            ..Default::default()
+        };
+        self.edit_client(id, update).await
+    }
+
+    pub async fn set_client_disabled(&self, id: usize, disabled: bool) -> ResponseResult<()> {
+        check_admin!(self);
+        let update = ClientUpdate {
+            disabled: Some(disabled),
+            ..Default::default()
+        };
+        self.edit_client(id, update).await
+    }
+
+    async fn edit_client(&self, id: usize, update: ClientUpdate) -> ResponseResult<()> {
+        match wglib::nonblocking::edit_client(
+            &self.device,
+            &self.config_path,
+            id,
+            update,
+            &self.actor(),
+        )
+        .await
+        {
+            Ok(client) => {
+                let state = if client.disabled {
+                    "disabled"
+                } else {
+                    "enabled"
+                };
+                self.bot
+                    .send_message(
+                        self.admin_id,
+                        format!("updated client {}: {} ({})", client.id, client.name, state),
+                    )
+                    .await?;
+            }
+            Err(err) => self.report_to_admin(err).await?,
+        }
+        Ok(())
+    }
+
     pub async fn list_clients(&self) -> ResponseResult<()> {
         check_admin!(self);
-        match wglib::actions::list_clients(&self.config_path) {
+        match wglib::nonblocking::list_clients(&self.config_path).await {
             Ok(clients) => {
                 let clients: Vec<String> = clients
                     .iter()
@@ -144,6 +219,34 @@ impl CommandProcessor {
         Ok(())
     }
 
+    pub async fn export_client(&self, id: usize, format: String) -> ResponseResult<()> {
+        check_admin!(self);
+        let files = match format.parse::<ExportFormat>() {
+            Ok(format) => {
+                wglib::nonblocking::export_client_config(&self.config_path, id, format).await
+            }
+            Err(err) => Err(err),
+        };
+        match files {
+            Ok(files) => {
+                for file in files {
+                    self.bot
+                        .send_document(
+                            self.msg.chat.id,
+                            InputFile::memory(file.contents).file_name(file.name),
+                        )
+                        .await?;
+                }
+            }
+            Err(err) => self.report_to_admin(err).await?,
+        }
+        Ok(())
+    }
+
+    fn actor(&self) -> Actor {
+        Actor::Telegram(self.msg.chat.id.0)
+    }
+
     async fn report_to_admin(&self, err: anyhow::Error) -> ResponseResult<()> {
         let username = self.msg.chat.username().unwrap_or("-");
         self.bot
@@ -1,9 +1,13 @@
 mod commands;
 mod macros;
+mod notifications;
 
 use clap::Parser;
 use commands::CommandProcessor;
-use teloxide::{prelude::*, utils::command::BotCommands};
+use teloxide::{
+    prelude::*,
+    utils::command::{BotCommands, ParseError},
+};
 
 #[derive(Parser)]
 pub struct Cli {
@@ -15,6 +19,12 @@ pub struct Cli {
     config_path: std::path::PathBuf,
     #[arg(long)]
     admin_id: i64,
+    #[arg(long)]
+    history_path: Option<std::path::PathBuf>,
+    #[arg(long)]
+    notify_sessions: bool,
+    #[arg(long, default_value_t = 60)]
+    collect_interval: u64,
 }
 
 #[derive(BotCommands, Clone)]
@@ -23,9 +33,30 @@ enum Command {
     Up,
     Down,
     Reboot,
-    AddClient { name: String },
-    RemoveClient { id: usize },
+    Reload,
+    AddClient {
+        name: String,
+    },
+    RemoveClient {
+        id: usize,
+    },
+    #[command(parse_with = parse_id_and_name)]
+    RenameClient {
+        id: usize,
+        name: String,
+    },
+    DisableClient {
+        id: usize,
+    },
+    EnableClient {
+        id: usize,
+    },
     ListClients,
+    #[command(parse_with = "split")]
+    ExportClient {
+        id: usize,
+        format: String,
+    },
 }
 
 #[tokio::main]
@@ -33,6 +64,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     sudo::escalate_if_needed()?;
     let cli = Cli::parse();
     let bot = Bot::new(&cli.token);
+    if cli.notify_sessions {
+        let history_path = match cli.history_path {
+            Some(path) => path,
+            None => wglib::history::default_path(&cli.config_path),
+        };
+        tokio::spawn(notifications::notify_sessions(
+            bot.clone(),
+            cli.device,
+            cli.config_path,
+            history_path,
+            ChatId(cli.admin_id),
+            std::time::Duration::from_secs(cli.collect_interval),
+        ));
+    }
     Command::repl(bot, |bot: Bot, msg: Message, cmd: Command| async move {
         let cli = Cli::parse();
         let processor =
@@ -41,11 +86,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             Command::Up => processor.up().await,
             Command::Down => processor.down().await,
             Command::Reboot => processor.reboot().await,
+            Command::Reload => processor.reload().await,
             Command::AddClient { name } => processor.add_client(name).await,
             Command::RemoveClient { id } => processor.remove_client(id).await,
+            Command::RenameClient { id, name } => processor.rename_client(id, name).await,
+            Command::DisableClient { id } => processor.set_client_disabled(id, true).await,
+            Command::EnableClient { id } => processor.set_client_disabled(id, false).await,
             Command::ListClients => processor.list_clients().await,
+            Command::ExportClient { id, format } => processor.export_client(id, format).await,
         }
     })
     .await;
     Ok(())
 }
+
+/// Splits `id name` keeping spaces inside the name.
+fn parse_id_and_name(input: String) -> Result<(usize, String), ParseError> {
+    let (id, name) = input
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| ParseError::Custom("expected id and name".into()))?;
+    let id = id
+        .parse()
+        .map_err(|err| ParseError::IncorrectFormat(Box::new(err)))?;
+    Ok((id, name.trim().to_string()))
+}
@@ -0,0 +1,57 @@
+use std::{path::PathBuf, time::Duration};
+
+use teloxide::prelude::*;
+use wglib::sessions::SessionEvent;
+
+pub async fn notify_sessions(
+    bot: Bot,
+    device: String,
+    config_path: PathBuf,
+    history_path: PathBuf,
+    admin_id: ChatId,
+    interval: Duration,
+) {
+    // Other processes may collect the same history, so events are read from its log instead of
+    // taken from the collection of this process.
+    let consumer = format!("wgbot:{}", admin_id.0);
+    // Starts reading at the end of the log, errors are reported by the loop.
+    let _ = wglib::nonblocking::take_session_events(&config_path, &history_path, &consumer).await;
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        let result =
+            match wglib::nonblocking::collect_history(&device, &config_path, &history_path).await {
+                Ok(_) => {
+                    wglib::nonblocking::take_session_events(&config_path, &history_path, &consumer)
+                        .await
+                }
+                Err(err) => Err(err),
+            };
+
+        let text = match result {
+            Ok(events) => {
+                for event in events {
+                    if let Err(err) = bot.send_message(admin_id, format_event(&event)).await {
+                        eprintln!("failed to send session notification: {err}");
+                    }
+                }
+                continue;
+            }
+            Err(err) => format!("failed to collect sessions: |{}|", err),
+        };
+        if let Err(err) = bot.send_message(admin_id, text).await {
+            eprintln!("failed to report collector error: {err}");
+        }
+    }
+}
+
+fn format_event(event: &SessionEvent) -> String {
+    let endpoint = match event.endpoint {
+        Some(endpoint) => format!(" from {}", endpoint),
+        None => String::new(),
+    };
+    format!(
+        "client {} ({}) {}{}",
+        event.client_id, event.client_name, event.kind, endpoint
+    )
+}
@@ -0,0 +1,30 @@
+use axum::{extract::Query, http::StatusCode, Json};
+use clap::Parser;
+use serde::Deserialize;
+use wglib::audit::{AuditEntry, AuditFilter};
+
+use crate::Cli;
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    client: Option<usize>,
+    action: Option<String>,
+    since: Option<u64>,
+    limit: Option<usize>,
+}
+
+pub async fn get_audit(
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, (StatusCode, String)> {
+    let cli = Cli::parse();
+    let filter = AuditFilter {
+        action: query.action,
+        client_id: query.client,
+        since: query.since,
+        limit: query.limit,
+    };
+    match wglib::nonblocking::get_audit_log(&cli.config_path, filter).await {
+        Ok(entries) => Ok(Json(entries)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
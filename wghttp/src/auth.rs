@@ -0,0 +1,56 @@
+use axum::{
+    http::{header, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use clap::Parser;
+use subtle::ConstantTimeEq;
+use wglib::audit::Actor;
+
+use crate::Cli;
+
+const ANONYMOUS: &str = "anonymous";
+
+/// Checks the bearer token against `--token name:secret` arguments and stores the
+/// authenticated [`Actor`] in request extensions. Without configured tokens every request is
+/// accepted as anonymous.
+pub async fn authenticate<B>(
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Result<Response, (StatusCode, String)> {
+    let cli = Cli::parse();
+    let actor = if cli.tokens.is_empty() {
+        Actor::Http(ANONYMOUS.to_string())
+    } else {
+        let secret = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match secret.and_then(|secret| find_token(&cli.tokens, secret)) {
+            Some(name) => Actor::Http(name),
+            None => {
+                return Err((
+                    StatusCode::UNAUTHORIZED,
+                    "missing or invalid token".to_string(),
+                ))
+            }
+        }
+    };
+    request.extensions_mut().insert(actor);
+    Ok(next.run(request).await)
+}
+
+/// Compares `secret` with every token in constant time, so timing doesn't reveal how much of
+/// it matched.
+fn find_token(tokens: &[String], secret: &str) -> Option<String> {
+    let mut found = None;
+    for token in tokens {
+        if let Some((name, token_secret)) = token.split_once(':') {
+            if bool::from(token_secret.as_bytes().ct_eq(secret.as_bytes())) && found.is_none() {
+                found = Some(name.to_string());
+            }
+        }
+    }
+    found
+}
@@ -0,0 +1,34 @@
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use clap::Parser;
+
+use crate::Cli;
+
+/// Backups contain the private keys, so they are only served to authenticated requests.
+pub async fn get_backup() -> Result<impl IntoResponse, (StatusCode, String)> {
+    let cli = Cli::parse();
+    if cli.tokens.is_empty() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "backups require wghttp to be started with --token".to_string(),
+        ));
+    }
+    match wglib::nonblocking::backup(&cli.config_path, &cli.history_path()).await {
+        Ok((manifest, archive)) => Ok((
+            [
+                (header::CONTENT_TYPE, "application/gzip".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!(
+                        "attachment; filename=\"wgsuite-backup-{}.tar.gz\"",
+                        manifest.created_at
+                    ),
+                ),
+            ],
+            archive,
+        )),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
@@ -1,28 +1,69 @@
-use axum::http::StatusCode;
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
 use clap::Parser;
+use wglib::{audit::Actor, plan::Operation};
 
-use crate::Cli;
+use crate::{
+    plan::{self, DryRunQuery},
+    Cli,
+};
 
-pub async fn up() -> Result<(), (StatusCode, String)> {
+pub async fn up(
+    Extension(actor): Extension<Actor>,
+    Query(query): Query<DryRunQuery>,
+) -> Result<Response, (StatusCode, String)> {
     let cli = Cli::parse();
-    match wglib::actions::up(&cli.device, &cli.config_path) {
-        Ok(()) => Ok(()),
+    if query.dry_run {
+        return plan::preview(&cli, Operation::Up).await;
+    }
+    match wglib::nonblocking::up(&cli.device, &cli.config_path, &actor).await {
+        Ok(()) => Ok(().into_response()),
         Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
     }
 }
 
-pub async fn down() -> Result<(), (StatusCode, String)> {
+pub async fn down(
+    Extension(actor): Extension<Actor>,
+    Query(query): Query<DryRunQuery>,
+) -> Result<Response, (StatusCode, String)> {
     let cli = Cli::parse();
-    match wglib::actions::down(&cli.device) {
-        Ok(()) => Ok(()),
+    if query.dry_run {
+        return plan::preview(&cli, Operation::Down).await;
+    }
+    match wglib::nonblocking::down(&cli.device, &cli.config_path, &actor).await {
+        Ok(()) => Ok(().into_response()),
         Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
     }
 }
 
-pub async fn reboot() -> Result<(), (StatusCode, String)> {
+pub async fn reboot(
+    Extension(actor): Extension<Actor>,
+    Query(query): Query<DryRunQuery>,
+) -> Result<Response, (StatusCode, String)> {
     let cli = Cli::parse();
-    match wglib::actions::reboot(&cli.device, &cli.config_path) {
-        Ok(()) => Ok(()),
+    if query.dry_run {
+        return plan::preview(&cli, Operation::Reboot).await;
+    }
+    match wglib::nonblocking::reboot(&cli.device, &cli.config_path, &actor).await {
+        Ok(()) => Ok(().into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+pub async fn reload(
+    Extension(actor): Extension<Actor>,
+    Query(query): Query<DryRunQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let cli = Cli::parse();
+    if query.dry_run {
+        return plan::preview(&cli, Operation::Reload).await;
+    }
+    match wglib::nonblocking::reload(&cli.device, &cli.config_path, &actor).await {
+        Ok(diff) => Ok(Json(diff).into_response()),
         Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
     }
 }
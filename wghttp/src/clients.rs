@@ -1,31 +1,152 @@
-use std::{collections::HashMap, net::Ipv4Addr};
+use std::net::Ipv4Addr;
 
-use axum::{extract::Path, http::StatusCode, Json};
+use axum::{
+    extract::{Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
 use clap::Parser;
 use serde::{Deserialize, Serialize};
-use wglib::Server;
+use wglib::{
+    audit::Actor,
+    export::{self, ExportFormat},
+    import,
+    model::client::ClientUpdate,
+    plan::Operation,
+    qr::{ImageFormat, QR_SCALE},
+    status::DeviceStatus,
+};
 
-use crate::Cli;
+use crate::{
+    plan::{self, DryRunQuery},
+    Cli,
+};
+
+#[derive(Deserialize)]
+pub struct ConfigQuery {
+    format: Option<ExportFormat>,
+}
+
+#[derive(Deserialize)]
+pub struct QrQuery {
+    format: Option<ImageFormat>,
+}
 
 #[derive(Deserialize)]
 pub struct AddClient {
     name: String,
+    #[serde(default)]
+    tags: Vec<String>,
 }
 
 pub async fn add_client(
+    Extension(actor): Extension<Actor>,
+    Query(query): Query<DryRunQuery>,
     Json(payload): Json<AddClient>,
-) -> Result<Json<usize>, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
+    let cli = Cli::parse();
+    if query.dry_run {
+        let operation = Operation::AddClient {
+            name: payload.name,
+            tags: payload.tags,
+        };
+        return plan::preview(&cli, operation).await;
+    }
+    match wglib::nonblocking::add_client(
+        &cli.device,
+        &cli.config_path,
+        payload.name,
+        payload.tags,
+        &actor,
+    )
+    .await
+    {
+        Ok(id) => Ok(Json(id).into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+/// Creates clients from a JSON array or, with `Content-Type: text/csv`, a CSV file and responds
+/// with a zip archive of their configs.
+pub async fn add_clients_bulk(
+    Extension(actor): Extension<Actor>,
+    Query(query): Query<DryRunQuery>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Response, (StatusCode, String)> {
     let cli = Cli::parse();
-    match wglib::actions::add_client(&cli.device, &cli.config_path, payload.name) {
-        Ok(id) => Ok(Json(id)),
+    let is_csv = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/csv"));
+    let specs = if is_csv {
+        import::parse_csv(&body)
+    } else {
+        import::parse_json(&body)
+    }
+    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    if query.dry_run {
+        return plan::preview(&cli, Operation::ImportClients(specs)).await;
+    }
+
+    let result = match wglib::nonblocking::import_clients(
+        &cli.device,
+        &cli.config_path,
+        specs,
+        &actor,
+    )
+    .await
+    {
+        Ok(clients) => {
+            let ids = clients.iter().map(|client| client.id).collect();
+            wglib::nonblocking::export_configs_zip(&cli.config_path, ids).await
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(archive) => Ok((
+            [
+                (header::CONTENT_TYPE, "application/zip"),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"clients.zip\"",
+                ),
+            ],
+            archive,
+        )
+            .into_response()),
         Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
     }
 }
 
-pub async fn remove_client(Path(id): Path<usize>) -> Result<(), (StatusCode, String)> {
+pub async fn edit_client(
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<usize>,
+    Query(query): Query<DryRunQuery>,
+    Json(update): Json<ClientUpdate>,
+) -> Result<Response, (StatusCode, String)> {
     let cli = Cli::parse();
-    match wglib::actions::remove_client(&cli.device, &cli.config_path, id) {
-        Ok(_) => Ok(()),
+    if query.dry_run {
+        return plan::preview(&cli, Operation::EditClient(id, update)).await;
+    }
+    match wglib::nonblocking::edit_client(&cli.device, &cli.config_path, id, update, &actor).await {
+        Ok(_) => Ok(().into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+pub async fn remove_client(
+    Extension(actor): Extension<Actor>,
+    Path(id): Path<usize>,
+    Query(query): Query<DryRunQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let cli = Cli::parse();
+    if query.dry_run {
+        return plan::preview(&cli, Operation::RemoveClient(id)).await;
+    }
+    match wglib::nonblocking::remove_client(&cli.device, &cli.config_path, id, &actor).await {
+        Ok(_) => Ok(().into_response()),
         Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
     }
 }
@@ -35,52 +156,67 @@ pub struct Client {
     id: usize,
     name: String,
     ip: Ipv4Addr,
-    last_connected: u64,
+    last_connected: Option<u64>,
     uploaded: u128,
     downloaded: u128,
 }
 
+/// Every client of the config, the ones without a peer on the device have no traffic.
 pub async fn get_clients() -> Result<Json<Vec<Client>>, (StatusCode, String)> {
     let cli = Cli::parse();
-    match get_clients_impl(&cli.device, &cli.config_path) {
-        Ok(clients) => Ok(Json(clients)),
+    match wglib::nonblocking::get_status(&cli.device, &cli.config_path).await {
+        Ok(status) => Ok(Json(
+            status
+                .clients
+                .into_iter()
+                .map(|client| Client {
+                    id: client.id,
+                    name: client.name,
+                    ip: client.ip,
+                    last_connected: client.latest_handshake,
+                    uploaded: client.received_bytes,
+                    downloaded: client.sent_bytes,
+                })
+                .collect(),
+        )),
         Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
     }
 }
 
-fn get_clients_impl(device: &str, config_path: &std::path::Path) -> anyhow::Result<Vec<Client>> {
-    let server = Server::load_from_file(config_path)?;
-    let clients = server.clients;
-    let metrics = wglib::metrics::get_metrics(device)?;
+pub async fn get_status() -> Result<Json<DeviceStatus>, (StatusCode, String)> {
+    let cli = Cli::parse();
+    match wglib::nonblocking::get_status(&cli.device, &cli.config_path).await {
+        Ok(status) => Ok(Json(status)),
+        Err(e) => Err((StatusCode::BAD_REQUEST, e.to_string())),
+    }
+}
 
-    let mut clients: HashMap<String, (usize, String, Ipv4Addr)> = clients
-        .into_values()
-        .map(|client| {
-            let ip = client.get_ip_address(&server.subnet);
-            (client.keys.public, (client.id, client.name, ip))
-        })
-        .collect();
-    Ok(metrics
-        .into_iter()
-        .filter_map(move |metric| {
-            clients
-                .remove(&metric.public_key)
-                .map(|(id, name, ip)| Client {
-                    id,
-                    name,
-                    ip,
-                    last_connected: metric.latest_handshake,
-                    uploaded: metric.received_bytes,
-                    downloaded: metric.sent_bytes,
-                })
-        })
-        .collect())
+pub async fn get_config(
+    Path(id): Path<usize>,
+    Query(query): Query<ConfigQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let cli = Cli::parse();
+    let format = query.format.unwrap_or(ExportFormat::WgQuick);
+    match wglib::nonblocking::export_client_config(&cli.config_path, id, format).await {
+        Ok(files) => Ok((
+            [(header::CONTENT_TYPE, format.content_type())],
+            export::join(&files),
+        )),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
 }
 
-pub async fn get_config(Path(id): Path<usize>) -> Result<String, (StatusCode, String)> {
+pub async fn get_config_qr(
+    Path(id): Path<usize>,
+    Query(query): Query<QrQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
     let cli = Cli::parse();
-    match wglib::actions::get_client_wg_config(&cli.config_path, id) {
-        Ok(config) => Ok(config),
+    let format = query.format.unwrap_or(ImageFormat::Png);
+    match wglib::nonblocking::get_client_wg_config(&cli.config_path, id)
+        .await
+        .and_then(|config| wglib::qr::render_image(&config, format, QR_SCALE))
+    {
+        Ok(image) => Ok(([(header::CONTENT_TYPE, format.content_type())], image)),
         Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
     }
 }
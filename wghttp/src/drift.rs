@@ -0,0 +1,55 @@
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use clap::Parser;
+use serde::Deserialize;
+use wglib::{
+    audit::Actor,
+    plan::Operation,
+    sync::{Drift, RepairDirection},
+};
+
+use crate::{plan, Cli};
+
+#[derive(Deserialize)]
+pub struct RepairQuery {
+    direction: RepairDirection,
+    #[serde(default)]
+    dry_run: bool,
+    /// Repairing towards the config removes clients, so it has to be confirmed.
+    #[serde(default)]
+    yes: bool,
+}
+
+pub async fn get_drift() -> Result<Json<Drift>, (StatusCode, String)> {
+    let cli = Cli::parse();
+    match wglib::nonblocking::get_drift(&cli.device, &cli.config_path).await {
+        Ok(drift) => Ok(Json(drift)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+pub async fn repair_drift(
+    Extension(actor): Extension<Actor>,
+    Query(query): Query<RepairQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let cli = Cli::parse();
+    if query.dry_run {
+        return plan::preview(&cli, Operation::RepairDrift(query.direction)).await;
+    }
+    if query.direction == RepairDirection::Config && !query.yes {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "repairing towards the config removes clients, confirm it with yes=true".to_string(),
+        ));
+    }
+    match wglib::nonblocking::repair_drift(&cli.device, &cli.config_path, query.direction, &actor)
+        .await
+    {
+        Ok(drift) => Ok(Json(drift).into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
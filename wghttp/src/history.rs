@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    Json,
+};
+use clap::Parser;
+use serde::Deserialize;
+use wglib::{
+    history::{self, Bucket, TrafficPoint, TrafficTotal},
+    sessions::Session,
+};
+
+use crate::Cli;
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    client: Option<usize>,
+    bucket: Option<Bucket>,
+    from: Option<u64>,
+    to: Option<u64>,
+}
+
+pub async fn get_history(
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<TrafficPoint>>, (StatusCode, String)> {
+    let cli = Cli::parse();
+    let to = match query.to {
+        Some(to) => to,
+        None => history::unix_now().map_err(internal_error)?,
+    };
+    let from = query.from.unwrap_or(0);
+    let bucket = query.bucket.unwrap_or(Bucket::Hour);
+    match wglib::nonblocking::get_traffic_history(
+        &cli.config_path,
+        &cli.history_path(),
+        query.client,
+        from,
+        to,
+        bucket,
+    )
+    .await
+    {
+        Ok(points) => Ok(Json(points)),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+pub async fn get_history_totals() -> Result<Json<Vec<TrafficTotal>>, (StatusCode, String)> {
+    let cli = Cli::parse();
+    match wglib::nonblocking::get_traffic_totals(&cli.config_path, &cli.history_path()).await {
+        Ok(totals) => Ok(Json(totals)),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SessionsQuery {
+    limit: Option<usize>,
+}
+
+pub async fn get_sessions(
+    Path(id): Path<usize>,
+    Query(query): Query<SessionsQuery>,
+) -> Result<Json<Vec<Session>>, (StatusCode, String)> {
+    let cli = Cli::parse();
+    let limit = query.limit.unwrap_or(100);
+    match wglib::nonblocking::get_sessions(&cli.config_path, &cli.history_path(), id, limit).await {
+        Ok(sessions) => Ok(Json(sessions)),
+        Err(e) => Err(internal_error(e)),
+    }
+}
+
+pub async fn run_collector() {
+    let cli = Cli::parse();
+    let mut interval = tokio::time::interval(Duration::from_secs(cli.collect_interval));
+    loop {
+        interval.tick().await;
+        let cli = Cli::parse();
+        let result =
+            wglib::nonblocking::collect_history(&cli.device, &cli.config_path, &cli.history_path())
+                .await;
+        if let Err(e) = result {
+            eprintln!("failed to collect traffic history: {e}");
+        }
+    }
+}
+
+fn internal_error(e: anyhow::Error) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
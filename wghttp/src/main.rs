@@ -1,17 +1,30 @@
+mod audit;
+mod auth;
+mod backup;
 mod boot;
 mod clients;
+mod drift;
+mod history;
+mod metrics;
+mod plan;
 
 use std::{net::SocketAddr, path::PathBuf};
 
 use axum::{
-    http::Method,
+    http::{header, Method},
+    middleware,
     routing::{delete, get, post},
     Router,
 };
 use clap::Parser;
 
+use audit::*;
+use backup::*;
 use boot::*;
 use clients::*;
+use drift::*;
+use history::*;
+use metrics::*;
 
 #[derive(Parser)]
 struct Cli {
@@ -19,25 +32,55 @@ struct Cli {
     device: String,
     #[arg(long)]
     config_path: PathBuf,
+    #[arg(long)]
+    history_path: Option<PathBuf>,
+    #[arg(long, default_value_t = 60)]
+    collect_interval: u64,
+    /// Accepted bearer token in `name:secret` format, can be repeated.
+    #[arg(long = "token")]
+    tokens: Vec<String>,
+}
+
+impl Cli {
+    fn history_path(&self) -> PathBuf {
+        match &self.history_path {
+            Some(path) => path.clone(),
+            None => wglib::history::default_path(&self.config_path),
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     sudo::escalate_if_needed()?;
     Cli::parse();
+    tokio::spawn(run_collector());
 
     let app = Router::new()
         .route("/up", post(up))
         .route("/down", post(down))
         .route("/reboot", post(reboot))
+        .route("/reload", post(reload))
         .route("/clients", post(add_client))
-        .route("/clients/:id", delete(remove_client))
+        .route("/clients/bulk", post(add_clients_bulk))
+        .route("/clients/:id", delete(remove_client).patch(edit_client))
+        .route("/clients/:id/sessions", get(get_sessions))
         .route("/clients", get(get_clients))
+        .route("/status", get(get_status))
         .route("/config/:id", get(get_config))
+        .route("/config/:id/qr", get(get_config_qr))
+        .route("/history", get(get_history))
+        .route("/history/totals", get(get_history_totals))
+        .route("/metrics", get(get_metrics))
+        .route("/audit", get(get_audit))
+        .route("/backup", get(get_backup))
+        .route("/drift", get(get_drift))
+        .route("/drift/repair", post(repair_drift))
+        .route_layer(middleware::from_fn(auth::authenticate))
         .layer(
             tower_http::cors::CorsLayer::new()
-                .allow_methods([Method::GET, Method::POST, Method::DELETE])
-                .allow_headers(tower_http::cors::Any)
+                .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+                .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
                 .allow_origin(tower_http::cors::Any),
         );
 
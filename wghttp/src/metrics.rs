@@ -0,0 +1,32 @@
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+};
+use clap::Parser;
+use wglib::Server;
+
+use crate::Cli;
+
+pub async fn get_metrics() -> Result<impl IntoResponse, (StatusCode, String)> {
+    let cli = Cli::parse();
+    let device = cli.device.clone();
+    let result = wglib::nonblocking::read(&cli.config_path, move |config_path| {
+        get_metrics_impl(&device, config_path)
+    })
+    .await;
+    match result {
+        Ok(text) => Ok(([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], text)),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+fn get_metrics_impl(device: &str, config_path: &std::path::Path) -> anyhow::Result<String> {
+    let server = Server::load_from_file(config_path)?;
+    let stats = if wglib::actions::is_wg_started(device)? {
+        Some(wglib::metrics::get_device_stats(device)?)
+    } else {
+        None
+    };
+    let counters = wglib::actions::action_counters(config_path)?;
+    wglib::prometheus::render(device, &server, stats.as_ref(), &counters)
+}
@@ -0,0 +1,23 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use wglib::plan::Operation;
+
+use crate::Cli;
+
+/// `?dry_run=true` makes a state-changing endpoint respond with the plan instead of executing.
+#[derive(Deserialize)]
+pub struct DryRunQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+pub async fn preview(cli: &Cli, operation: Operation) -> Result<Response, (StatusCode, String)> {
+    match wglib::nonblocking::dry_run(&cli.device, &cli.config_path, operation).await {
+        Ok(plan) => Ok(Json(plan).into_response()),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
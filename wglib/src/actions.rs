@@ -1,50 +1,242 @@
-use std::{io::Write, path::Path};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
 
 use anyhow::anyhow;
-use sysctl::Sysctl;
+use ipnet::Ipv4Net;
 
-use crate::{Client, Server};
+use crate::{
+    audit::{self, ActionCounter, Actor, AuditEntry, AuditFilter},
+    backup::{self, BackupPaths, Manifest},
+    export::{self, ExportFormat, ExportedFile},
+    history::{self, Bucket, History, TrafficPoint, TrafficTotal},
+    import::{self, ClientSpec},
+    metrics,
+    model::{client::ClientUpdate, pool::AddressPool, server::Renumbered},
+    plan::{Executor, Operation, Plan},
+    service::{self, ServiceOptions},
+    sessions::{Session, SessionEvent},
+    status::{self, ClientStatus, DeviceStatus},
+    storage::{self, StorageKind},
+    sync::{self, Drift, PeerDiff, RepairDirection},
+    Client, Server,
+};
 
 #[cfg(target_os = "linux")]
 const CTLNAME: &str = "net.ipv4.ip_forward";
 
-pub fn up(device: &str, config_path: &Path) -> anyhow::Result<()> {
-    let server = Server::load_from_file(config_path)?;
-    dump_wg_config(&server, device)?;
-    start_wg(device)?;
-    Ok(())
+pub fn up(device: &str, config_path: &Path, actor: &Actor) -> anyhow::Result<()> {
+    let result = up_impl(device, config_path, &mut Executor::new(false));
+    record_action(config_path, actor, "up", None, result)
 }
 
-pub fn down(device: &str) -> anyhow::Result<()> {
-    stop_wg(device)
+pub fn down(device: &str, config_path: &Path, actor: &Actor) -> anyhow::Result<()> {
+    let result = stop_wg(device);
+    record_action(config_path, actor, "down", None, result)
 }
 
-pub fn reboot(device: &str, config_path: &Path) -> anyhow::Result<()> {
-    let server = Server::load_from_file(config_path)?;
-    dump_wg_config(&server, device)?;
-    restart_wg(device)?;
-    Ok(())
+pub fn reboot(device: &str, config_path: &Path, actor: &Actor) -> anyhow::Result<()> {
+    let result = reboot_impl(device, config_path, &mut Executor::new(false));
+    record_action(config_path, actor, "reboot", None, result)
 }
 
-pub fn add_client(device: &str, config_path: &Path, name: String) -> anyhow::Result<usize> {
-    let mut server = Server::load_from_file(config_path)?;
-    let id = server.add_client(name)?;
-    let client = server.get_client(id)?;
-    if is_wg_started(device)? {
-        wg_update_with_client(device, WgUpdatedClient::Added(client), &server)?;
+/// Previews `operation` without executing anything: returns the diffs of the stored config and
+/// of the wireguard config, and the commands which would run. The state of the interface is
+/// still queried, so the plan matches what the operation would do right now.
+pub fn dry_run(device: &str, config_path: &Path, operation: Operation) -> anyhow::Result<Plan> {
+    // A backup may be restored before there is a config.
+    let before = match config_path.exists() {
+        true => Some(storage::open(config_path)?.load()?),
+        false => None,
+    };
+    let mut executor = Executor::new(true);
+    run_operation(device, config_path, operation, &mut executor)?;
+    executor.into_plan(before.as_ref(), config_path, device)
+}
+
+/// Applies config changes to the running interface without restarting it, so connected
+/// clients keep their tunnels. Returns the applied difference.
+pub fn reload(device: &str, config_path: &Path, actor: &Actor) -> anyhow::Result<PeerDiff> {
+    let result = reload_impl(device, config_path, &mut Executor::new(false));
+    record_action(config_path, actor, "reload", None, result)
+}
+
+/// Returns peers on which the config and the live interface disagree.
+pub fn get_drift(device: &str, config_path: &Path) -> anyhow::Result<Drift> {
+    let server = Server::load_from_file(config_path)?;
+    if !is_wg_started(device)? {
+        return Err(anyhow!("device {} is not running", device));
     }
-    server.dump_to_file(config_path)?;
-    Ok(id)
+    let stats = metrics::get_device_stats(device)?;
+    Ok(sync::drift(&server, &stats))
 }
 
-pub fn remove_client(device: &str, config_path: &Path, id: usize) -> anyhow::Result<()> {
-    let mut server = Server::load_from_file(config_path)?;
-    let client = server.remove_client(id)?;
-    if is_wg_started(device)? {
-        wg_update_with_client(device, WgUpdatedClient::Removed(client), &server)?;
+/// Brings the config and the live interface back in sync. Returns the drift which couldn't be
+/// repaired: peers added by hand can't be imported into the config since their private keys
+/// are unknown, and allowed ips of configured clients are derived from their ids.
+pub fn repair_drift(
+    device: &str,
+    config_path: &Path,
+    direction: RepairDirection,
+    actor: &Actor,
+) -> anyhow::Result<Drift> {
+    let result = repair_drift_impl(device, config_path, direction, &mut Executor::new(false));
+    match result {
+        // Every removed client gets its own entry, so it shows up in the client's audit log.
+        Ok((drift, removed)) if !removed.is_empty() => {
+            for id in removed {
+                record_action(config_path, actor, "repair_drift", Some(id), Ok(()))?;
+            }
+            Ok(drift)
+        }
+        result => {
+            let result = result.map(|(drift, _)| drift);
+            record_action(config_path, actor, "repair_drift", None, result)
+        }
     }
-    server.dump_to_file(config_path)?;
-    Ok(())
+}
+
+pub fn add_client(
+    device: &str,
+    config_path: &Path,
+    name: String,
+    tags: Vec<String>,
+    actor: &Actor,
+) -> anyhow::Result<usize> {
+    let result = add_client_impl(device, config_path, name, tags, &mut Executor::new(false));
+    let id = result.as_ref().ok().copied();
+    record_action(config_path, actor, "add_client", id, result)
+}
+
+pub fn remove_client(
+    device: &str,
+    config_path: &Path,
+    id: usize,
+    actor: &Actor,
+) -> anyhow::Result<()> {
+    let result = remove_client_impl(device, config_path, id, &mut Executor::new(false));
+    record_action(config_path, actor, "remove_client", Some(id), result)
+}
+
+/// Creates all clients or none of them, and adds them to the running interface with a single
+/// update.
+pub fn import_clients(
+    device: &str,
+    config_path: &Path,
+    specs: &[ClientSpec],
+    actor: &Actor,
+) -> anyhow::Result<Vec<Client>> {
+    let result = import_clients_impl(device, config_path, specs, &mut Executor::new(false));
+    record_action(config_path, actor, "import_clients", None, result)
+}
+
+/// Writes a zip archive with wg-quick configs of the given clients.
+pub fn export_configs_zip(
+    config_path: &Path,
+    ids: &[usize],
+    writer: impl Write + Seek,
+) -> anyhow::Result<()> {
+    let server = Server::load_from_file(config_path)?;
+    import::write_configs_zip(&server, ids, writer)
+}
+
+/// Moves the server to another subnet keeping all keys, regenerates the wireguard config and
+/// restarts the interface if it's running. Returns the clients which need a new config.
+pub fn resize_subnet(
+    device: &str,
+    config_path: &Path,
+    subnet: Ipv4Net,
+    actor: &Actor,
+) -> anyhow::Result<Vec<Renumbered>> {
+    let result = resize_subnet_impl(device, config_path, subnet, &mut Executor::new(false));
+    record_action(config_path, actor, "resize_subnet", None, result)
+}
+
+/// Replaces the address pool. Existing clients must already have addresses inside of it.
+pub fn set_address_pool(
+    config_path: &Path,
+    pool: AddressPool,
+    actor: &Actor,
+) -> anyhow::Result<()> {
+    let result = set_address_pool_impl(config_path, pool, &mut Executor::new(false));
+    record_action(config_path, actor, "set_address_pool", None, result)
+}
+
+/// Installs the systemd setup described by `options`, see [`service`]. Returns the written files.
+pub fn install_service(options: &ServiceOptions, actor: &Actor) -> anyhow::Result<Vec<PathBuf>> {
+    let result = service::install(options, &mut Executor::new(false));
+    record_action(&options.config_path, actor, "install_service", None, result)
+}
+
+/// Removes what [`install_service`] added. Returns the removed files.
+pub fn uninstall_service(
+    device: &str,
+    config_path: &Path,
+    actor: &Actor,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let result = service::uninstall(device, &mut Executor::new(false));
+    record_action(config_path, actor, "uninstall_service", None, result)
+}
+
+/// Changes the client without re-issuing its keys. Disabling or enabling a client is applied to
+/// the running interface, the other settings only affect the client config.
+pub fn edit_client(
+    device: &str,
+    config_path: &Path,
+    id: usize,
+    update: ClientUpdate,
+    actor: &Actor,
+) -> anyhow::Result<Client> {
+    let result = edit_client_impl(device, config_path, id, update, &mut Executor::new(false));
+    record_action(config_path, actor, "edit_client", Some(id), result)
+}
+
+pub fn backup(
+    config_path: &Path,
+    history_path: &Path,
+    writer: impl Write,
+) -> anyhow::Result<Manifest> {
+    backup::create(&backup_paths(config_path, history_path), writer)
+}
+
+/// Restores a backup archive. With `regenerate` the wireguard config of `device` is written
+/// again, with `restart` the interface is also (re)started to pick it up.
+pub fn restore(
+    device: &str,
+    config_path: &Path,
+    history_path: &Path,
+    archive: impl Read,
+    regenerate: bool,
+    restart: bool,
+    actor: &Actor,
+) -> anyhow::Result<Manifest> {
+    let result = restore_impl(
+        device,
+        config_path,
+        history_path,
+        archive,
+        regenerate,
+        restart,
+        &mut Executor::new(false),
+    );
+    record_action(config_path, actor, "restore", None, result)
+}
+
+/// Copies the config into a new storage of `kind` at `output`.
+pub fn convert_storage(config_path: &Path, output: &Path, kind: StorageKind) -> anyhow::Result<()> {
+    convert_storage_impl(config_path, output, kind, &mut Executor::new(false))
+}
+
+pub fn get_audit_log(config_path: &Path, filter: &AuditFilter) -> anyhow::Result<Vec<AuditEntry>> {
+    audit::read(&audit::default_path(config_path), filter)
+}
+
+/// Returns counters of administrative actions in the audit log, keyed by action name.
+pub fn action_counters(config_path: &Path) -> anyhow::Result<BTreeMap<String, ActionCounter>> {
+    audit::counters(&audit::default_path(config_path))
 }
 
 pub fn list_clients(config_path: &Path) -> anyhow::Result<Vec<Client>> {
@@ -66,34 +258,144 @@ pub fn get_client(config_path: &Path, id: usize) -> anyhow::Result<Client> {
     Ok(client)
 }
 
+/// Returns the device and all of its clients with live stats, which are empty while the device
+/// isn't running or wireguard-tools aren't installed.
+pub fn get_status(device: &str, config_path: &Path) -> anyhow::Result<DeviceStatus> {
+    let server = Server::load_from_file(config_path)?;
+    let stats = if is_wg_started(device).unwrap_or(false) {
+        Some(metrics::get_device_stats(device)?)
+    } else {
+        None
+    };
+    Ok(DeviceStatus {
+        device: device.to_string(),
+        running: stats.is_some(),
+        public_key: server.keys.public.clone(),
+        listen_port: stats
+            .as_ref()
+            .map_or(server.port, |stats| stats.interface.listen_port),
+        clients: status::join(&server, stats.as_ref()),
+    })
+}
+
+pub fn get_client_status(
+    device: &str,
+    config_path: &Path,
+    id: usize,
+) -> anyhow::Result<ClientStatus> {
+    get_status(device, config_path)?
+        .clients
+        .into_iter()
+        .find(|client| client.id == id)
+        .ok_or_else(|| anyhow!("client with id {} doesn't exist", id))
+}
+
 pub fn get_client_wg_config(config_path: &Path, id: usize) -> anyhow::Result<String> {
     let server = Server::load_from_file(config_path)?;
     let config = server.get_client_wg_config(id)?;
     Ok(config)
 }
 
+pub fn export_client_config(
+    config_path: &Path,
+    id: usize,
+    format: ExportFormat,
+) -> anyhow::Result<Vec<ExportedFile>> {
+    let server = Server::load_from_file(config_path)?;
+    let client = server.get_client(id)?;
+    export::export(&client, &server, format)
+}
+
+pub fn collect_history(
+    device: &str,
+    config_path: &Path,
+    history_path: &Path,
+) -> anyhow::Result<Vec<SessionEvent>> {
+    let server = Server::load_from_file(config_path)?;
+    let stats = metrics::get_device_stats(device)?;
+    let mut history = History::open(history_path)?;
+    history.record(&server, &stats, history::unix_now()?)
+}
+
+/// Lets every consumer of session events, like notifications, see each event once even when
+/// another process collects the history.
+pub fn take_session_events(
+    config_path: &Path,
+    history_path: &Path,
+    consumer: &str,
+) -> anyhow::Result<Vec<SessionEvent>> {
+    let server = Server::load_from_file(config_path)?;
+    let mut history = History::open(history_path)?;
+    history.take_events(&server, consumer)
+}
+
+/// History is stored by public key, the config maps it to the current client ids.
+pub fn get_traffic_history(
+    config_path: &Path,
+    history_path: &Path,
+    client_id: Option<usize>,
+    from: u64,
+    to: u64,
+    bucket: Bucket,
+) -> anyhow::Result<Vec<TrafficPoint>> {
+    let server = Server::load_from_file(config_path)?;
+    let history = History::open(history_path)?;
+    history.query(&server, client_id, from, to, bucket)
+}
+
+pub fn get_sessions(
+    config_path: &Path,
+    history_path: &Path,
+    client_id: usize,
+    limit: usize,
+) -> anyhow::Result<Vec<Session>> {
+    let server = Server::load_from_file(config_path)?;
+    let history = History::open(history_path)?;
+    history.sessions(&server, client_id, limit)
+}
+
+pub fn get_traffic_totals(
+    config_path: &Path,
+    history_path: &Path,
+) -> anyhow::Result<Vec<TrafficTotal>> {
+    let server = Server::load_from_file(config_path)?;
+    let history = History::open(history_path)?;
+    history.totals(&server)
+}
+
 pub fn start_wg(device: &str) -> anyhow::Result<()> {
-    allow_ip4_forwarding()?;
-    wg_manage(device, WgManageCommand::Up)
+    start_wg_with(device, &mut Executor::new(false))
 }
 
 pub fn stop_wg(device: &str) -> anyhow::Result<()> {
-    wg_manage(device, WgManageCommand::Down)
+    wg_manage(device, WgManageCommand::Down, &mut Executor::new(false))
 }
 
 pub fn restart_wg(device: &str) -> anyhow::Result<()> {
-    stop_wg(device)?;
-    start_wg(device)?;
-    Ok(())
+    restart_wg_with(device, &mut Executor::new(false))
+}
+
+fn start_wg_with(device: &str, executor: &mut Executor) -> anyhow::Result<()> {
+    allow_ip4_forwarding(executor)?;
+    wg_manage(device, WgManageCommand::Up, executor)
+}
+
+fn restart_wg_with(device: &str, executor: &mut Executor) -> anyhow::Result<()> {
+    wg_manage(device, WgManageCommand::Down, executor)?;
+    start_wg_with(device, executor)
+}
+
+pub(crate) fn wg_config_path(device: &str) -> PathBuf {
+    format!("/etc/wireguard/{}.conf", device).into()
 }
 
 pub fn dump_wg_config(server: &Server, device: &str) -> anyhow::Result<()> {
     let wg_conf = server.get_wg_config();
 
-    let wg_conf_path: std::path::PathBuf = format!("/etc/wireguard/{}.conf", device).into();
+    let wg_conf_path = wg_config_path(device);
     std::fs::create_dir_all(wg_conf_path.parent().unwrap())?;
 
-    let mut file = std::fs::File::create(wg_conf_path)?;
+    let mut file = crate::fs::create_private_file(&wg_conf_path)?;
     file.write_all(wg_conf.as_bytes())?;
 
     Ok(())
@@ -104,22 +406,15 @@ enum WgManageCommand {
     Down,
 }
 
-fn wg_manage(device: &str, cmd: WgManageCommand) -> anyhow::Result<()> {
-    let status = std::process::Command::new("wg-quick")
+fn wg_manage(device: &str, cmd: WgManageCommand, executor: &mut Executor) -> anyhow::Result<()> {
+    let mut command = Command::new("wg-quick");
+    command
         .arg(match cmd {
             WgManageCommand::Up => "up",
             WgManageCommand::Down => "down",
         })
-        .arg(device)
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .status()?;
-
-    if status.success() {
-        Ok(())
-    } else {
-        Err(anyhow!("wg-quick failed with exit code: {}", status))
-    }
+        .arg(device);
+    executor.run(command, None)
 }
 
 enum WgUpdatedClient {
@@ -131,37 +426,21 @@ fn wg_update_with_client(
     device: &str,
     cmd: WgUpdatedClient,
     server: &Server,
+    executor: &mut Executor,
 ) -> anyhow::Result<()> {
-    let status = match cmd {
-        WgUpdatedClient::Added(client) => std::process::Command::new("wg")
-            .arg("set")
-            .arg(device)
-            .arg("peer")
+    let mut command = Command::new("wg");
+    command.arg("set").arg(device).arg("peer");
+    match cmd {
+        WgUpdatedClient::Added(client) => command
             .arg(&client.keys.public)
             .arg("allowed-ips")
-            .arg(format!("{}/32", client.get_ip_address(&server.subnet)))
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .status()?,
-        WgUpdatedClient::Removed(client) => std::process::Command::new("wg")
-            .arg("set")
-            .arg(device)
-            .arg("peer")
-            .arg(&client.keys.public)
-            .arg("remove")
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .status()?,
+            .arg(format!("{}/32", client.get_ip_address(&server.subnet))),
+        WgUpdatedClient::Removed(client) => command.arg(&client.keys.public).arg("remove"),
     };
-
-    if status.success() {
-        Ok(())
-    } else {
-        Err(anyhow!("wg failed with exit code: {}", status))
-    }
+    executor.run(command, None)
 }
 
-fn is_wg_started(device: &str) -> anyhow::Result<bool> {
+pub fn is_wg_started(device: &str) -> anyhow::Result<bool> {
     let status = std::process::Command::new("wg")
         .arg("show")
         .arg(device)
@@ -171,8 +450,340 @@ fn is_wg_started(device: &str) -> anyhow::Result<bool> {
     Ok(status.success())
 }
 
-fn allow_ip4_forwarding() -> anyhow::Result<()> {
-    let ctl = sysctl::Ctl::new(CTLNAME)?;
-    ctl.set_value(sysctl::CtlValue::String("1".to_string()))?;
+fn run_operation(
+    device: &str,
+    config_path: &Path,
+    operation: Operation,
+    executor: &mut Executor,
+) -> anyhow::Result<()> {
+    match operation {
+        Operation::Up => up_impl(device, config_path, executor)?,
+        Operation::Down => wg_manage(device, WgManageCommand::Down, executor)?,
+        Operation::Reboot => reboot_impl(device, config_path, executor)?,
+        Operation::Reload => {
+            reload_impl(device, config_path, executor)?;
+        }
+        Operation::RepairDrift(direction) => {
+            repair_drift_impl(device, config_path, direction, executor)?;
+        }
+        Operation::AddClient { name, tags } => {
+            add_client_impl(device, config_path, name, tags, executor)?;
+        }
+        Operation::RemoveClient(id) => remove_client_impl(device, config_path, id, executor)?,
+        Operation::EditClient(id, update) => {
+            edit_client_impl(device, config_path, id, update, executor)?;
+        }
+        Operation::ImportClients(specs) => {
+            import_clients_impl(device, config_path, &specs, executor)?;
+        }
+        Operation::ResizeSubnet(subnet) => {
+            resize_subnet_impl(device, config_path, subnet, executor)?;
+        }
+        Operation::SetAddressPool(pool) => set_address_pool_impl(config_path, pool, executor)?,
+        Operation::Restore {
+            archive,
+            history_path,
+            regenerate,
+            restart,
+        } => {
+            restore_impl(
+                device,
+                config_path,
+                &history_path,
+                archive.as_slice(),
+                regenerate,
+                restart,
+                executor,
+            )?;
+        }
+        Operation::ConvertStorage { output, kind } => {
+            convert_storage_impl(config_path, &output, kind, executor)?
+        }
+        Operation::InstallService(options) => {
+            service::install(&options, executor)?;
+        }
+        Operation::UninstallService => {
+            service::uninstall(device, executor)?;
+        }
+    }
+    Ok(())
+}
+
+fn up_impl(device: &str, config_path: &Path, executor: &mut Executor) -> anyhow::Result<()> {
+    let server = Server::load_from_file(config_path)?;
+    executor.write_wg_config(&server, device)?;
+    start_wg_with(device, executor)?;
+    Ok(())
+}
+
+fn reboot_impl(device: &str, config_path: &Path, executor: &mut Executor) -> anyhow::Result<()> {
+    let server = Server::load_from_file(config_path)?;
+    executor.write_wg_config(&server, device)?;
+    restart_wg_with(device, executor)?;
+    Ok(())
+}
+
+fn reload_impl(
+    device: &str,
+    config_path: &Path,
+    executor: &mut Executor,
+) -> anyhow::Result<PeerDiff> {
+    let server = Server::load_from_file(config_path)?;
+    if !is_wg_started(device)? {
+        return Err(anyhow!("device {} is not running", device));
+    }
+    executor.write_wg_config(&server, device)?;
+    let stats = metrics::get_device_stats(device)?;
+    let diff = sync::diff(&server, &stats);
+    sync::apply_with(executor, device, &server, &diff)?;
+    Ok(diff)
+}
+
+/// Returns the drift left and the ids of the clients removed from the config.
+fn repair_drift_impl(
+    device: &str,
+    config_path: &Path,
+    direction: RepairDirection,
+    executor: &mut Executor,
+) -> anyhow::Result<(Drift, Vec<usize>)> {
+    let mut removed = Vec::new();
+    match direction {
+        RepairDirection::Interface => {
+            reload_impl(device, config_path, executor)?;
+        }
+        RepairDirection::Config => {
+            let drift = get_drift(device, config_path)?;
+            let mut storage = storage::open(config_path)?;
+            let mut server = storage.load()?;
+            let ids: Vec<usize> = drift
+                .config_only
+                .iter()
+                .filter_map(|peer| peer.client_id)
+                .collect();
+            for &id in &ids {
+                server.remove_client(id)?;
+            }
+            executor.persist(&server, || {
+                ids.iter()
+                    .try_for_each(|&id| storage.remove_client(id).map(|_| ()))
+            })?;
+            executor.write_wg_config(&server, device)?;
+            removed = ids;
+        }
+    }
+    Ok((get_drift(device, config_path)?, removed))
+}
+
+fn add_client_impl(
+    device: &str,
+    config_path: &Path,
+    name: String,
+    tags: Vec<String>,
+    executor: &mut Executor,
+) -> anyhow::Result<usize> {
+    let mut storage = storage::open(config_path)?;
+    let mut server = storage.load()?;
+    let id = server.add_client(name, tags)?;
+    let client = server.get_client(id)?;
+    // The client is only stored once it's on the interface, so a failed update leaves no trace.
+    if is_wg_started(device)? {
+        wg_update_with_client(
+            device,
+            WgUpdatedClient::Added(client.clone()),
+            &server,
+            executor,
+        )?;
+    }
+    executor.persist(&server, || storage.insert_client(&client))?;
+    executor.write_wg_config(&server, device)?;
+    Ok(id)
+}
+
+fn import_clients_impl(
+    device: &str,
+    config_path: &Path,
+    specs: &[ClientSpec],
+    executor: &mut Executor,
+) -> anyhow::Result<Vec<Client>> {
+    let mut storage = storage::open(config_path)?;
+    let mut server = storage.load()?;
+    let clients = server
+        .add_clients(specs)?
+        .into_iter()
+        .map(|id| server.get_client(id))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if is_wg_started(device)? {
+        let diff = PeerDiff {
+            added: clients
+                .iter()
+                .map(|client| sync::peer(client, &server))
+                .collect(),
+            ..Default::default()
+        };
+        sync::apply_with(executor, device, &server, &diff)?;
+    }
+    executor.persist(&server, || storage.insert_clients(&clients))?;
+    executor.write_wg_config(&server, device)?;
+    Ok(clients)
+}
+
+fn resize_subnet_impl(
+    device: &str,
+    config_path: &Path,
+    subnet: Ipv4Net,
+    executor: &mut Executor,
+) -> anyhow::Result<Vec<Renumbered>> {
+    let mut storage = storage::open(config_path)?;
+    let mut server = storage.load()?;
+    let renumbered = server.resize_subnet(subnet)?;
+    executor.persist(&server, || storage.save(&server))?;
+    executor.write_wg_config(&server, device)?;
+    if is_wg_started(device)? {
+        restart_wg_with(device, executor)?;
+    }
+    Ok(renumbered)
+}
+
+fn set_address_pool_impl(
+    config_path: &Path,
+    pool: AddressPool,
+    executor: &mut Executor,
+) -> anyhow::Result<()> {
+    let mut storage = storage::open(config_path)?;
+    let mut server = storage.load()?;
+    server.set_pool(pool)?;
+    executor.persist(&server, || storage.save(&server))
+}
+
+fn edit_client_impl(
+    device: &str,
+    config_path: &Path,
+    id: usize,
+    update: ClientUpdate,
+    executor: &mut Executor,
+) -> anyhow::Result<Client> {
+    let mut storage = storage::open(config_path)?;
+    let mut server = storage.load()?;
+    let was_disabled = server.get_client(id)?.disabled;
+    let client = server.update_client(id, update)?;
+    if client.disabled != was_disabled && is_wg_started(device)? {
+        let cmd = if client.disabled {
+            WgUpdatedClient::Removed(client.clone())
+        } else {
+            WgUpdatedClient::Added(client.clone())
+        };
+        wg_update_with_client(device, cmd, &server, executor)?;
+    }
+    executor.persist(&server, || storage.update_client(&client))?;
+    executor.write_wg_config(&server, device)?;
+    Ok(client)
+}
+
+fn restore_impl(
+    device: &str,
+    config_path: &Path,
+    history_path: &Path,
+    archive: impl Read,
+    regenerate: bool,
+    restart: bool,
+    executor: &mut Executor,
+) -> anyhow::Result<Manifest> {
+    let (manifest, entries) = backup::validate(archive)?;
+    let server = backup::load_config(&entries[backup::CONFIG_NAME])?;
+    for (name, path) in backup_paths(config_path, history_path).files() {
+        let data = match entries.get(name) {
+            Some(data) => data,
+            None => continue,
+        };
+        let restore = || backup::restore_file(name, path, data);
+        if name == backup::CONFIG_NAME {
+            executor.persist(&server, restore)?;
+        } else if name == backup::AUDIT_NAME {
+            executor.change_file(
+                format!("append missing entries to {}", path.display()),
+                restore,
+            )?;
+        } else {
+            executor.change_file(format!("replace {}", path.display()), restore)?;
+        }
+    }
+    if regenerate || restart {
+        executor.write_wg_config(&server, device)?;
+    }
+    if restart {
+        if is_wg_started(device)? {
+            restart_wg_with(device, executor)?;
+        } else {
+            start_wg_with(device, executor)?;
+        }
+    }
+    Ok(manifest)
+}
+
+fn convert_storage_impl(
+    config_path: &Path,
+    output: &Path,
+    kind: StorageKind,
+    executor: &mut Executor,
+) -> anyhow::Result<()> {
+    if output.exists() {
+        return Err(anyhow!("{} already exists", output.display()));
+    }
+    storage::open(config_path)?.load()?;
+    executor.change_file(format!("write {} as {:?}", output.display(), kind), || {
+        storage::convert(config_path, output, kind)
+    })
+}
+
+fn backup_paths(config_path: &Path, history_path: &Path) -> BackupPaths {
+    BackupPaths {
+        config: config_path.to_path_buf(),
+        history: history_path.to_path_buf(),
+        audit: audit::default_path(config_path),
+    }
+}
+
+fn remove_client_impl(
+    device: &str,
+    config_path: &Path,
+    id: usize,
+    executor: &mut Executor,
+) -> anyhow::Result<()> {
+    let mut storage = storage::open(config_path)?;
+    let mut server = storage.load()?;
+    let client = server.remove_client(id)?;
+    if is_wg_started(device)? {
+        wg_update_with_client(device, WgUpdatedClient::Removed(client), &server, executor)?;
+    }
+    executor.persist(&server, || storage.remove_client(id))?;
+    executor.write_wg_config(&server, device)?;
     Ok(())
 }
+
+/// Writes the action to the audit log next to `config_path`. The action has
+/// already run by now, so a failed audit write is only reported and its result is returned.
+fn record_action<T>(
+    config_path: &Path,
+    actor: &Actor,
+    action: &'static str,
+    client_id: Option<usize>,
+    result: anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    if let Err(err) = audit::record(
+        &audit::default_path(config_path),
+        actor,
+        action,
+        client_id,
+        &result,
+    ) {
+        eprintln!(
+            "warning: failed to write {} to the audit log: {:#}",
+            action, err
+        );
+    }
+    result
+}
+
+fn allow_ip4_forwarding(executor: &mut Executor) -> anyhow::Result<()> {
+    executor.set_sysctl(CTLNAME, "1")
+}
@@ -0,0 +1,230 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::history;
+
+/// Who performed an administrative action.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "lowercase")]
+pub enum Actor {
+    /// Local user running wgtool.
+    Cli(String),
+    /// Telegram chat id of the wgbot user.
+    Telegram(i64),
+    /// Name of the wghttp token used for the request.
+    Http(String),
+}
+
+impl Actor {
+    /// Returns the local user, preferring the user who invoked `sudo`.
+    pub fn current_user() -> Self {
+        let user = std::env::var("SUDO_USER")
+            .or_else(|_| std::env::var("USER"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        Actor::Cli(user)
+    }
+}
+
+impl fmt::Display for Actor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Actor::Cli(user) => write!(f, "cli:{}", user),
+            Actor::Telegram(id) => write!(f, "telegram:{}", id),
+            Actor::Http(token) => write!(f, "http:{}", token),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum Outcome {
+    Success,
+    Failure { error: String },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: u64,
+    pub actor: Actor,
+    pub action: String,
+    pub client_id: Option<usize>,
+    pub outcome: Outcome,
+}
+
+/// Number of administrative actions recorded in the audit log.
+#[derive(Clone, Default)]
+pub struct ActionCounter {
+    pub succeeded: u64,
+    pub failed: u64,
+}
+
+#[derive(Default)]
+pub struct AuditFilter {
+    pub action: Option<String>,
+    pub client_id: Option<usize>,
+    pub since: Option<u64>,
+    /// Keep only the latest `limit` matching entries.
+    pub limit: Option<usize>,
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.action.as_ref().is_none_or(|a| *a == entry.action)
+            && self.client_id.is_none_or(|id| entry.client_id == Some(id))
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+    }
+}
+
+/// Returns the audit log path used next to the server config.
+pub fn default_path(config_path: &Path) -> PathBuf {
+    let mut path = config_path.as_os_str().to_owned();
+    path.push(".audit");
+    path.into()
+}
+
+/// Appends an entry to the audit log. The log is a JSON-lines file which is only ever
+/// appended to.
+pub fn append(log_path: &Path, entry: &AuditEntry) -> anyhow::Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+pub fn record<T>(
+    log_path: &Path,
+    actor: &Actor,
+    action: &str,
+    client_id: Option<usize>,
+    result: &anyhow::Result<T>,
+) -> anyhow::Result<()> {
+    let outcome = match result {
+        Ok(_) => Outcome::Success,
+        Err(err) => Outcome::Failure {
+            error: err.to_string(),
+        },
+    };
+    let entry = AuditEntry {
+        timestamp: history::unix_now()?,
+        actor: actor.clone(),
+        action: action.to_string(),
+        client_id,
+        outcome,
+    };
+    append(log_path, &entry)
+}
+
+/// Reads entries matching `filter`, oldest first. A missing log is treated as empty.
+pub fn read(log_path: &Path, filter: &AuditFilter) -> anyhow::Result<Vec<AuditEntry>> {
+    let file = match std::fs::File::open(log_path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: AuditEntry = serde_json::from_str(&line)?;
+        if filter.matches(&entry) {
+            entries.push(entry);
+        }
+    }
+
+    if let Some(limit) = filter.limit {
+        let skip = entries.len().saturating_sub(limit);
+        entries.drain(..skip);
+    }
+    Ok(entries)
+}
+
+/// Counts the logged actions by name and outcome, whichever process performed them.
+pub fn counters(log_path: &Path) -> anyhow::Result<BTreeMap<String, ActionCounter>> {
+    let mut counters: BTreeMap<String, ActionCounter> = BTreeMap::new();
+    for entry in read(log_path, &AuditFilter::default())? {
+        let counter = counters.entry(entry.action).or_default();
+        match entry.outcome {
+            Outcome::Success => counter.succeeded += 1,
+            Outcome::Failure { .. } => counter.failed += 1,
+        }
+    }
+    Ok(counters)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_and_filters_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = dir.path().join("audit");
+
+        let ok: anyhow::Result<()> = Ok(());
+        let failed: anyhow::Result<()> = Err(anyhow::anyhow!("no enough space for new clients"));
+        record(
+            &log_path,
+            &Actor::Cli("root".into()),
+            "add_client",
+            Some(0),
+            &ok,
+        )
+        .unwrap();
+        record(&log_path, &Actor::Telegram(42), "add_client", None, &failed).unwrap();
+        record(
+            &log_path,
+            &Actor::Http("ci".into()),
+            "remove_client",
+            Some(0),
+            &ok,
+        )
+        .unwrap();
+
+        let all = read(&log_path, &AuditFilter::default()).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[1].actor, Actor::Telegram(42));
+        assert_eq!(
+            all[1].outcome,
+            Outcome::Failure {
+                error: "no enough space for new clients".to_string()
+            }
+        );
+
+        let filter = AuditFilter {
+            client_id: Some(0),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let latest = read(&log_path, &filter).unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].action, "remove_client");
+
+        let counters = counters(&log_path).unwrap();
+        assert_eq!(counters.len(), 2);
+        assert_eq!(counters["add_client"].succeeded, 1);
+        assert_eq!(counters["add_client"].failed, 1);
+        assert_eq!(counters["remove_client"].succeeded, 1);
+    }
+
+    #[test]
+    fn missing_log_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let entries = read(&dir.path().join("audit"), &AuditFilter::default()).unwrap();
+        assert!(entries.is_empty());
+    }
+}
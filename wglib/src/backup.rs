@@ -0,0 +1,373 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{anyhow, Context};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    audit::{self, AuditEntry},
+    history,
+    model::server::ServerConfig,
+    storage::{self, StorageKind},
+    Server,
+};
+
+const MANIFEST_NAME: &str = "manifest.json";
+const MANIFEST_VERSION: u32 = 1;
+
+pub const CONFIG_NAME: &str = "config";
+pub const HISTORY_NAME: &str = "history";
+pub const AUDIT_NAME: &str = "audit";
+
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub created_at: u64,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Locations of everything included into a backup.
+pub struct BackupPaths {
+    pub config: PathBuf,
+    pub history: PathBuf,
+    pub audit: PathBuf,
+}
+
+impl BackupPaths {
+    pub(crate) fn files(&self) -> [(&'static str, &Path); 3] {
+        [
+            (CONFIG_NAME, &self.config),
+            (HISTORY_NAME, &self.history),
+            (AUDIT_NAME, &self.audit),
+        ]
+    }
+}
+
+/// Writes a gzipped tar archive with the server config (including keys), traffic history and
+/// audit log. The archive starts with a manifest holding a SHA-256 checksum of every file.
+pub fn create(paths: &BackupPaths, writer: impl Write) -> anyhow::Result<Manifest> {
+    let mut contents = Vec::new();
+    for (name, path) in paths.files() {
+        if let Some(data) = snapshot(path).with_context(|| format!("failed to read {}", name))? {
+            contents.push((name, data));
+        } else if name == CONFIG_NAME {
+            return Err(anyhow!("config {} doesn't exist", path.display()));
+        }
+    }
+
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        created_at: history::unix_now()?,
+        files: contents
+            .iter()
+            .map(|(name, data)| ManifestFile {
+                name: name.to_string(),
+                size: data.len() as u64,
+                sha256: sha256(data),
+            })
+            .collect(),
+    };
+
+    let mut builder = tar::Builder::new(GzEncoder::new(writer, Compression::default()));
+    append(
+        &mut builder,
+        MANIFEST_NAME,
+        &serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    for (name, data) in &contents {
+        append(&mut builder, name, data)?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(manifest)
+}
+
+/// Reads the archive and checks that every file listed in the manifest is present and
+/// matches its checksum, and that the config can be loaded.
+pub fn validate(reader: impl Read) -> anyhow::Result<(Manifest, BTreeMap<String, Vec<u8>>)> {
+    let mut entries = BTreeMap::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(reader));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().to_string();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        entries.insert(name, data);
+    }
+
+    let manifest: Manifest = match entries.remove(MANIFEST_NAME) {
+        Some(data) => serde_json::from_slice(&data).context("invalid manifest")?,
+        None => return Err(anyhow!("archive has no manifest")),
+    };
+    if manifest.version != MANIFEST_VERSION {
+        return Err(anyhow!("unsupported backup version {}", manifest.version));
+    }
+
+    for file in &manifest.files {
+        let data = entries
+            .get(&file.name)
+            .ok_or_else(|| anyhow!("{} is missing from the archive", file.name))?;
+        if data.len() as u64 != file.size || sha256(data) != file.sha256 {
+            return Err(anyhow!("checksum mismatch for {}", file.name));
+        }
+    }
+    if let Some(name) = entries
+        .keys()
+        .find(|name| !manifest.files.iter().any(|file| &file.name == *name))
+    {
+        return Err(anyhow!("{} is not listed in the manifest", name));
+    }
+
+    let config = entries
+        .get(CONFIG_NAME)
+        .ok_or_else(|| anyhow!("archive has no config"))?;
+    load_config(config).context("config in the archive is invalid")?;
+    if let Some(log) = entries.get(AUDIT_NAME) {
+        parse_audit_log(log).context("audit log in the archive is invalid")?;
+    }
+
+    Ok((manifest, entries))
+}
+
+/// Validates the archive and replaces the files at `paths` with its contents. Files missing
+/// from the archive are left untouched. The audit log is append-only, so only its entries which
+/// are missing from the current log are appended.
+pub fn restore(reader: impl Read, paths: &BackupPaths) -> anyhow::Result<Manifest> {
+    let (manifest, entries) = validate(reader)?;
+    for (name, path) in paths.files() {
+        if let Some(data) = entries.get(name) {
+            restore_file(name, path, data)?;
+        }
+    }
+    Ok(manifest)
+}
+
+/// Restores the archive file `name` to `path`, see [`restore`].
+pub(crate) fn restore_file(name: &str, path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let result = if name == AUDIT_NAME {
+        append_missing_entries(path, data)
+    } else {
+        replace(path, data)
+    };
+    result.with_context(|| format!("failed to restore {}", name))
+}
+
+fn append<W: Write>(builder: &mut tar::Builder<W>, name: &str, data: &[u8]) -> anyhow::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(history::unix_now()?);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+/// Returns a consistent copy of the file, `None` if it doesn't exist. SQLite databases are
+/// copied with `VACUUM INTO` so concurrent writers can't corrupt the snapshot.
+fn snapshot(path: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    if storage::detect(path)? != StorageKind::Sqlite {
+        return Ok(Some(std::fs::read(path)?));
+    }
+
+    // VACUUM INTO accepts an empty file, created first so the copy isn't world-readable.
+    let copy = sibling(path, "backup-tmp");
+    create_private(&copy)?;
+    let conn = rusqlite::Connection::open(path)?;
+    conn.execute("VACUUM INTO ?1", [copy.to_string_lossy()])?;
+    let data = std::fs::read(&copy);
+    std::fs::remove_file(&copy)?;
+    Ok(Some(data?))
+}
+
+/// Loads the config, which holds the private keys. JSON configs are parsed in memory, SQLite
+/// needs a file, which is staged in a directory only accessible by the current user.
+pub(crate) fn load_config(data: &[u8]) -> anyhow::Result<Server> {
+    if !data.starts_with(storage::SQLITE_HEADER) {
+        let config: ServerConfig = serde_json::from_slice(data)?;
+        return Server::from_server_config(config);
+    }
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "wgsuite-restore-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let path = dir.join(CONFIG_NAME);
+    let result = write_private(&path, data)
+        .and_then(|_| storage::open(&path))
+        .and_then(|storage| storage.load());
+    std::fs::remove_dir_all(&dir)?;
+    result
+}
+
+fn append_missing_entries(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let current = match std::fs::read_to_string(path) {
+        Ok(current) => current,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    let known: HashSet<&str> = current.lines().collect();
+    for (line, entry) in parse_audit_log(data)? {
+        if !known.contains(line) {
+            audit::append(path, &entry)?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_audit_log(data: &[u8]) -> anyhow::Result<Vec<(&str, AuditEntry)>> {
+    std::str::from_utf8(data)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok((line, serde_json::from_str(line)?)))
+        .collect()
+}
+
+/// Writes `data` next to `path` and renames it over, so the target is never half-written.
+fn replace(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let tmp = sibling(path, "restore-tmp");
+    write_private(&tmp, data)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Creates a new file readable by the owner only, replacing a leftover one.
+fn create_private(path: &Path) -> anyhow::Result<File> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+        _ => {}
+    }
+    Ok(OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?)
+}
+
+fn write_private(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    create_private(path)?.write_all(data)?;
+    Ok(())
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(".");
+    sibling.push(suffix);
+    sibling.into()
+}
+
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    fn paths(dir: &Path) -> BackupPaths {
+        BackupPaths {
+            config: dir.join("wg"),
+            history: dir.join("wg.history"),
+            audit: dir.join("wg.audit"),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let source = tempfile::tempdir().unwrap();
+        let source_paths = paths(source.path());
+        let mut server = Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        server.add_client("alice".to_string(), Vec::new()).unwrap();
+        server.dump_to_file(&source_paths.config).unwrap();
+        history::History::open(&source_paths.history).unwrap();
+        let ok: anyhow::Result<()> = Ok(());
+        let actor = audit::Actor::Cli("root".to_string());
+        audit::record(&source_paths.audit, &actor, "add_client", Some(0), &ok).unwrap();
+
+        let mut archive = Vec::new();
+        let manifest = create(&source_paths, &mut archive).unwrap();
+        assert_eq!(manifest.files.len(), 3);
+
+        let target = tempfile::tempdir().unwrap();
+        let target_paths = paths(target.path());
+        // Written after the backup, it must survive the restore.
+        audit::record(&target_paths.audit, &actor, "remove_client", Some(0), &ok).unwrap();
+        restore(archive.as_slice(), &target_paths).unwrap();
+        restore(archive.as_slice(), &target_paths).unwrap();
+
+        let restored = Server::load_from_file(&target_paths.config).unwrap();
+        let mode = std::fs::metadata(&target_paths.config)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(restored.keys.private, server.keys.private);
+        assert_eq!(restored.clients.len(), 1);
+        assert_eq!(
+            storage::detect(&target_paths.history).unwrap(),
+            StorageKind::Sqlite
+        );
+        let actions: Vec<String> = audit::read(&target_paths.audit, &Default::default())
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.action)
+            .collect();
+        assert_eq!(actions, ["remove_client", "add_client"]);
+    }
+
+    #[test]
+    fn rejects_tampered_archive() {
+        let source = tempfile::tempdir().unwrap();
+        let source_paths = paths(source.path());
+        std::fs::write(&source_paths.config, "{}").unwrap();
+
+        let manifest = Manifest {
+            version: MANIFEST_VERSION,
+            created_at: 0,
+            files: vec![ManifestFile {
+                name: CONFIG_NAME.to_string(),
+                size: 2,
+                sha256: sha256(b"[]"),
+            }],
+        };
+        let mut archive = Vec::new();
+        let mut builder = tar::Builder::new(GzEncoder::new(&mut archive, Compression::default()));
+        append(
+            &mut builder,
+            MANIFEST_NAME,
+            &serde_json::to_vec(&manifest).unwrap(),
+        )
+        .unwrap();
+        append(&mut builder, CONFIG_NAME, b"{}").unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let err = validate(archive.as_slice()).err().unwrap();
+        assert!(err.to_string().contains("checksum mismatch"));
+    }
+}
@@ -0,0 +1,253 @@
+//! Named contexts, each pointing the command line tools at a server. They're kept in a TOML
+//! file like
+//!
+//! ```toml
+//! current = "office"
+//!
+//! [contexts.office]
+//! device = "wg1"
+//! config_path = "/root/office.wg"
+//! output = "json"
+//!
+//! [contexts.cloud]
+//! url = "https://vpn.example.com:3000"
+//! token = "secret"
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    io::Write,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContextConfig {
+    /// Context used when none is given on the command line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current: Option<String>,
+    #[serde(default)]
+    pub contexts: BTreeMap<String, Context>,
+}
+
+/// Defaults of one server. A context is either local, with a device and config path, or remote,
+/// with the URL of a wghttp server and its bearer token.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Context {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_path: Option<PathBuf>,
+    /// Output format of read commands.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Base URL of a wghttp server, e.g. `https://vpn.example.com:3000`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl Context {
+    pub fn is_remote(&self) -> bool {
+        self.url.is_some()
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match &self.url {
+            Some(url) => {
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    return Err(anyhow!("url {} must start with http:// or https://", url));
+                }
+                if self.device.is_some() || self.config_path.is_some() {
+                    return Err(anyhow!(
+                        "a context with url can't have a device or config_path, they're set by the wghttp server"
+                    ));
+                }
+            }
+            None => {
+                if self.token.is_some() {
+                    return Err(anyhow!("token is only used together with url"));
+                }
+            }
+        }
+        if let Some(token) = &self.token {
+            if token.is_empty() || token.chars().any(char::is_whitespace) {
+                return Err(anyhow!("token must not be empty or contain whitespace"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ContextConfig {
+    /// Loads the contexts from `path`, a missing file has none.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(anyhow!("failed to read {}: {}", path.display(), err)),
+        };
+        let config: Self = toml::from_str(&contents)
+            .map_err(|err| anyhow!("failed to parse {}: {}", path.display(), err))?;
+        config
+            .validate()
+            .map_err(|err| anyhow!("invalid {}: {}", path.display(), err))?;
+        Ok(config)
+    }
+
+    /// Writes the contexts to `path`, readable by the owner only since they may contain tokens.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        self.validate()?;
+        if let Some(dir) = path.parent() {
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)?;
+        }
+        let contents = toml::to_string(self)?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)
+            .map_err(|err| anyhow!("failed to write {}: {}", path.display(), err))?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, context) in &self.contexts {
+            context
+                .validate()
+                .map_err(|err| anyhow!("context {}: {}", name, err))?;
+        }
+        if let Some(current) = &self.current {
+            self.get(current)?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> anyhow::Result<&Context> {
+        self.contexts.get(name).ok_or_else(|| {
+            let names: Vec<&str> = self.contexts.keys().map(String::as_str).collect();
+            if names.is_empty() {
+                anyhow!("unknown context {}, no contexts are defined", name)
+            } else {
+                anyhow!(
+                    "unknown context {}, expected one of {}",
+                    name,
+                    names.join(", ")
+                )
+            }
+        })
+    }
+
+    /// Returns the context named `name` or, without a name, the current one. `None` if there's
+    /// neither.
+    pub fn resolve<'a>(
+        &'a self,
+        name: Option<&'a str>,
+    ) -> anyhow::Result<Option<(&'a str, &'a Context)>> {
+        match name.or(self.current.as_deref()) {
+            Some(name) => Ok(Some((name, self.get(name)?))),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_current(&mut self, name: &str) -> anyhow::Result<()> {
+        self.get(name)?;
+        self.current = Some(name.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+current = "office"
+
+[contexts.office]
+device = "wg1"
+config_path = "/root/office.wg"
+output = "json"
+
+[contexts.cloud]
+url = "https://vpn.example.com:3000"
+token = "secret"
+"#;
+
+    #[test]
+    fn resolves_contexts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        assert_eq!(
+            ContextConfig::load(&path).unwrap(),
+            ContextConfig::default()
+        );
+        std::fs::write(&path, CONFIG).unwrap();
+
+        let mut config = ContextConfig::load(&path).unwrap();
+        let (name, office) = config.resolve(None).unwrap().unwrap();
+        assert_eq!(name, "office");
+        assert_eq!(office.device.as_deref(), Some("wg1"));
+        assert!(!office.is_remote());
+        let (_, cloud) = config.resolve(Some("cloud")).unwrap().unwrap();
+        assert!(cloud.is_remote());
+        assert!(config.resolve(Some("home")).is_err());
+
+        config.set_current("cloud").unwrap();
+        assert!(config.set_current("home").is_err());
+        let path = dir.path().join("wgsuite").join("config.toml");
+        config.save(&path).unwrap();
+        assert_eq!(ContextConfig::load(&path).unwrap(), config);
+
+        config.current = None;
+        assert_eq!(config.resolve(None).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_contexts() {
+        let remote = Context {
+            url: Some("https://vpn.example.com".to_string()),
+            ..Default::default()
+        };
+        assert!(remote.validate().is_ok());
+        for context in [
+            Context {
+                url: Some("vpn.example.com".to_string()),
+                ..Default::default()
+            },
+            Context {
+                device: Some("wg0".to_string()),
+                ..remote.clone()
+            },
+            Context {
+                token: Some("secret".to_string()),
+                ..Default::default()
+            },
+            Context {
+                token: Some("two words".to_string()),
+                ..remote.clone()
+            },
+        ] {
+            assert!(context.validate().is_err(), "{:?}", context);
+        }
+
+        assert!(toml::from_str::<ContextConfig>("[contexts.office]\nhost = \"x\"\n").is_err());
+        let config = ContextConfig {
+            current: Some("home".to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,395 @@
+use std::{
+    net::{IpAddr, ToSocketAddrs},
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use serde::Serialize;
+
+use crate::{actions, metrics, net, Server};
+
+const IP_FORWARD_PATH: &str = "/proc/sys/net/ipv4/ip_forward";
+const WG_DIR: &str = "/etc/wireguard";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    /// Not a problem right now, but may become one.
+    Warn,
+    Fail,
+}
+
+/// Result of a single host check, with a hint on how to fix it unless it passed.
+#[derive(Clone, Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Checks whether this host can run `device` with the config at `config_path`. Checks which
+/// need the config are skipped if it can't be loaded.
+pub fn run(device: &str, config_path: &Path) -> Vec<Check> {
+    let mut checks = vec![check_tools(), check_kernel_module(), check_ip_forward()];
+
+    let server = match Server::load_from_file(config_path) {
+        Ok(server) => server,
+        Err(err) => {
+            checks.push(Check::fail(
+                "config",
+                format!("can't load {}: {}", config_path.display(), err),
+                "run `wgtool init` or pass the right `--config-path`",
+            ));
+            return checks;
+        }
+    };
+    let running = actions::is_wg_started(device).unwrap_or(false);
+
+    checks.push(check_private_file("config permissions", config_path));
+    checks.push(check_wg_dir());
+    let wg_conf = actions::wg_config_path(device);
+    if wg_conf.exists() {
+        checks.push(check_private_file("wireguard config permissions", &wg_conf));
+    }
+    checks.push(check_nat_rule(&server.network_interface, running));
+    checks.push(check_listen_port(device, server.port, running));
+    checks.push(check_endpoint(&server.endpoint, server.port));
+    checks
+}
+
+fn check_tools() -> Check {
+    let missing: Vec<&str> = ["wg", "wg-quick"]
+        .into_iter()
+        .filter(|tool| !command_exists(tool))
+        .collect();
+    if missing.is_empty() {
+        Check::pass("wireguard-tools", "wg and wg-quick found")
+    } else {
+        Check::fail(
+            "wireguard-tools",
+            format!("{} not found in PATH", missing.join(" and ")),
+            "install wireguard-tools, e.g. `apt install wireguard-tools`",
+        )
+    }
+}
+
+fn check_kernel_module() -> Check {
+    if Path::new("/sys/module/wireguard").exists() {
+        return Check::pass("kernel module", "wireguard is loaded");
+    }
+    if run_quietly(Command::new("modinfo").arg("wireguard")) {
+        return Check::pass(
+            "kernel module",
+            "wireguard is available and loaded on start",
+        );
+    }
+    Check::fail(
+        "kernel module",
+        "wireguard module not found",
+        "use a kernel with WireGuard (5.6 or newer) or install wireguard-dkms",
+    )
+}
+
+fn check_ip_forward() -> Check {
+    match std::fs::read_to_string(IP_FORWARD_PATH) {
+        Ok(value) if value.trim() == "1" => Check::pass("ip forwarding", "enabled"),
+        Ok(_) => Check::warn(
+            "ip forwarding",
+            "disabled, clients can't reach anything behind the server",
+            "`wgtool start` enables it until reboot, `wgtool install-service` keeps it enabled",
+        ),
+        Err(err) => Check::fail(
+            "ip forwarding",
+            format!("can't read {}: {}", IP_FORWARD_PATH, err),
+            "make sure /proc is mounted",
+        ),
+    }
+}
+
+fn check_private_file(name: &str, path: &Path) -> Check {
+    match std::fs::metadata(path) {
+        Ok(metadata) if !is_private(metadata.permissions().mode()) => Check::fail(
+            name,
+            format!(
+                "{} is readable by other users and contains private keys",
+                path.display()
+            ),
+            format!("chmod 600 {}", path.display()),
+        ),
+        Ok(_) => Check::pass(
+            name,
+            format!("{} is only readable by owner", path.display()),
+        ),
+        Err(err) => Check::fail(
+            name,
+            format!("can't read {}: {}", path.display(), err),
+            "run as root",
+        ),
+    }
+}
+
+fn check_wg_dir() -> Check {
+    let name = format!("{} permissions", WG_DIR);
+    match std::fs::metadata(WG_DIR) {
+        Ok(metadata) if !is_private(metadata.permissions().mode()) => Check::fail(
+            name,
+            format!("{} is accessible by other users", WG_DIR),
+            format!("chmod 700 {}", WG_DIR),
+        ),
+        Ok(_) => Check::pass(name, format!("{} is only accessible by owner", WG_DIR)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Check::warn(
+            name,
+            format!("{} doesn't exist", WG_DIR),
+            format!(
+                "it's created on start, or create it with `install -d -m 700 {}`",
+                WG_DIR
+            ),
+        ),
+        Err(err) => Check::fail(
+            name,
+            format!("can't read {}: {}", WG_DIR, err),
+            "run as root",
+        ),
+    }
+}
+
+fn check_nat_rule(interface: &str, running: bool) -> Check {
+    let name = "nat rule";
+    if !Path::new("/sys/class/net").join(interface).exists() {
+        return Check::fail(
+            name,
+            format!("network interface {} doesn't exist", interface),
+            "set `network_interface` in the config to the interface with the default route",
+        );
+    }
+    let output = Command::new("iptables")
+        .args(["-t", "nat", "-S", "POSTROUTING"])
+        .stderr(Stdio::null())
+        .output();
+    let rules = match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        Ok(output) => {
+            return Check::fail(
+                name,
+                format!("iptables failed with exit code: {}", output.status),
+                "run as root",
+            )
+        }
+        Err(err) => {
+            return Check::fail(
+                name,
+                format!("can't run iptables: {}", err),
+                "install iptables, PostUp and PostDown of the wireguard config use it",
+            )
+        }
+    };
+    if has_masquerade_rule(&rules, interface) {
+        Check::pass(
+            name,
+            format!("traffic leaving {} is masqueraded", interface),
+        )
+    } else if running {
+        Check::fail(
+            name,
+            format!("no MASQUERADE rule for {}", interface),
+            format!(
+                "run `wgtool restart` so PostUp adds it, or `iptables -t nat -A POSTROUTING -o {} -j MASQUERADE`",
+                interface
+            ),
+        )
+    } else {
+        Check::warn(
+            name,
+            format!("no MASQUERADE rule for {}", interface),
+            "the device isn't running, PostUp adds the rule on start",
+        )
+    }
+}
+
+fn check_listen_port(device: &str, port: u16, running: bool) -> Check {
+    let name = "listen port";
+    if running {
+        return match metrics::get_device_stats(device) {
+            Ok(stats) if stats.interface.listen_port == port => {
+                Check::pass(name, format!("{} listens on {}", device, port))
+            }
+            Ok(stats) => Check::fail(
+                name,
+                format!(
+                    "{} listens on {}, the config has {}",
+                    device, stats.interface.listen_port, port
+                ),
+                "run `wgtool reload` to apply the configured port",
+            ),
+            Err(err) => Check::fail(
+                name,
+                format!("can't read {}: {}", device, err),
+                "run as root",
+            ),
+        };
+    }
+    match net::check_port_free(port) {
+        Ok(()) => Check::pass(name, format!("udp port {} is free", port)),
+        Err(err) => Check::fail(
+            name,
+            err.to_string(),
+            format!(
+                "find the process with `ss -ulpn 'sport = :{}'` or change `port` in the config",
+                port
+            ),
+        ),
+    }
+}
+
+fn check_endpoint(endpoint: &str, port: u16) -> Check {
+    let name = "endpoint";
+    let resolved: Vec<IpAddr> = match (endpoint, port).to_socket_addrs() {
+        Ok(addrs) => addrs.map(|addr| addr.ip()).collect(),
+        Err(err) => {
+            return Check::fail(
+                name,
+                format!("can't resolve {}: {}", endpoint, err),
+                "set `endpoint` in the config to a public address or hostname of this host",
+            )
+        }
+    };
+    let local = match Command::new("ip").args(["-o", "addr", "show"]).output() {
+        Ok(output) if output.status.success() => {
+            parse_addresses(&String::from_utf8_lossy(&output.stdout))
+        }
+        _ => {
+            return Check::warn(
+                name,
+                "can't list local addresses",
+                "install iproute2 to compare the endpoint with local addresses",
+            )
+        }
+    };
+    if resolved.iter().any(|ip| local.contains(ip)) {
+        Check::pass(name, format!("{} is a local address", endpoint))
+    } else {
+        Check::warn(
+            name,
+            format!("{} isn't an address of this host", endpoint),
+            format!(
+                "fine behind NAT if udp port {} is forwarded here, otherwise change `endpoint` in the config",
+                port
+            ),
+        )
+    }
+}
+
+/// Returns whether group and others have no access.
+fn is_private(mode: u32) -> bool {
+    mode & 0o077 == 0
+}
+
+fn command_exists(name: &str) -> bool {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).any(|dir| dir.join(name).is_file()))
+        .unwrap_or(false)
+}
+
+fn run_quietly(cmd: &mut Command) -> bool {
+    cmd.stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// Looks for a MASQUERADE rule on `interface` in the output of `iptables -t nat -S`.
+fn has_masquerade_rule(rules: &str, interface: &str) -> bool {
+    rules.lines().any(|rule| {
+        let args: Vec<&str> = rule.split_whitespace().collect();
+        args.windows(2).any(|pair| pair == ["-o", interface])
+            && args.windows(2).any(|pair| pair == ["-j", "MASQUERADE"])
+    })
+}
+
+/// Collects the addresses from the output of `ip -o addr show`.
+fn parse_addresses(output: &str) -> Vec<IpAddr> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            words.find(|&word| word == "inet" || word == "inet6")?;
+            let cidr = words.next()?;
+            cidr.split('/').next()?.parse().ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_masquerade_rule() {
+        let rules = "-P POSTROUTING ACCEPT\n\
+                     -A POSTROUTING -o eth0 -j MASQUERADE\n\
+                     -A POSTROUTING -o eth1 -j ACCEPT\n";
+        assert!(has_masquerade_rule(rules, "eth0"));
+        assert!(!has_masquerade_rule(rules, "eth1"));
+        assert!(!has_masquerade_rule(rules, "eth"));
+    }
+
+    #[test]
+    fn parses_addresses() {
+        let output = "\
+1: lo    inet 127.0.0.1/8 scope host lo\\       valid_lft forever preferred_lft forever
+2: eth0    inet 203.0.113.5/24 brd 203.0.113.255 scope global eth0\\       valid_lft forever
+2: eth0    inet6 2001:db8::5/64 scope global \\       valid_lft forever preferred_lft forever
+";
+        assert_eq!(
+            parse_addresses(output),
+            vec![
+                "127.0.0.1".parse::<IpAddr>().unwrap(),
+                "203.0.113.5".parse().unwrap(),
+                "2001:db8::5".parse().unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_permissions() {
+        assert!(is_private(0o100600));
+        assert!(is_private(0o40700));
+        assert!(!is_private(0o100644));
+        assert!(!is_private(0o40750));
+    }
+}
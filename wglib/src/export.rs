@@ -0,0 +1,443 @@
+use std::{
+    fmt::Write,
+    net::{IpAddr, Ipv6Addr},
+    str::FromStr,
+};
+
+use anyhow::anyhow;
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+
+use crate::{Client, Server};
+
+/// Name of the tunnel interface created on the client by formats which need one.
+const INTERFACE: &str = "wg0";
+/// Routing table and firewall mark of default routes through the tunnel, the same as wg-quick.
+const ROUTE_TABLE: u32 = 51820;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum ExportFormat {
+    /// INI config for `wg-quick` and the WireGuard apps.
+    #[serde(rename = "wg-quick")]
+    WgQuick,
+    /// NetworkManager keyfile.
+    #[serde(rename = "nmconnection")]
+    NetworkManager,
+    /// systemd-networkd `.netdev` and `.network` pair.
+    #[serde(rename = "networkd")]
+    Networkd,
+    /// OpenWrt UCI snippet for `/etc/config/network`.
+    #[serde(rename = "openwrt")]
+    OpenWrt,
+    /// MikroTik RouterOS script.
+    #[serde(rename = "mikrotik")]
+    Mikrotik,
+    #[serde(rename = "json")]
+    Json,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wg-quick" => Ok(ExportFormat::WgQuick),
+            "nmconnection" => Ok(ExportFormat::NetworkManager),
+            "networkd" => Ok(ExportFormat::Networkd),
+            "openwrt" => Ok(ExportFormat::OpenWrt),
+            "mikrotik" => Ok(ExportFormat::Mikrotik),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(anyhow!(
+                "unknown export format {}, expected wg-quick, nmconnection, networkd, openwrt, mikrotik or json",
+                s
+            )),
+        }
+    }
+}
+
+impl ExportFormat {
+    /// Name used on the command line and in the `format` query parameter of wghttp.
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportFormat::WgQuick => "wg-quick",
+            ExportFormat::NetworkManager => "nmconnection",
+            ExportFormat::Networkd => "networkd",
+            ExportFormat::OpenWrt => "openwrt",
+            ExportFormat::Mikrotik => "mikrotik",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Json => "application/json",
+            _ => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+/// A file produced by an export. Most formats produce a single file.
+pub struct ExportedFile {
+    pub name: String,
+    pub contents: String,
+}
+
+/// Joins exported files into a single text, prefixing each one with its name if there are
+/// several of them.
+pub fn join(files: &[ExportedFile]) -> String {
+    match files {
+        [file] => file.contents.clone(),
+        _ => files
+            .iter()
+            .map(|file| format!("# {}\n{}", file.name, file.contents))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+#[derive(Serialize)]
+struct JsonConfig<'a> {
+    name: &'a str,
+    address: String,
+    private_key: &'a str,
+    public_key: &'a str,
+    dns: Vec<IpAddr>,
+    peer: JsonPeer<'a>,
+}
+
+#[derive(Serialize)]
+struct JsonPeer<'a> {
+    public_key: &'a str,
+    endpoint: String,
+    allowed_ips: Vec<IpNet>,
+    persistent_keepalive: u16,
+}
+
+pub fn export(
+    client: &Client,
+    server: &Server,
+    format: ExportFormat,
+) -> anyhow::Result<Vec<ExportedFile>> {
+    let name = file_name(&client.name);
+    let address = format!("{}/32", client.get_ip_address(&server.subnet));
+    let endpoint = server.endpoint_address();
+    let dns = client.get_dns(server);
+    let allowed_ips = client.get_allowed_ips(server);
+    let keepalive = client.get_persistent_keepalive();
+
+    let file = |name: String, contents: String| ExportedFile { name, contents };
+    let files = match format {
+        ExportFormat::WgQuick => vec![file(format!("{}.conf", name), client.get_wg_config(server))],
+        ExportFormat::NetworkManager => {
+            let mut out = String::new();
+            writeln!(out, "[connection]")?;
+            writeln!(out, "id={}", client.name)?;
+            writeln!(out, "type=wireguard")?;
+            writeln!(out, "interface-name={}", INTERFACE)?;
+            writeln!(out, "\n[wireguard]")?;
+            writeln!(out, "private-key={}", client.keys.private)?;
+            writeln!(out, "\n[wireguard-peer.{}]", server.keys.public)?;
+            writeln!(out, "endpoint={}", endpoint)?;
+            writeln!(out, "allowed-ips={};", list(&allowed_ips, ";"))?;
+            writeln!(out, "persistent-keepalive={}", keepalive)?;
+            writeln!(out, "\n[ipv4]")?;
+            writeln!(out, "address1={}", address)?;
+            writeln!(out, "dns={};", list(&dns, ";"))?;
+            writeln!(out, "method=manual")?;
+            writeln!(out, "\n[ipv6]")?;
+            writeln!(out, "method=disabled")?;
+            vec![file(format!("{}.nmconnection", name), out)]
+        }
+        ExportFormat::Networkd => {
+            let mut netdev = String::new();
+            writeln!(netdev, "[NetDev]")?;
+            writeln!(netdev, "Name={}", INTERFACE)?;
+            writeln!(netdev, "Kind=wireguard")?;
+            writeln!(netdev, "Description={}", client.name)?;
+            writeln!(netdev, "\n[WireGuard]")?;
+            writeln!(netdev, "PrivateKey={}", client.keys.private)?;
+            // Default routes would also catch the encrypted packets to the endpoint. Like
+            // wg-quick they go to their own table, which the marked tunnel packets skip.
+            let defaults: Vec<&IpNet> = allowed_ips
+                .iter()
+                .filter(|net| net.prefix_len() == 0)
+                .collect();
+            if !defaults.is_empty() {
+                writeln!(netdev, "FirewallMark={}", ROUTE_TABLE)?;
+            }
+            writeln!(netdev, "\n[WireGuardPeer]")?;
+            writeln!(netdev, "PublicKey={}", server.keys.public)?;
+            writeln!(netdev, "Endpoint={}", endpoint)?;
+            writeln!(netdev, "AllowedIPs={}", list(&allowed_ips, ","))?;
+            if keepalive != 0 {
+                writeln!(netdev, "PersistentKeepalive={}", keepalive)?;
+            }
+
+            let mut network = String::new();
+            writeln!(network, "[Match]")?;
+            writeln!(network, "Name={}", INTERFACE)?;
+            writeln!(network, "\n[Network]")?;
+            writeln!(network, "Address={}", address)?;
+            writeln!(network, "DNS={}", list(&dns, " "))?;
+            for allowed_ip in &allowed_ips {
+                writeln!(network, "\n[Route]")?;
+                writeln!(network, "Destination={}", allowed_ip)?;
+                if allowed_ip.prefix_len() == 0 {
+                    writeln!(network, "Table={}", ROUTE_TABLE)?;
+                }
+            }
+            for default in defaults {
+                let family = match default {
+                    IpNet::V4(_) => "ipv4",
+                    IpNet::V6(_) => "ipv6",
+                };
+                // More specific routes of the main table, like the local network, still win.
+                writeln!(network, "\n[RoutingPolicyRule]")?;
+                writeln!(network, "Family={}", family)?;
+                writeln!(network, "Table=main")?;
+                writeln!(network, "SuppressPrefixLength=0")?;
+                writeln!(network, "Priority=32764")?;
+                writeln!(network, "\n[RoutingPolicyRule]")?;
+                writeln!(network, "Family={}", family)?;
+                writeln!(network, "FirewallMark={}", ROUTE_TABLE)?;
+                writeln!(network, "InvertRule=yes")?;
+                writeln!(network, "Table={}", ROUTE_TABLE)?;
+                writeln!(network, "Priority=32765")?;
+            }
+
+            vec![
+                file(format!("{}.netdev", INTERFACE), netdev),
+                file(format!("{}.network", INTERFACE), network),
+            ]
+        }
+        ExportFormat::OpenWrt => {
+            let mut out = String::new();
+            writeln!(out, "config interface '{}'", INTERFACE)?;
+            writeln!(out, "\toption proto 'wireguard'")?;
+            writeln!(out, "\toption private_key '{}'", client.keys.private)?;
+            writeln!(out, "\tlist addresses '{}'", address)?;
+            for dns in &dns {
+                writeln!(out, "\tlist dns '{}'", dns)?;
+            }
+            writeln!(out, "\nconfig wireguard_{}", INTERFACE)?;
+            writeln!(out, "\toption description '{}'", uci_escape(&client.name))?;
+            writeln!(out, "\toption public_key '{}'", server.keys.public)?;
+            writeln!(
+                out,
+                "\toption endpoint_host '{}'",
+                uci_escape(&server.endpoint)
+            )?;
+            writeln!(out, "\toption endpoint_port '{}'", server.port)?;
+            if keepalive != 0 {
+                writeln!(out, "\toption persistent_keepalive '{}'", keepalive)?;
+            }
+            writeln!(out, "\toption route_allowed_ips '1'")?;
+            for allowed_ip in &allowed_ips {
+                writeln!(out, "\tlist allowed_ips '{}'", allowed_ip)?;
+            }
+            vec![file(format!("{}.uci", name), out)]
+        }
+        ExportFormat::Mikrotik => {
+            let mut out = String::new();
+            writeln!(
+                out,
+                "/interface wireguard add name={} private-key=\"{}\" comment=\"{}\"",
+                INTERFACE,
+                client.keys.private,
+                routeros_escape(&client.name)
+            )?;
+            writeln!(
+                out,
+                "/ip address add address={} interface={}",
+                address, INTERFACE
+            )?;
+            writeln!(
+                out,
+                "/interface wireguard peers add interface={} public-key=\"{}\" endpoint-address=\"{}\" endpoint-port={} allowed-address={} persistent-keepalive={}s",
+                INTERFACE,
+                server.keys.public,
+                routeros_escape(&server.endpoint),
+                server.port,
+                list(&allowed_ips, ","),
+                keepalive
+            )?;
+            for allowed_ip in &allowed_ips {
+                match allowed_ip {
+                    IpNet::V4(net) if net.prefix_len() == 0 => {
+                        // The endpoint keeps its route through the current gateway, the two
+                        // halves of the address space then win over the default route. An
+                        // IPv6 endpoint isn't affected by IPv4 routes.
+                        if server.endpoint.parse::<Ipv6Addr>().is_err() {
+                            writeln!(
+                                out,
+                                "{{ :local gateway [/ip route get [:pick [/ip route find dst-address=0.0.0.0/0 active=yes] 0] gateway]; /ip route add dst-address=[:resolve \"{}\"] gateway=$gateway comment=\"{} endpoint\" }}",
+                                routeros_escape(&server.endpoint),
+                                INTERFACE
+                            )?;
+                        }
+                        for half in ["0.0.0.0/1", "128.0.0.0/1"] {
+                            writeln!(
+                                out,
+                                "/ip route add dst-address={} gateway={}",
+                                half, INTERFACE
+                            )?;
+                        }
+                    }
+                    IpNet::V4(net) => writeln!(
+                        out,
+                        "/ip route add dst-address={} gateway={}",
+                        net, INTERFACE
+                    )?,
+                    IpNet::V6(net) => writeln!(
+                        out,
+                        "/ipv6 route add dst-address={} gateway={}",
+                        net, INTERFACE
+                    )?,
+                }
+            }
+            writeln!(out, "/ip dns set servers={}", list(&dns, ","))?;
+            vec![file(format!("{}.rsc", name), out)]
+        }
+        ExportFormat::Json => {
+            let config = JsonConfig {
+                name: &client.name,
+                address,
+                private_key: &client.keys.private,
+                public_key: &client.keys.public,
+                dns,
+                peer: JsonPeer {
+                    public_key: &server.keys.public,
+                    endpoint,
+                    allowed_ips,
+                    persistent_keepalive: keepalive,
+                },
+            };
+            let mut out = serde_json::to_string_pretty(&config)?;
+            out.push('\n');
+            vec![file(format!("{}.json", name), out)]
+        }
+    };
+    Ok(files)
+}
+
+/// Turns a client name into something safe to use as a file name.
+pub(crate) fn file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() {
+        "client".to_string()
+    } else {
+        name
+    }
+}
+
+fn list<T: ToString>(values: &[T], separator: &str) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn uci_escape(value: &str) -> String {
+    value.replace('\'', "'\\''")
+}
+
+fn routeros_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::client::Routing;
+
+    fn server() -> (Server, Client) {
+        let mut server = Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "vpn.example.com".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        let id = server
+            .add_client("bob's phone".to_string(), Vec::new())
+            .unwrap();
+        let client = server.get_client(id).unwrap();
+        (server, client)
+    }
+
+    #[test]
+    fn exports_every_format() {
+        let (server, client) = server();
+
+        let files = export(&client, &server, ExportFormat::Networkd).unwrap();
+        let names: Vec<&str> = files.iter().map(|file| file.name.as_str()).collect();
+        assert_eq!(names, ["wg0.netdev", "wg0.network"]);
+        assert!(files[1].contents.contains("Address=10.0.0.2/32\n"));
+        assert!(files[0].contents.contains("FirewallMark=51820\n"));
+        assert!(files[1]
+            .contents
+            .contains("[Route]\nDestination=0.0.0.0/0\nTable=51820\n"));
+        assert!(files[1].contents.contains(
+            "[RoutingPolicyRule]\nFamily=ipv4\nFirewallMark=51820\nInvertRule=yes\nTable=51820\n"
+        ));
+        assert!(join(&files).starts_with("# wg0.netdev\n[NetDev]\n"));
+
+        let files = export(&client, &server, ExportFormat::NetworkManager).unwrap();
+        assert_eq!(files[0].name, "bob_s_phone.nmconnection");
+        assert!(files[0].contents.contains(&format!(
+            "[wireguard-peer.{}]\nendpoint=vpn.example.com:51820\n",
+            server.keys.public
+        )));
+
+        let files = export(&client, &server, ExportFormat::OpenWrt).unwrap();
+        assert!(files[0]
+            .contents
+            .contains("option description 'bob'\\''s phone'\n"));
+
+        let files = export(&client, &server, ExportFormat::Mikrotik).unwrap();
+        assert!(files[0]
+            .contents
+            .contains("/ip address add address=10.0.0.2/32 interface=wg0\n"));
+        assert!(files[0]
+            .contents
+            .contains("/ip route add dst-address=[:resolve \"vpn.example.com\"]"));
+        assert!(files[0]
+            .contents
+            .contains("/ip route add dst-address=128.0.0.0/1 gateway=wg0\n"));
+
+        let files = export(&client, &server, ExportFormat::Json).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&files[0].contents).unwrap();
+        assert_eq!(json["address"], "10.0.0.2/32");
+        assert_eq!(json["peer"]["endpoint"], "vpn.example.com:51820");
+
+        let files = export(&client, &server, ExportFormat::WgQuick).unwrap();
+        assert_eq!(files[0].contents, client.get_wg_config(&server));
+    }
+
+    #[test]
+    fn routes_split_tunnel_through_main_table() {
+        let (mut server, client) = server();
+        server.client_defaults.routing = Routing::Split;
+
+        let files = export(&client, &server, ExportFormat::Networkd).unwrap();
+        assert!(!files[0].contents.contains("FirewallMark"));
+        assert!(files[1]
+            .contents
+            .ends_with("[Route]\nDestination=10.0.0.0/24\n"));
+
+        let files = export(&client, &server, ExportFormat::Mikrotik).unwrap();
+        assert!(files[0]
+            .contents
+            .contains("/ip route add dst-address=10.0.0.0/24 gateway=wg0\n"));
+        assert!(!files[0].contents.contains(":resolve"));
+    }
+}
@@ -0,0 +1,64 @@
+//! Creation of files holding private keys, which should be readable by their owner only.
+
+use std::{
+    fs::{File, OpenOptions},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::Path,
+};
+
+use anyhow::anyhow;
+
+/// Creates or truncates a file readable by the owner only, for anything holding private keys.
+pub fn create_private_file(path: &Path) -> anyhow::Result<File> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .map_err(|err| anyhow!("failed to create {}: {}", path.display(), err))?;
+    // The mode only applies to new files.
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+/// Creates an empty file readable by the owner only unless it already exists, so a database
+/// opened on it afterwards keeps these permissions.
+pub fn ensure_private_file(path: &Path) -> anyhow::Result<()> {
+    match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+    {
+        Err(err) if err.kind() != std::io::ErrorKind::AlreadyExists => {
+            Err(anyhow!("failed to create {}: {}", path.display(), err))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creates_private_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let path = dir.path().join("config");
+        std::fs::write(&path, "old").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        create_private_file(&path).unwrap();
+        assert_eq!(mode(&path), 0o600);
+        assert!(std::fs::read(&path).unwrap().is_empty());
+
+        let path = dir.path().join("db");
+        ensure_private_file(&path).unwrap();
+        assert_eq!(mode(&path), 0o600);
+        std::fs::write(&path, "data").unwrap();
+        ensure_private_file(&path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"data");
+    }
+}
@@ -0,0 +1,479 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::UNIX_EPOCH,
+};
+
+use anyhow::anyhow;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    metrics::DeviceStats,
+    sessions::{self, Session, SessionEvent},
+    Server,
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS counters (
+    public_key TEXT PRIMARY KEY,
+    received INTEGER NOT NULL,
+    sent INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS traffic (
+    public_key TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    received INTEGER NOT NULL,
+    sent INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS traffic_key_timestamp ON traffic (public_key, timestamp);
+";
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Bucket {
+    Hour,
+    Day,
+}
+
+impl Bucket {
+    fn seconds(self) -> u64 {
+        match self {
+            Bucket::Hour => 60 * 60,
+            Bucket::Day => 24 * 60 * 60,
+        }
+    }
+}
+
+impl FromStr for Bucket {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hour" => Ok(Bucket::Hour),
+            "day" => Ok(Bucket::Day),
+            _ => Err(anyhow!("unknown bucket {}, expected hour or day", s)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TrafficPoint {
+    pub client_id: usize,
+    pub start: u64,
+    pub received_bytes: u64,
+    pub sent_bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct TrafficTotal {
+    pub client_id: usize,
+    pub received_bytes: u64,
+    pub sent_bytes: u64,
+}
+
+/// Traffic history stored in a local SQLite database.
+///
+/// Each sample stores per-client deltas against the previous raw counters, so totals keep
+/// growing even when the interface restarts and its counters drop back to zero. Samples also
+/// drive client sessions, see [`sessions`].
+///
+/// Rows are keyed by public key since client ids are reused after removal and change when the
+/// subnet is resized. Results carry the current id of the client, history of removed clients
+/// is left out.
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        conn.execute_batch(sessions::SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    pub fn record(
+        &mut self,
+        server: &Server,
+        stats: &DeviceStats,
+        timestamp: u64,
+    ) -> anyhow::Result<Vec<SessionEvent>> {
+        let tx = self.conn.transaction()?;
+        let mut events = Vec::new();
+        for peer in &stats.peers {
+            let client = match server.find_client_by_public_key(&peer.public_key) {
+                Some(client) => client,
+                None => continue,
+            };
+
+            let received = to_sql_int(peer.received_bytes);
+            let sent = to_sql_int(peer.sent_bytes);
+            let previous: Option<(i64, i64)> = tx
+                .query_row(
+                    "SELECT received, sent FROM counters WHERE public_key = ?1",
+                    params![peer.public_key],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+
+            let (received_delta, sent_delta) = match previous {
+                Some((prev_received, prev_sent)) => (
+                    counter_delta(prev_received, received),
+                    counter_delta(prev_sent, sent),
+                ),
+                None => (received, sent),
+            };
+
+            tx.execute(
+                "INSERT INTO counters (public_key, received, sent) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (public_key) DO UPDATE SET received = ?2, sent = ?3",
+                params![peer.public_key, received, sent],
+            )?;
+
+            if received_delta > 0 || sent_delta > 0 {
+                tx.execute(
+                    "INSERT INTO traffic (public_key, timestamp, received, sent)
+                     VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        peer.public_key,
+                        timestamp as i64,
+                        received_delta,
+                        sent_delta
+                    ],
+                )?;
+            }
+
+            events.extend(sessions::update(
+                &tx,
+                &client,
+                peer,
+                (received_delta, sent_delta),
+                timestamp,
+            )?);
+        }
+        tx.commit()?;
+        Ok(events)
+    }
+
+    /// Session events logged since the previous call with the same `consumer`, whichever
+    /// process recorded them. The first call of a consumer returns nothing.
+    pub fn take_events(
+        &mut self,
+        server: &Server,
+        consumer: &str,
+    ) -> anyhow::Result<Vec<SessionEvent>> {
+        let tx = self.conn.transaction()?;
+        let events = sessions::take_events(&tx, server, consumer)?;
+        tx.commit()?;
+        Ok(events)
+    }
+
+    /// Returns the latest `limit` sessions of the client, newest first.
+    pub fn sessions(
+        &self,
+        server: &Server,
+        client_id: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Session>> {
+        sessions::list(&self.conn, &server.get_client(client_id)?, limit)
+    }
+
+    pub fn totals(&self, server: &Server) -> anyhow::Result<Vec<TrafficTotal>> {
+        let ids = client_ids(server);
+        let mut stmt = self.conn.prepare(
+            "SELECT public_key, SUM(received), SUM(sent) FROM traffic GROUP BY public_key",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)? as u64,
+                row.get::<_, i64>(2)? as u64,
+            ))
+        })?;
+        let mut totals = Vec::new();
+        for row in rows {
+            let (public_key, received_bytes, sent_bytes) = row?;
+            if let Some(&client_id) = ids.get(public_key.as_str()) {
+                totals.push(TrafficTotal {
+                    client_id,
+                    received_bytes,
+                    sent_bytes,
+                });
+            }
+        }
+        totals.sort_by_key(|total| total.client_id);
+        Ok(totals)
+    }
+
+    /// Returns traffic aggregated into buckets for the `[from, to)` time range. Buckets without
+    /// traffic are omitted.
+    pub fn query(
+        &self,
+        server: &Server,
+        client_id: Option<usize>,
+        from: u64,
+        to: u64,
+        bucket: Bucket,
+    ) -> anyhow::Result<Vec<TrafficPoint>> {
+        let public_key = match client_id {
+            Some(id) => Some(server.get_client(id)?.keys.public),
+            None => None,
+        };
+        let ids = client_ids(server);
+        let mut stmt = self.conn.prepare(
+            "SELECT public_key, (timestamp / ?1) * ?1 AS start, SUM(received), SUM(sent)
+             FROM traffic
+             WHERE timestamp >= ?2 AND timestamp < ?3 AND (?4 IS NULL OR public_key = ?4)
+             GROUP BY public_key, start",
+        )?;
+        let rows = stmt.query_map(
+            params![bucket.seconds() as i64, from as i64, to as i64, public_key],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)? as u64,
+                    row.get::<_, i64>(2)? as u64,
+                    row.get::<_, i64>(3)? as u64,
+                ))
+            },
+        )?;
+        let mut points = Vec::new();
+        for row in rows {
+            let (public_key, start, received_bytes, sent_bytes) = row?;
+            if let Some(&client_id) = ids.get(public_key.as_str()) {
+                points.push(TrafficPoint {
+                    client_id,
+                    start,
+                    received_bytes,
+                    sent_bytes,
+                });
+            }
+        }
+        points.sort_by_key(|point| (point.start, point.client_id));
+        Ok(points)
+    }
+}
+
+fn client_ids(server: &Server) -> HashMap<&str, usize> {
+    server
+        .clients
+        .values()
+        .map(|client| (client.keys.public.as_str(), client.id))
+        .collect()
+}
+
+/// Returns the history database path used next to the server config.
+pub fn default_path(config_path: &Path) -> PathBuf {
+    let mut path = config_path.as_os_str().to_owned();
+    path.push(".history");
+    path.into()
+}
+
+pub fn unix_now() -> anyhow::Result<u64> {
+    Ok(std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .as_secs())
+}
+
+fn counter_delta(previous: i64, current: i64) -> i64 {
+    if current >= previous {
+        current - previous
+    } else {
+        // The interface was restarted and its counters started from zero.
+        current
+    }
+}
+
+fn to_sql_int(value: u128) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{InterfaceStats, PeerStats};
+
+    fn server_with_client() -> (Server, String) {
+        let mut server = Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        let id = server.add_client("alice".to_string(), Vec::new()).unwrap();
+        let public_key = server.get_client(id).unwrap().keys.public;
+        (server, public_key)
+    }
+
+    fn stats(public_key: &str, received: u128, sent: u128) -> DeviceStats {
+        stats_with_handshake(public_key, received, sent, None)
+    }
+
+    fn stats_with_handshake(
+        public_key: &str,
+        received: u128,
+        sent: u128,
+        latest_handshake: Option<u64>,
+    ) -> DeviceStats {
+        DeviceStats {
+            interface: InterfaceStats {
+                public_key: String::new(),
+                listen_port: 51820,
+                fwmark: None,
+            },
+            peers: vec![PeerStats {
+                public_key: public_key.to_string(),
+                endpoint: Some("203.0.113.7:40000".parse().unwrap()),
+                allowed_ips: Vec::new(),
+                latest_handshake,
+                received_bytes: received,
+                sent_bytes: sent,
+                persistent_keepalive: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn totals_survive_counter_reset() {
+        let (server, public_key) = server_with_client();
+        let mut history = History::open(Path::new(":memory:")).unwrap();
+
+        history
+            .record(&server, &stats(&public_key, 100, 10), 0)
+            .unwrap();
+        history
+            .record(&server, &stats(&public_key, 150, 30), 60)
+            .unwrap();
+        // The interface was restarted between samples.
+        history
+            .record(&server, &stats(&public_key, 20, 5), 120)
+            .unwrap();
+
+        let totals = history.totals(&server).unwrap();
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].received_bytes, 170);
+        assert_eq!(totals[0].sent_bytes, 35);
+    }
+
+    #[test]
+    fn query_groups_by_bucket() {
+        let (server, public_key) = server_with_client();
+        let mut history = History::open(Path::new(":memory:")).unwrap();
+
+        history
+            .record(&server, &stats(&public_key, 100, 0), 10)
+            .unwrap();
+        history
+            .record(&server, &stats(&public_key, 300, 0), 3000)
+            .unwrap();
+        history
+            .record(&server, &stats(&public_key, 350, 0), 3700)
+            .unwrap();
+
+        let points = history.query(&server, None, 0, 7200, Bucket::Hour).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].start, points[0].received_bytes), (0, 300));
+        assert_eq!((points[1].start, points[1].received_bytes), (3600, 50));
+
+        let points = history
+            .query(&server, Some(0), 0, 3600, Bucket::Day)
+            .unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].received_bytes, 300);
+    }
+
+    #[test]
+    fn reused_ids_get_no_history() {
+        let (mut server, public_key) = server_with_client();
+        let mut history = History::open(Path::new(":memory:")).unwrap();
+        history
+            .record(
+                &server,
+                &stats_with_handshake(&public_key, 100, 0, Some(0)),
+                0,
+            )
+            .unwrap();
+        assert_eq!(history.totals(&server).unwrap().len(), 1);
+
+        server.remove_client(0).unwrap();
+        let id = server.add_client("bob".to_string(), Vec::new()).unwrap();
+        assert_eq!(id, 0);
+        assert!(history.totals(&server).unwrap().is_empty());
+        assert!(history.sessions(&server, 0, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn sessions_follow_handshakes_and_traffic() {
+        use crate::sessions::SessionEventKind;
+
+        let (server, public_key) = server_with_client();
+        let mut history = History::open(Path::new(":memory:")).unwrap();
+
+        let events = history
+            .record(&server, &stats_with_handshake(&public_key, 0, 0, None), 0)
+            .unwrap();
+        assert!(events.is_empty());
+
+        let events = history
+            .record(
+                &server,
+                &stats_with_handshake(&public_key, 100, 50, Some(100)),
+                110,
+            )
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].kind == SessionEventKind::Connected);
+
+        let events = history
+            .record(
+                &server,
+                &stats_with_handshake(&public_key, 200, 50, Some(100)),
+                170,
+            )
+            .unwrap();
+        assert!(events.is_empty());
+
+        let events = history
+            .record(
+                &server,
+                &stats_with_handshake(&public_key, 200, 50, Some(100)),
+                1000,
+            )
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].kind == SessionEventKind::Disconnected);
+
+        let sessions = history.sessions(&server, 0, 10).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].started_at, 110);
+        assert_eq!(sessions[0].ended_at, Some(170));
+        assert_eq!(sessions[0].received_bytes, 200);
+        assert_eq!(sessions[0].endpoint, Some("203.0.113.7".parse().unwrap()));
+    }
+
+    #[test]
+    fn events_reach_every_consumer() {
+        let (server, public_key) = server_with_client();
+        let mut history = History::open(Path::new(":memory:")).unwrap();
+        assert!(history.take_events(&server, "bot").unwrap().is_empty());
+
+        // Whoever records the sample, each consumer gets the event once.
+        history
+            .record(
+                &server,
+                &stats_with_handshake(&public_key, 100, 50, Some(100)),
+                110,
+            )
+            .unwrap();
+        let events = history.take_events(&server, "bot").unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].client_name, "alice");
+        assert_eq!(events[0].endpoint, Some("203.0.113.7".parse().unwrap()));
+        assert!(history.take_events(&server, "bot").unwrap().is_empty());
+
+        // A new consumer starts at the end of the log.
+        assert!(history.take_events(&server, "other").unwrap().is_empty());
+    }
+}
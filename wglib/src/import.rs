@@ -0,0 +1,179 @@
+use std::{
+    io::{Seek, Write},
+    net::Ipv4Addr,
+    path::Path,
+};
+
+use serde::Deserialize;
+use zip::{write::FileOptions, ZipWriter};
+
+use crate::{export, Server};
+
+/// A client to create in bulk.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ClientSpec {
+    pub name: String,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Static address of the client inside the server subnet.
+    #[serde(default)]
+    pub ip: Option<Ipv4Addr>,
+}
+
+#[derive(Deserialize)]
+struct CsvRow {
+    name: String,
+    #[serde(default)]
+    owner: Option<String>,
+    #[serde(default)]
+    tags: Option<String>,
+    #[serde(default)]
+    ip: Option<Ipv4Addr>,
+}
+
+/// Parses a CSV file with a `name,owner,tags,ip` header. Only `name` is required, tags are
+/// separated with `;`.
+pub fn parse_csv(data: &str) -> anyhow::Result<Vec<ClientSpec>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+    let mut specs = Vec::new();
+    for row in reader.deserialize() {
+        let row: CsvRow = row?;
+        specs.push(ClientSpec {
+            name: row.name,
+            owner: row.owner.filter(|owner| !owner.is_empty()),
+            tags: row
+                .tags
+                .unwrap_or_default()
+                .split(';')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect(),
+            ip: row.ip,
+        });
+    }
+    Ok(specs)
+}
+
+/// Parses a JSON array of clients.
+pub fn parse_json(data: &str) -> anyhow::Result<Vec<ClientSpec>> {
+    Ok(serde_json::from_str(data)?)
+}
+
+/// Reads clients from a `.json` file or, for any other extension, a CSV file.
+pub fn parse_file(path: &Path) -> anyhow::Result<Vec<ClientSpec>> {
+    let data = std::fs::read_to_string(path)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => parse_json(&data),
+        _ => parse_csv(&data),
+    }
+}
+
+/// Writes a zip archive with a wg-quick config for every client in `ids`.
+pub fn write_configs_zip(
+    server: &Server,
+    ids: &[usize],
+    writer: impl Write + Seek,
+) -> anyhow::Result<()> {
+    let mut zip = ZipWriter::new(writer);
+    for &id in ids {
+        let client = server.get_client(id)?;
+        let name = format!("{}-{}.conf", id, export::file_name(&client.name));
+        zip.start_file(name, FileOptions::default().unix_permissions(0o600))?;
+        zip.write_all(client.get_wg_config(server).as_bytes())?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> Server {
+        Server::new(
+            "10.0.0.0/29".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        )
+    }
+
+    #[test]
+    fn parses_csv() {
+        let specs = parse_csv(
+            "name,owner,tags,ip\nlaptop,alice,staff; it ,10.0.0.5\nphone,,,\n\"desk, 2\",bob,,\n",
+        )
+        .unwrap();
+        assert_eq!(
+            specs,
+            vec![
+                ClientSpec {
+                    name: "laptop".to_string(),
+                    owner: Some("alice".to_string()),
+                    tags: vec!["staff".to_string(), "it".to_string()],
+                    ip: Some("10.0.0.5".parse().unwrap()),
+                },
+                ClientSpec {
+                    name: "phone".to_string(),
+                    owner: None,
+                    tags: Vec::new(),
+                    ip: None,
+                },
+                ClientSpec {
+                    name: "desk, 2".to_string(),
+                    owner: Some("bob".to_string()),
+                    tags: Vec::new(),
+                    ip: None,
+                },
+            ]
+        );
+        assert!(parse_csv("owner\nalice\n").is_err());
+    }
+
+    #[test]
+    fn adds_all_or_nothing() {
+        let mut server = server();
+        let specs = parse_json(r#"[{"name": "a", "ip": "10.0.0.2"}, {"name": "b"}]"#).unwrap();
+        let ids = server.add_clients(&specs).unwrap();
+        assert_eq!(ids, vec![0, 1]);
+        assert_eq!(
+            server.get_client(0).unwrap().get_ip_address(&server.subnet),
+            "10.0.0.2".parse::<Ipv4Addr>().unwrap()
+        );
+
+        // A /29 holds 5 clients, so 3 more don't fit and nothing is added.
+        let specs =
+            parse_json(r#"[{"name": "c"}, {"name": "d"}, {"name": "e"}, {"name": "f"}]"#).unwrap();
+        assert!(server.add_clients(&specs).is_err());
+        assert_eq!(server.clients.len(), 2);
+
+        let specs = parse_json(r#"[{"name": "c", "ip": "10.0.0.3"}]"#).unwrap();
+        assert!(server.add_clients(&specs).is_err());
+        let specs = parse_json(r#"[{"name": "c", "ip": "10.0.1.3"}]"#).unwrap();
+        assert!(server.add_clients(&specs).is_err());
+    }
+
+    #[test]
+    fn zips_configs() {
+        let mut server = server();
+        let ids = server
+            .add_clients(&parse_json(r#"[{"name": "a b"}, {"name": "c"}]"#).unwrap())
+            .unwrap();
+        let mut archive = std::io::Cursor::new(Vec::new());
+        write_configs_zip(&server, &ids, &mut archive).unwrap();
+
+        let mut zip = zip::ZipArchive::new(archive).unwrap();
+        let mut names: Vec<&str> = zip.file_names().collect();
+        names.sort();
+        assert_eq!(names, vec!["0-a_b.conf", "1-c.conf"]);
+        assert_eq!(
+            std::io::read_to_string(zip.by_name("1-c.conf").unwrap()).unwrap(),
+            server.get_client(1).unwrap().get_wg_config(&server)
+        );
+    }
+}
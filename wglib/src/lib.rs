@@ -1,7 +1,26 @@
 pub mod actions;
+pub mod audit;
+pub mod backup;
+pub mod context;
 pub mod crypto;
+pub mod doctor;
+pub mod export;
+pub mod fs;
+pub mod history;
+pub mod import;
 pub mod metrics;
 pub mod model;
+pub mod net;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod plan;
+pub mod prometheus;
+pub mod qr;
+pub mod service;
+pub mod sessions;
+pub mod status;
+pub mod storage;
+pub mod sync;
 
 pub use model::client::Client;
 pub use model::server::Server;
@@ -1,53 +1,271 @@
-use std::time::UNIX_EPOCH;
+use std::{net::SocketAddr, time::UNIX_EPOCH};
 
+use anyhow::{anyhow, Context};
+use ipnet::IpNet;
 use serde::Serialize;
 
 #[derive(Serialize)]
 pub struct ClientMetrics {
     pub public_key: String,
-    pub latest_handshake: u64,
+    pub latest_handshake: Option<u64>,
     pub received_bytes: u128,
     pub sent_bytes: u128,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct InterfaceStats {
+    pub public_key: String,
+    pub listen_port: u16,
+    pub fwmark: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PeerStats {
+    pub public_key: String,
+    pub endpoint: Option<SocketAddr>,
+    pub allowed_ips: Vec<IpNet>,
+    /// Unix timestamp of the latest handshake, `None` if the peer never connected.
+    pub latest_handshake: Option<u64>,
+    pub received_bytes: u128,
+    pub sent_bytes: u128,
+    pub persistent_keepalive: Option<u16>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DeviceStats {
+    pub interface: InterfaceStats,
+    pub peers: Vec<PeerStats>,
+}
+
 pub fn get_metrics(device: &str) -> anyhow::Result<Vec<ClientMetrics>> {
-    let dump = get_dump_from_wg(device)?;
+    let stats = get_device_stats(device)?;
 
     let mut metrics = Vec::new();
-    for line in dump.lines().skip(1) {
-        let (public_key, latest_handshake, received_bytes, sent_bytes) = parse_line(line)?;
-        let latest_handshake = get_seconds_from_now(latest_handshake)?;
+    for peer in stats.peers {
+        let latest_handshake = match peer.latest_handshake {
+            Some(timestamp) => Some(get_seconds_from_now(timestamp)?),
+            None => None,
+        };
 
         metrics.push(ClientMetrics {
-            public_key: public_key.to_string(),
+            public_key: peer.public_key,
             latest_handshake,
-            received_bytes,
-            sent_bytes,
+            received_bytes: peer.received_bytes,
+            sent_bytes: peer.sent_bytes,
         });
     }
     Ok(metrics)
 }
 
+pub fn get_device_stats(device: &str) -> anyhow::Result<DeviceStats> {
+    let dump = get_dump_from_wg(device)?;
+    parse_dump(&dump)
+}
+
+/// Parses the output of `wg show <device> dump`.
+pub fn parse_dump(dump: &str) -> anyhow::Result<DeviceStats> {
+    let mut lines = dump
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+
+    let interface = match lines.next() {
+        Some((_, line)) => parse_interface_line(line).context("malformed interface line")?,
+        None => return Err(anyhow!("wg dump is empty")),
+    };
+
+    let mut peers = Vec::new();
+    for (number, line) in lines {
+        let peer =
+            parse_peer_line(line).with_context(|| format!("malformed peer line {}", number + 1))?;
+        peers.push(peer);
+    }
+
+    Ok(DeviceStats { interface, peers })
+}
+
+/// Returns the number of seconds elapsed since the unix `timestamp`, saturating at zero for
+/// timestamps in the future.
+pub fn get_seconds_from_now(timestamp: u64) -> anyhow::Result<u64> {
+    let now = std::time::SystemTime::now().duration_since(UNIX_EPOCH)?;
+    Ok(now.as_secs().saturating_sub(timestamp))
+}
+
 fn get_dump_from_wg(device: &str) -> anyhow::Result<String> {
     let output = std::process::Command::new("wg")
         .arg("show")
         .arg(device)
         .arg("dump")
         .output()?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "wg show failed with exit code: {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
     Ok(String::from_utf8(output.stdout)?)
 }
 
-fn parse_line(line: &str) -> anyhow::Result<(&str, u64, u128, u128)> {
+fn parse_interface_line(line: &str) -> anyhow::Result<InterfaceStats> {
+    let items = split_fields(line, 4)?;
+    Ok(InterfaceStats {
+        public_key: items[1].to_string(),
+        listen_port: items[2].parse().context("invalid listen port")?,
+        fwmark: parse_off(items[3]).context("invalid fwmark")?,
+    })
+}
+
+fn parse_peer_line(line: &str) -> anyhow::Result<PeerStats> {
+    let items = split_fields(line, 8)?;
+
+    let endpoint = match items[2] {
+        "(none)" => None,
+        endpoint => Some(endpoint.parse().context("invalid endpoint")?),
+    };
+
+    let allowed_ips = match items[3] {
+        "(none)" => Vec::new(),
+        allowed_ips => allowed_ips
+            .split(',')
+            .map(|net| net.parse().context("invalid allowed ips"))
+            .collect::<anyhow::Result<_>>()?,
+    };
+
+    let latest_handshake = match items[4].parse().context("invalid latest handshake")? {
+        0 => None,
+        timestamp => Some(timestamp),
+    };
+
+    Ok(PeerStats {
+        public_key: items[0].to_string(),
+        endpoint,
+        allowed_ips,
+        latest_handshake,
+        received_bytes: items[5].parse().context("invalid received bytes")?,
+        sent_bytes: items[6].parse().context("invalid sent bytes")?,
+        persistent_keepalive: parse_off(items[7]).context("invalid persistent keepalive")?,
+    })
+}
+
+fn split_fields(line: &str, expected: usize) -> anyhow::Result<Vec<&str>> {
     let items: Vec<&str> = line.split_whitespace().collect();
-    Ok((
-        items[0],
-        items[4].parse()?,
-        items[5].parse()?,
-        items[6].parse()?,
-    ))
+    if items.len() != expected {
+        return Err(anyhow!(
+            "expected {} fields, found {}",
+            expected,
+            items.len()
+        ));
+    }
+    Ok(items)
 }
 
-fn get_seconds_from_now(timestamp: u64) -> anyhow::Result<u64> {
-    let now = std::time::SystemTime::now().duration_since(UNIX_EPOCH)?;
-    Ok(now.as_secs() - timestamp)
+fn parse_off<T: std::str::FromStr>(value: &str) -> Result<Option<T>, T::Err> {
+    match value {
+        "off" => Ok(None),
+        value => value.parse().map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMP: &str = "\
+eFw4jyC6CbWQ8bZrB2e5xLM3o7gYkDSCWp0ApmPRqHQ=\tZjdDpdVhi9hPgOgdLUwMqvbSwVsKnV2ia2v3d5rPNE0=\t51820\toff
+xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=\t(none)\t203.0.113.7:53211\t10.0.0.2/32\t1700000000\t1024\t2048\t25
+TrMvSoP4jYQlY6RIzBgbssQqY3vxI2Pi+y71lOWWXX0=\t(none)\t(none)\t10.0.0.3/32,fd00::3/128\t0\t0\t0\toff
+";
+
+    #[test]
+    fn parses_interface() {
+        let stats = parse_dump(DUMP).unwrap();
+        assert_eq!(
+            stats.interface,
+            InterfaceStats {
+                public_key: "ZjdDpdVhi9hPgOgdLUwMqvbSwVsKnV2ia2v3d5rPNE0=".to_string(),
+                listen_port: 51820,
+                fwmark: None,
+            }
+        );
+    }
+
+    #[test]
+    fn parses_connected_peer() {
+        let stats = parse_dump(DUMP).unwrap();
+        assert_eq!(
+            stats.peers[0],
+            PeerStats {
+                public_key: "xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=".to_string(),
+                endpoint: Some("203.0.113.7:53211".parse().unwrap()),
+                allowed_ips: vec!["10.0.0.2/32".parse().unwrap()],
+                latest_handshake: Some(1700000000),
+                received_bytes: 1024,
+                sent_bytes: 2048,
+                persistent_keepalive: Some(25),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_never_connected_peer() {
+        let stats = parse_dump(DUMP).unwrap();
+        let peer = &stats.peers[1];
+        assert_eq!(peer.endpoint, None);
+        assert_eq!(peer.latest_handshake, None);
+        assert_eq!(peer.persistent_keepalive, None);
+        assert_eq!(
+            peer.allowed_ips,
+            vec![
+                "10.0.0.3/32".parse::<IpNet>().unwrap(),
+                "fd00::3/128".parse::<IpNet>().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_interface_without_peers() {
+        let stats = parse_dump(
+            "eFw4jyC6CbWQ8bZrB2e5xLM3o7gYkDSCWp0ApmPRqHQ=\tZjdDpdVhi9hPgOgdLUwMqvbSwVsKnV2ia2v3d5rPNE0=\t51820\t51\n",
+        )
+        .unwrap();
+        assert_eq!(stats.interface.fwmark, Some(51));
+        assert!(stats.peers.is_empty());
+    }
+
+    #[test]
+    fn parses_ipv6_endpoint() {
+        let line = "xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=\t(none)\t[2001:db8::1]:51820\t(none)\t1700000000\t1\t2\toff";
+        let peer = parse_peer_line(line).unwrap();
+        assert_eq!(peer.endpoint, Some("[2001:db8::1]:51820".parse().unwrap()));
+        assert!(peer.allowed_ips.is_empty());
+    }
+
+    #[test]
+    fn rejects_empty_dump() {
+        assert!(parse_dump("").is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_peer_line() {
+        let dump = "\
+eFw4jyC6CbWQ8bZrB2e5xLM3o7gYkDSCWp0ApmPRqHQ=\tZjdDpdVhi9hPgOgdLUwMqvbSwVsKnV2ia2v3d5rPNE0=\t51820\toff
+xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=\t(none)\t203.0.113.7:53211
+";
+        let err = parse_dump(dump).unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn rejects_invalid_numbers() {
+        let line =
+            "xTIBA5rboUvnH4htodjb6e697QjLERt1NAB4mZqp8Dg=\t(none)\t(none)\t(none)\tsoon\t1\t2\toff";
+        assert!(parse_peer_line(line).is_err());
+    }
+
+    #[test]
+    fn seconds_from_now_saturates() {
+        assert_eq!(get_seconds_from_now(u64::MAX).unwrap(), 0);
+    }
 }
@@ -1,23 +1,121 @@
-use std::net::Ipv4Addr;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr},
+    str::FromStr,
+};
 
-use ipnet::Ipv4Net;
+use anyhow::anyhow;
+use ipnet::{IpNet, Ipv4Net};
 use serde::{Deserialize, Serialize};
 
 use crate::{crypto::KeyPair, Server};
 
+pub const DEFAULT_DNS: IpAddr = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+pub const DEFAULT_PERSISTENT_KEEPALIVE: u16 = 25;
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Client {
     pub id: usize,
     pub name: String,
     pub keys: KeyPair,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Person the client was issued to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// DNS servers used by the client, the ones of [`ClientDefaults`] if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns: Option<Vec<IpAddr>>,
+    /// Networks routed through the tunnel by the client, the [`Routing`] of [`ClientDefaults`]
+    /// if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_ips: Option<Vec<IpNet>>,
+    /// Keepalive interval of the client in seconds, 0 disables it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persistent_keepalive: Option<u16>,
+    /// Disabled clients keep their keys and address but aren't added to the interface.
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+/// Changes to apply to a client. Fields which are `None` are left as is.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ClientUpdate {
+    pub name: Option<String>,
+    pub tags: Option<Vec<String>>,
+    /// An empty string removes the owner.
+    pub owner: Option<String>,
+    /// An empty list resets the client to the default DNS servers.
+    pub dns: Option<Vec<IpAddr>>,
+    /// An empty list resets the client to the default routing.
+    pub allowed_ips: Option<Vec<IpNet>>,
+    pub persistent_keepalive: Option<u16>,
+    pub disabled: Option<bool>,
+}
+
+/// Settings of the server used by clients which don't set their own.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientDefaults {
+    /// [`DEFAULT_DNS`] if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dns: Option<Vec<IpAddr>>,
+    #[serde(default)]
+    pub routing: Routing,
+}
+
+/// What clients route through the tunnel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Routing {
+    /// All traffic, the server is the gateway of its clients.
+    #[default]
+    Full,
+    /// Only traffic to the VPN subnet.
+    Split,
+}
+
+impl Routing {
+    pub fn allowed_ips(self, subnet: &Ipv4Net) -> Vec<IpNet> {
+        match self {
+            Routing::Full => vec![IpNet::V4(Ipv4Net::default())],
+            Routing::Split => vec![IpNet::V4(subnet.trunc())],
+        }
+    }
+}
+
+impl FromStr for Routing {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => Ok(Routing::Full),
+            "split" => Ok(Routing::Split),
+            _ => Err(anyhow!("unknown routing {}, expected full or split", s)),
+        }
+    }
+}
+
+impl fmt::Display for Routing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Routing::Full => "full",
+            Routing::Split => "split",
+        })
+    }
 }
 
 impl Client {
-    pub fn new(id: usize, name: String) -> Self {
+    pub fn new(id: usize, name: String, tags: Vec<String>) -> Self {
         Self {
             id,
             name,
             keys: KeyPair::generate(),
+            tags,
+            owner: None,
+            dns: None,
+            allowed_ips: None,
+            persistent_keepalive: None,
+            disabled: false,
         }
     }
 
@@ -29,16 +127,74 @@ impl Client {
             self.get_ip_address(&server.subnet)
         ));
         config.push_str(&format!("PrivateKey = {}\n", self.keys.private));
-        config.push_str("DNS = 8.8.8.8\n");
+        config.push_str(&format!("DNS = {}\n", join(&self.get_dns(server))));
         config.push_str("\n[Peer]\n");
         config.push_str(&format!("PublicKey = {}\n", server.keys.public));
-        config.push_str(&format!("Endpoint = {}:{}\n", server.endpoint, server.port));
-        config.push_str("AllowedIPs = 0.0.0.0/0\n");
-        config.push_str("PersistentKeepalive = 25\n");
+        config.push_str(&format!("Endpoint = {}\n", server.endpoint_address()));
+        config.push_str(&format!(
+            "AllowedIPs = {}\n",
+            join(&self.get_allowed_ips(server))
+        ));
+        if self.get_persistent_keepalive() != 0 {
+            config.push_str(&format!(
+                "PersistentKeepalive = {}\n",
+                self.get_persistent_keepalive()
+            ));
+        }
         config
     }
 
     pub fn get_ip_address(&self, subnet: &Ipv4Net) -> Ipv4Addr {
         subnet.subnets(32).unwrap().nth(self.id + 2).unwrap().addr()
     }
+
+    pub fn get_dns(&self, server: &Server) -> Vec<IpAddr> {
+        self.dns
+            .clone()
+            .or_else(|| server.client_defaults.dns.clone())
+            .unwrap_or_else(|| vec![DEFAULT_DNS])
+    }
+
+    pub fn get_allowed_ips(&self, server: &Server) -> Vec<IpNet> {
+        self.allowed_ips
+            .clone()
+            .unwrap_or_else(|| server.client_defaults.routing.allowed_ips(&server.subnet))
+    }
+
+    pub fn get_persistent_keepalive(&self) -> u16 {
+        self.persistent_keepalive
+            .unwrap_or(DEFAULT_PERSISTENT_KEEPALIVE)
+    }
+
+    pub(crate) fn apply(&mut self, update: ClientUpdate) {
+        if let Some(name) = update.name {
+            self.name = name;
+        }
+        if let Some(tags) = update.tags {
+            self.tags = tags;
+        }
+        if let Some(owner) = update.owner {
+            self.owner = Some(owner).filter(|owner| !owner.is_empty());
+        }
+        if let Some(dns) = update.dns {
+            self.dns = Some(dns).filter(|dns| !dns.is_empty());
+        }
+        if let Some(allowed_ips) = update.allowed_ips {
+            self.allowed_ips = Some(allowed_ips).filter(|allowed_ips| !allowed_ips.is_empty());
+        }
+        if let Some(persistent_keepalive) = update.persistent_keepalive {
+            self.persistent_keepalive = Some(persistent_keepalive);
+        }
+        if let Some(disabled) = update.disabled {
+            self.disabled = disabled;
+        }
+    }
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
 }
@@ -0,0 +1,194 @@
+use std::{fmt, net::Ipv4Addr, str::FromStr};
+
+use anyhow::anyhow;
+use ipnet::Ipv4Net;
+use serde::{Deserialize, Serialize};
+
+/// Part of the subnet which is given out to clients. The first address of the subnet is the
+/// network itself, the second one belongs to the server and the last one is broadcast, so
+/// clients can only get addresses in between.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressPool {
+    /// First client address, the third address of the subnet if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<Ipv4Addr>,
+    /// Last client address, the one before broadcast if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<Ipv4Addr>,
+    /// Addresses kept for static infrastructure, never given to clients.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reserved: Vec<AddressRange>,
+}
+
+/// Inclusive range of addresses, written as `10.0.0.5`, `10.0.0.5-10.0.0.9` or `10.0.0.8/29`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressRange {
+    pub start: Ipv4Addr,
+    pub end: Ipv4Addr,
+}
+
+impl AddressRange {
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        self.start <= ip && ip <= self.end
+    }
+}
+
+impl FromStr for AddressRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = if let Some((start, end)) = s.split_once('-') {
+            (start.trim().parse()?, end.trim().parse()?)
+        } else if s.contains('/') {
+            let net: Ipv4Net = s.parse()?;
+            (net.network(), net.broadcast())
+        } else {
+            let ip = s.parse()?;
+            (ip, ip)
+        };
+        if start > end {
+            return Err(anyhow!("range {} ends before it starts", s));
+        }
+        Ok(AddressRange { start, end })
+    }
+}
+
+impl fmt::Display for AddressRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+impl AddressPool {
+    /// Returns the range of client addresses in `subnet`, failing if the pool doesn't fit into
+    /// the subnet or leaves no address for clients.
+    pub fn client_range(&self, subnet: &Ipv4Net) -> anyhow::Result<(Ipv4Addr, Ipv4Addr)> {
+        let network = u32::from(subnet.network());
+        let broadcast = u32::from(subnet.broadcast());
+        if broadcast - network < 3 {
+            return Err(anyhow!(
+                "subnet {} is too small, it has no addresses for clients",
+                subnet
+            ));
+        }
+        let first = Ipv4Addr::from(network + 2);
+        let last = Ipv4Addr::from(broadcast - 1);
+
+        let start = self.start.unwrap_or(first);
+        let end = self.end.unwrap_or(last);
+        if start < first || end > last || start > end {
+            return Err(anyhow!(
+                "pool {}-{} doesn't fit into client addresses {}-{} of {}",
+                start,
+                end,
+                first,
+                last,
+                subnet
+            ));
+        }
+        if self.capacity(start, end) == 0 {
+            return Err(anyhow!(
+                "every address of pool {}-{} is reserved",
+                start,
+                end
+            ));
+        }
+        Ok((start, end))
+    }
+
+    /// Returns the number of addresses between `start` and `end` which aren't reserved.
+    pub(crate) fn capacity(&self, start: Ipv4Addr, end: Ipv4Addr) -> usize {
+        let (start, end) = (u32::from(start), u32::from(end));
+        let mut reserved: Vec<(u32, u32)> = self
+            .reserved
+            .iter()
+            .map(|range| {
+                (
+                    u32::from(range.start).max(start),
+                    u32::from(range.end).min(end),
+                )
+            })
+            .filter(|(from, to)| from <= to)
+            .collect();
+        reserved.sort();
+
+        let mut taken = 0;
+        let mut covered_until: Option<u32> = None;
+        for (from, to) in reserved {
+            let from = match covered_until {
+                Some(covered) if covered >= to => continue,
+                Some(covered) if covered >= from => covered + 1,
+                _ => from,
+            };
+            taken += (to - from) as usize + 1;
+            covered_until = Some(to);
+        }
+        (end - start) as usize + 1 - taken
+    }
+
+    pub fn is_reserved(&self, ip: Ipv4Addr) -> bool {
+        self.reserved.iter().any(|range| range.contains(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ranges() {
+        let range: AddressRange = "10.0.0.5".parse().unwrap();
+        assert_eq!(range.to_string(), "10.0.0.5");
+        let range: AddressRange = "10.0.0.5-10.0.0.9".parse().unwrap();
+        assert!(range.contains("10.0.0.9".parse().unwrap()));
+        let range: AddressRange = "10.0.0.8/29".parse().unwrap();
+        assert_eq!(range.to_string(), "10.0.0.8-10.0.0.15");
+        assert!("10.0.0.9-10.0.0.5".parse::<AddressRange>().is_err());
+    }
+
+    #[test]
+    fn validates_pool() {
+        let pool = AddressPool::default();
+        let range = pool.client_range(&"10.0.0.0/30".parse().unwrap()).unwrap();
+        assert_eq!(
+            range,
+            ("10.0.0.2".parse().unwrap(), "10.0.0.2".parse().unwrap())
+        );
+        assert!(pool.client_range(&"10.0.0.0/31".parse().unwrap()).is_err());
+        assert!(pool.client_range(&"10.0.0.0/32".parse().unwrap()).is_err());
+
+        let pool = AddressPool {
+            start: Some("10.0.0.1".parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(pool.client_range(&"10.0.0.0/24".parse().unwrap()).is_err());
+
+        let pool = AddressPool {
+            start: Some("10.0.0.10".parse().unwrap()),
+            end: Some("10.0.0.11".parse().unwrap()),
+            reserved: vec!["10.0.0.10-10.0.0.11".parse().unwrap()],
+        };
+        assert!(pool.client_range(&"10.0.0.0/24".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn counts_overlapping_reservations() {
+        let pool = AddressPool {
+            start: None,
+            end: None,
+            reserved: vec![
+                "10.0.0.0/28".parse().unwrap(),
+                "10.0.0.10-10.0.0.20".parse().unwrap(),
+                "10.0.0.12".parse().unwrap(),
+                "10.0.0.30".parse().unwrap(),
+            ],
+        };
+        let (start, end) = pool.client_range(&"10.0.0.0/24".parse().unwrap()).unwrap();
+        // 2..=254 minus 2..=20 and 30.
+        assert_eq!(pool.capacity(start, end), 253 - 19 - 1);
+    }
+}
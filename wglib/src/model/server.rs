@@ -1,28 +1,52 @@
-use super::client::Client;
-use crate::crypto::KeyPair;
+use super::{
+    client::{Client, ClientDefaults, ClientUpdate},
+    pool::AddressPool,
+};
+use crate::{crypto::KeyPair, import::ClientSpec, storage};
 
 use anyhow::anyhow;
 use ipnet::Ipv4Net;
 use serde::{Deserialize, Serialize};
 
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+};
 pub struct Server {
     pub subnet: Ipv4Net,
     pub endpoint: String,
     pub port: u16,
     pub network_interface: String,
     pub keys: KeyPair,
+    pub pool: AddressPool,
+    pub client_defaults: ClientDefaults,
     pub clients: HashMap<usize, Client>,
 }
 
+/// A client whose config changed after the subnet was resized, because its address or, with
+/// split routing, its allowed IPs changed.
+#[derive(Clone, Debug, Serialize)]
+pub struct Renumbered {
+    pub old_id: usize,
+    pub id: usize,
+    pub name: String,
+    pub old_address: Ipv4Addr,
+    pub address: Ipv4Addr,
+}
+
 #[derive(Serialize, Deserialize)]
-struct ServerConfig {
+pub(crate) struct ServerConfig {
     subnet_cidr: String,
     endpoint: String,
     port: u16,
     network_interface: String,
     keys: KeyPair,
-    clients: HashMap<usize, Client>,
+    #[serde(default)]
+    pool: AddressPool,
+    #[serde(default)]
+    client_defaults: ClientDefaults,
+    pub(crate) clients: HashMap<usize, Client>,
 }
 
 impl Server {
@@ -33,22 +57,89 @@ impl Server {
             port,
             network_interface,
             keys: KeyPair::generate(),
+            pool: AddressPool::default(),
+            client_defaults: ClientDefaults::default(),
             clients: HashMap::new(),
         }
     }
 
-    pub fn add_client(&mut self, name: String) -> anyhow::Result<usize> {
+    /// Checks that the subnet and the address pool leave room for clients.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.pool.client_range(&self.subnet).map(|_| ())
+    }
+
+    /// Replaces the address pool, failing if any existing client is outside of it.
+    pub fn set_pool(&mut self, pool: AddressPool) -> anyhow::Result<()> {
+        pool.client_range(&self.subnet)?;
+        let previous = std::mem::replace(&mut self.pool, pool);
+        let mut outside: Vec<usize> = self
+            .clients
+            .keys()
+            .copied()
+            .filter(|&id| !self.is_client_id(id))
+            .collect();
+        if !outside.is_empty() {
+            self.pool = previous;
+            outside.sort();
+            return Err(anyhow!(
+                "clients {:?} have addresses outside of the pool",
+                outside
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn add_client(&mut self, name: String, tags: Vec<String>) -> anyhow::Result<usize> {
+        check_name(&name)?;
         let id = match self.find_free_id() {
             Some(v) => v,
             None => return Err(anyhow!("no enough space for new clients")),
         };
         assert!(
-            self.clients.insert(id, Client::new(id, name)).is_none(),
+            self.clients
+                .insert(id, Client::new(id, name, tags))
+                .is_none(),
             "find_free_id() returned invalid id"
         );
         Ok(id)
     }
 
+    /// Adds either all clients or, if any of them can't be added, none. Clients with a static
+    /// address get the id matching it, the others take free ids.
+    pub fn add_clients(&mut self, specs: &[ClientSpec]) -> anyhow::Result<Vec<usize>> {
+        let mut taken: HashSet<usize> = self.clients.keys().copied().collect();
+        let mut ids = Vec::with_capacity(specs.len());
+        for spec in specs {
+            check_name(&spec.name)?;
+            let id = match spec.ip {
+                Some(ip) => {
+                    let id = self.id_for_address(ip)?;
+                    if !taken.insert(id) {
+                        return Err(anyhow!("address {} of {} is already taken", ip, spec.name));
+                    }
+                    Some(id)
+                }
+                None => None,
+            };
+            ids.push(id);
+        }
+
+        let ids = {
+            let mut free = self.client_ids().filter(|id| !taken.contains(id));
+            ids.into_iter()
+                .map(|id| id.or_else(|| free.next()))
+                .collect::<Option<Vec<usize>>>()
+                .ok_or_else(|| anyhow!("no enough space for {} new clients", specs.len()))?
+        };
+
+        for (spec, &id) in specs.iter().zip(&ids) {
+            let mut client = Client::new(id, spec.name.clone(), spec.tags.clone());
+            client.owner = spec.owner.clone();
+            self.clients.insert(id, client);
+        }
+        Ok(ids)
+    }
+
     pub fn remove_client(&mut self, id: usize) -> anyhow::Result<Client> {
         match self.clients.remove(&id) {
             Some(client) => Ok(client),
@@ -56,31 +147,109 @@ impl Server {
         }
     }
 
-    pub fn load_from_file(config_path: &Path) -> anyhow::Result<Self> {
-        let file = std::fs::File::open(config_path)?;
-        let config = serde_json::from_reader(file)?;
-        Self::from_server_config(config)
+    /// Applies `update` to the client and returns its new state. Keys and address are kept.
+    pub fn update_client(&mut self, id: usize, update: ClientUpdate) -> anyhow::Result<Client> {
+        if let Some(name) = &update.name {
+            check_name(name)?;
+        }
+        match self.clients.get_mut(&id) {
+            Some(client) => {
+                client.apply(update);
+                Ok(client.clone())
+            }
+            None => Err(anyhow!("client with id {} doesn't exist", id)),
+        }
     }
 
-    pub fn dump_to_file(&self, config_path: &Path) -> anyhow::Result<()> {
-        let config = ServerConfig {
-            subnet_cidr: self.subnet.to_string(),
+    /// Moves the server to `subnet`, keeping all keys. Clients keep their ids if they fit into
+    /// the new subnet, the others get the lowest free ids. Returns the clients whose config
+    /// changed, they need a new one.
+    pub fn resize_subnet(&mut self, subnet: Ipv4Net) -> anyhow::Result<Vec<Renumbered>> {
+        let subnet = subnet.trunc();
+        let mut resized = Server {
+            subnet,
             endpoint: self.endpoint.clone(),
             port: self.port,
             network_interface: self.network_interface.clone(),
             keys: self.keys.clone(),
-            clients: self.clients.clone(),
+            pool: self.pool.clone(),
+            client_defaults: self.client_defaults.clone(),
+            clients: HashMap::new(),
         };
+        resized.validate()?;
+        if self.clients.len() > resized.max_number_of_clients() {
+            return Err(anyhow!(
+                "subnet {} fits {} clients, but there are {}",
+                subnet,
+                resized.max_number_of_clients(),
+                self.clients.len()
+            ));
+        }
 
-        let file = std::fs::File::create(config_path)?;
-        serde_json::to_writer(file, &config)?;
-        Ok(())
+        let mut ids: Vec<usize> = self.clients.keys().copied().collect();
+        ids.sort();
+        let (fitting, moved): (Vec<usize>, Vec<usize>) =
+            ids.into_iter().partition(|&id| resized.is_client_id(id));
+        for id in fitting {
+            resized.clients.insert(id, self.clients[&id].clone());
+        }
+        for old_id in moved {
+            let id = resized.find_free_id().expect("capacity was checked");
+            let mut client = self.clients[&old_id].clone();
+            client.id = id;
+            resized.clients.insert(id, client);
+        }
+
+        let mut renumbered: Vec<Renumbered> = Vec::new();
+        for (&id, client) in &resized.clients {
+            let old = self
+                .clients
+                .values()
+                .find(|old| old.keys.public == client.keys.public)
+                .expect("every client is kept");
+            let old_address = old.get_ip_address(&self.subnet);
+            let address = client.get_ip_address(&subnet);
+            if old.get_wg_config(self) != client.get_wg_config(&resized) {
+                renumbered.push(Renumbered {
+                    old_id: old.id,
+                    id,
+                    name: client.name.clone(),
+                    old_address,
+                    address,
+                });
+            }
+        }
+        renumbered.sort_by_key(|client| client.id);
+
+        *self = resized;
+        Ok(renumbered)
     }
 
+    /// Loads the server from `config_path`, which may be any supported [`storage`] kind.
+    ///
+    /// [`storage`]: crate::storage
+    pub fn load_from_file(config_path: &Path) -> anyhow::Result<Self> {
+        storage::open(config_path)?.load()
+    }
+
+    pub fn dump_to_file(&self, config_path: &Path) -> anyhow::Result<()> {
+        storage::open(config_path)?.save(self)
+    }
+
+    /// Returns the number of addresses in the pool, 0 if the subnet is too small.
     pub fn max_number_of_clients(&self) -> usize {
-        let prefix_len = self.subnet.prefix_len();
-        let subnet_size = 2usize.pow((32 - prefix_len) as u32);
-        subnet_size - 3
+        match self.pool.client_range(&self.subnet) {
+            Ok((start, end)) => self.pool.capacity(start, end),
+            Err(_) => 0,
+        }
+    }
+
+    /// `endpoint:port` as clients connect to it, IPv6 addresses are put in brackets.
+    pub fn endpoint_address(&self) -> String {
+        match self.endpoint.parse::<Ipv6Addr>() {
+            Ok(ip) => SocketAddr::from((ip, self.port)).to_string(),
+            Err(_) => format!("{}:{}", self.endpoint, self.port),
+        }
     }
 
     pub fn get_wg_config(&self) -> String {
@@ -95,7 +264,7 @@ impl Server {
         config.push_str(&format!("PostDown = iptables -D FORWARD -i %i -j ACCEPT; iptables -t nat -D POSTROUTING -o {} -j MASQUERADE\n", self.network_interface));
         config.push_str(&format!("ListenPort = {}\n", self.port));
         config.push_str(&format!("PrivateKey = {}\n", self.keys.private));
-        for client in self.clients.values() {
+        for client in self.clients.values().filter(|client| !client.disabled) {
             config.push('\n');
             config.push_str("[Peer]\n");
             config.push_str(&format!("PublicKey = {}\n", client.keys.public));
@@ -130,18 +299,295 @@ impl Server {
         None
     }
 
+    fn id_for_address(&self, ip: Ipv4Addr) -> anyhow::Result<usize> {
+        let offset = u32::from(ip).wrapping_sub(u32::from(self.subnet.network())) as usize;
+        if self.subnet.contains(&ip) && offset >= 2 && self.is_client_id(offset - 2) {
+            Ok(offset - 2)
+        } else {
+            Err(anyhow!(
+                "address {} is outside of the client pool of {}",
+                ip,
+                self.subnet
+            ))
+        }
+    }
+
+    /// Returns whether the address of client `id` is in the pool.
+    fn is_client_id(&self, id: usize) -> bool {
+        let Ok((start, end)) = self.pool.client_range(&self.subnet) else {
+            return false;
+        };
+        let Some(ip) = u32::try_from(id)
+            .ok()
+            .and_then(|id| {
+                u32::from(self.subnet.network())
+                    .checked_add(id)?
+                    .checked_add(2)
+            })
+            .map(Ipv4Addr::from)
+        else {
+            return false;
+        };
+        start <= ip && ip <= end && !self.pool.is_reserved(ip)
+    }
+
+    /// Ids whose addresses are in the pool, in ascending order.
+    fn client_ids(&self) -> impl Iterator<Item = usize> + '_ {
+        let (start, end) = match self.pool.client_range(&self.subnet) {
+            Ok((start, end)) => (u32::from(start), u32::from(end)),
+            Err(_) => (1, 0),
+        };
+        let network = u32::from(self.subnet.network());
+        (start..=end)
+            .filter(|&ip| !self.pool.is_reserved(Ipv4Addr::from(ip)))
+            .map(move |ip| (ip - network - 2) as usize)
+    }
+
     fn find_free_id(&self) -> Option<usize> {
-        (0..self.max_number_of_clients()).find(|&id| !self.clients.contains_key(&id))
+        self.client_ids().find(|id| !self.clients.contains_key(id))
     }
 
-    fn from_server_config(config: ServerConfig) -> anyhow::Result<Self> {
+    pub(crate) fn to_server_config(&self) -> ServerConfig {
+        ServerConfig {
+            subnet_cidr: self.subnet.to_string(),
+            endpoint: self.endpoint.clone(),
+            port: self.port,
+            network_interface: self.network_interface.clone(),
+            keys: self.keys.clone(),
+            pool: self.pool.clone(),
+            client_defaults: self.client_defaults.clone(),
+            clients: self.clients.clone(),
+        }
+    }
+
+    pub(crate) fn from_server_config(config: ServerConfig) -> anyhow::Result<Self> {
         Ok(Self {
             subnet: config.subnet_cidr.parse()?,
             endpoint: config.endpoint,
             port: config.port,
             network_interface: config.network_interface,
             keys: config.keys,
+            pool: config.pool,
+            client_defaults: config.client_defaults,
             clients: config.clients,
         })
     }
 }
+
+/// Names end up in exported configs, where a line break would start a new key or section.
+fn check_name(name: &str) -> anyhow::Result<()> {
+    if name.chars().any(char::is_control) {
+        return Err(anyhow!(
+            "client name {:?} contains control characters",
+            name
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::client::Routing;
+
+    #[test]
+    fn updates_client() {
+        let mut server = Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        let id = server.add_client("alcie".to_string(), Vec::new()).unwrap();
+        let keys = server.get_client(id).unwrap().keys;
+
+        let update = ClientUpdate {
+            name: Some("alice".to_string()),
+            dns: Some(vec!["1.1.1.1".parse().unwrap()]),
+            persistent_keepalive: Some(0),
+            ..Default::default()
+        };
+        let client = server.update_client(id, update).unwrap();
+        assert_eq!(client.name, "alice");
+        assert_eq!(client.keys.private, keys.private);
+        let config = client.get_wg_config(&server);
+        assert!(config.contains("DNS = 1.1.1.1\n"));
+        assert!(config.contains("AllowedIPs = 0.0.0.0/0\n"));
+        assert!(!config.contains("PersistentKeepalive"));
+
+        let update = ClientUpdate {
+            dns: Some(Vec::new()),
+            disabled: Some(true),
+            ..Default::default()
+        };
+        let client = server.update_client(id, update).unwrap();
+        assert_eq!(client.dns, None);
+        assert!(!server.get_wg_config().contains(&client.keys.public));
+
+        assert!(server.update_client(42, ClientUpdate::default()).is_err());
+
+        let update = ClientUpdate {
+            name: Some("alice\n[Peer]".to_string()),
+            ..Default::default()
+        };
+        assert!(server.update_client(id, update).is_err());
+        assert!(server
+            .add_client("bob\r\nid=eve".to_string(), Vec::new())
+            .is_err());
+    }
+
+    #[test]
+    fn uses_client_defaults() {
+        let mut server = Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        server.client_defaults = ClientDefaults {
+            dns: Some(vec!["10.0.0.1".parse().unwrap()]),
+            routing: Routing::Split,
+        };
+        let id = server.add_client("laptop".to_string(), Vec::new()).unwrap();
+        let config = server.get_client_wg_config(id).unwrap();
+        assert!(config.contains("DNS = 10.0.0.1\n"));
+        assert!(config.contains("AllowedIPs = 10.0.0.0/24\n"));
+
+        server
+            .resize_subnet("10.0.0.0/23".parse().unwrap())
+            .unwrap();
+        let config = server.get_client_wg_config(id).unwrap();
+        assert!(config.contains("AllowedIPs = 10.0.0.0/23\n"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wg");
+        server.dump_to_file(&path).unwrap();
+        assert_eq!(
+            Server::load_from_file(&path).unwrap().client_defaults,
+            server.client_defaults
+        );
+    }
+
+    #[test]
+    fn resizes_subnet() {
+        let mut server = Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        for name in ["a", "b", "c"] {
+            server.add_client(name.to_string(), Vec::new()).unwrap();
+        }
+        server.remove_client(0).unwrap();
+        server.clients.insert(200, server.clients[&2].clone());
+        server.clients.get_mut(&200).unwrap().id = 200;
+        server.clients.get_mut(&200).unwrap().keys = KeyPair::generate();
+        let keys = server.keys.clone();
+
+        // Growing the prefix keeps every address.
+        let renumbered = server
+            .resize_subnet("10.0.0.0/23".parse().unwrap())
+            .unwrap();
+        assert!(renumbered.is_empty());
+
+        // Shrinking moves the client which doesn't fit to the lowest free id.
+        let renumbered = server
+            .resize_subnet("10.0.0.0/28".parse().unwrap())
+            .unwrap();
+        assert_eq!(renumbered.len(), 1);
+        assert_eq!(renumbered[0].old_id, 200);
+        assert_eq!(renumbered[0].id, 0);
+        assert_eq!(
+            renumbered[0].address,
+            "10.0.0.2".parse::<Ipv4Addr>().unwrap()
+        );
+        assert_eq!(server.keys.private, keys.private);
+
+        // Moving to another network changes every address but keeps the ids.
+        let renumbered = server
+            .resize_subnet("172.16.0.0/28".parse().unwrap())
+            .unwrap();
+        assert_eq!(renumbered.len(), 3);
+        assert!(renumbered.iter().all(|client| client.id == client.old_id));
+
+        assert!(server
+            .resize_subnet("172.16.0.0/30".parse().unwrap())
+            .is_err());
+        assert!(server
+            .resize_subnet("172.16.0.0/31".parse().unwrap())
+            .is_err());
+        assert_eq!(server.subnet, "172.16.0.0/28".parse().unwrap());
+
+        // With split routing the allowed IPs follow the subnet, every client needs a new config.
+        server.client_defaults.routing = Routing::Split;
+        let renumbered = server
+            .resize_subnet("172.16.0.0/27".parse().unwrap())
+            .unwrap();
+        assert_eq!(renumbered.len(), 3);
+        assert!(renumbered
+            .iter()
+            .all(|client| client.address == client.old_address));
+    }
+
+    #[test]
+    fn honors_address_pool() {
+        let mut server = Server::new(
+            "10.0.0.0/31".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        assert_eq!(server.max_number_of_clients(), 0);
+        assert!(server.validate().is_err());
+        assert!(server.add_client("a".to_string(), Vec::new()).is_err());
+
+        server.subnet = "10.0.0.0/24".parse().unwrap();
+        assert_eq!(server.max_number_of_clients(), 253);
+        server.add_client("a".to_string(), Vec::new()).unwrap();
+
+        let pool = AddressPool {
+            start: Some("10.0.0.10".parse().unwrap()),
+            end: Some("10.0.0.13".parse().unwrap()),
+            reserved: vec!["10.0.0.11".parse().unwrap()],
+        };
+        // Client 0 has 10.0.0.2, which is outside of the pool.
+        assert!(server.set_pool(pool.clone()).is_err());
+        assert_eq!(server.pool, AddressPool::default());
+        server.remove_client(0).unwrap();
+        server.set_pool(pool).unwrap();
+
+        assert_eq!(server.max_number_of_clients(), 3);
+        let ids: Vec<usize> = (0..3)
+            .map(|_| server.add_client("b".to_string(), Vec::new()).unwrap())
+            .collect();
+        assert_eq!(ids, vec![8, 10, 11]);
+        assert!(server.add_client("c".to_string(), Vec::new()).is_err());
+
+        let reserved = ClientSpec {
+            name: "d".to_string(),
+            owner: None,
+            tags: Vec::new(),
+            ip: Some("10.0.0.11".parse().unwrap()),
+        };
+        server.remove_client(8).unwrap();
+        assert!(server.add_clients(&[reserved]).is_err());
+    }
+
+    #[test]
+    fn brackets_ipv6_endpoint() {
+        let mut server = Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "2001:db8::1".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        assert_eq!(server.endpoint_address(), "[2001:db8::1]:51820");
+        let id = server.add_client("alice".to_string(), Vec::new()).unwrap();
+        let config = server.get_client(id).unwrap().get_wg_config(&server);
+        assert!(config.contains("Endpoint = [2001:db8::1]:51820\n"));
+
+        server.endpoint = "vpn.example.com".to_string();
+        assert_eq!(server.endpoint_address(), "vpn.example.com:51820");
+    }
+}
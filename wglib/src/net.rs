@@ -0,0 +1,390 @@
+//! Detection and checks of the host network used when choosing the settings of a new server.
+
+use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+
+use anyhow::anyhow;
+use ipnet::Ipv4Net;
+use pnet_datalink::NetworkInterface;
+
+const ROUTE_TABLE: &str = "/proc/net/route";
+
+/// An IPv4 route of the kernel routing table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Route {
+    pub interface: String,
+    pub destination: Ipv4Net,
+    pub gateway: Option<Ipv4Addr>,
+    pub metric: u32,
+}
+
+impl Route {
+    pub fn is_default(&self) -> bool {
+        self.destination.prefix_len() == 0
+    }
+}
+
+/// Reads the IPv4 routes of the main routing table.
+pub fn read_routes() -> anyhow::Result<Vec<Route>> {
+    let contents = std::fs::read_to_string(ROUTE_TABLE)
+        .map_err(|err| anyhow!("failed to read {}: {}", ROUTE_TABLE, err))?;
+    Ok(parse_routes(&contents))
+}
+
+/// Parses the format of `/proc/net/route`, where addresses are hexadecimal in host byte order.
+/// Routes which aren't up are skipped.
+pub fn parse_routes(contents: &str) -> Vec<Route> {
+    const RTF_UP: u16 = 0x1;
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 {
+                return None;
+            }
+            let flags = u16::from_str_radix(fields[3], 16).ok()?;
+            if flags & RTF_UP == 0 {
+                return None;
+            }
+            let destination = parse_address(fields[1])?;
+            let gateway = parse_address(fields[2])?;
+            let mask = parse_address(fields[7])?;
+            let prefix_len = u32::from(mask).leading_ones() as u8;
+            Some(Route {
+                interface: fields[0].to_string(),
+                destination: Ipv4Net::new(destination, prefix_len).ok()?,
+                gateway: (!gateway.is_unspecified()).then_some(gateway),
+                metric: fields[6].parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// The kernel prints addresses as the raw `u32` in host byte order.
+fn parse_address(hex: &str) -> Option<Ipv4Addr> {
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(Ipv4Addr::from(value.to_ne_bytes()))
+}
+
+/// An IPv4 address of a local interface which is up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterfaceAddress {
+    pub interface: String,
+    pub address: Ipv4Net,
+}
+
+/// Interface carrying the traffic to the internet and the address clients should connect to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Uplink {
+    pub interface: String,
+    pub address: Ipv4Addr,
+}
+
+/// Lists the IPv4 addresses of interfaces which are up, leaving out loopback and interfaces
+/// without a hardware address such as tunnels. The interface of the default route in `routes`
+/// is kept, uplinks like ppp0 or venet0 have no hardware address either.
+pub fn interface_addresses(routes: &[Route]) -> Vec<InterfaceAddress> {
+    let uplink = default_route(routes).map(|route| route.interface.as_str());
+    pnet_datalink::interfaces()
+        .into_iter()
+        .filter(|e| is_candidate(e, uplink))
+        .flat_map(|e| {
+            let addresses: Vec<Ipv4Net> = e
+                .ips
+                .iter()
+                .filter_map(|net| match net.ip() {
+                    IpAddr::V4(ip) => Ipv4Net::new(ip, net.prefix()).ok(),
+                    IpAddr::V6(_) => None,
+                })
+                .collect();
+            addresses.into_iter().map(move |address| InterfaceAddress {
+                interface: e.name.clone(),
+                address,
+            })
+        })
+        .collect()
+}
+
+fn is_candidate(interface: &NetworkInterface, uplink: Option<&str>) -> bool {
+    interface.is_up()
+        && !interface.is_loopback()
+        && (interface.mac.is_some() || uplink == Some(interface.name.as_str()))
+}
+
+/// Detects the uplink of this host from its routing table and interface addresses.
+pub fn detect_uplink() -> anyhow::Result<Uplink> {
+    let routes = read_routes()?;
+    choose_uplink(&routes, &interface_addresses(&routes))
+        .ok_or_else(|| anyhow!("no interface with an IPv4 address is up"))
+}
+
+/// The default route with the lowest metric, which is the one the kernel uses.
+pub fn default_route(routes: &[Route]) -> Option<&Route> {
+    routes
+        .iter()
+        .filter(|route| route.is_default())
+        .min_by_key(|route| route.metric)
+}
+
+/// Picks the interface of the default route and its best address for the endpoint: a globally
+/// routable one, otherwise one in the subnet of the gateway. Without a default route the best
+/// address of any interface is taken.
+pub fn choose_uplink(routes: &[Route], addresses: &[InterfaceAddress]) -> Option<Uplink> {
+    let route = default_route(routes);
+    let candidates: Vec<&InterfaceAddress> = match route {
+        Some(route) => addresses
+            .iter()
+            .filter(|address| address.interface == route.interface)
+            .collect(),
+        None => addresses.iter().collect(),
+    };
+    let gateway = route.and_then(|route| route.gateway);
+    let best = candidates.iter().min_by_key(|candidate| {
+        let ip = candidate.address.addr();
+        (
+            !is_global(ip),
+            !gateway.is_some_and(|gateway| candidate.address.contains(&gateway)),
+        )
+    })?;
+    Some(Uplink {
+        interface: best.interface.clone(),
+        address: best.address.addr(),
+    })
+}
+
+/// Sorts addresses by how likely they're the endpoint: the uplink interface first, then globally
+/// routable addresses. Otherwise the order is kept.
+pub fn sort_candidates(routes: &[Route], addresses: &mut [InterfaceAddress]) {
+    let uplink = default_route(routes).map(|route| route.interface.as_str());
+    addresses.sort_by_key(|candidate| {
+        (
+            Some(candidate.interface.as_str()) != uplink,
+            !is_global(candidate.address.addr()),
+        )
+    });
+}
+
+/// Whether `ip` can be reached from the internet, unlike private, shared (CGNAT), loopback,
+/// link-local, documentation, benchmarking and reserved addresses.
+pub fn is_global(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || (a == 100 && (b & 0xc0) == 64)
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (b & 0xfe) == 18)
+        || a >= 240)
+}
+
+/// Returns the routes to networks overlapping `subnet`, which would conflict with the VPN.
+/// Default routes overlap everything and are left out.
+pub fn overlapping_routes<'a>(subnet: &Ipv4Net, routes: &'a [Route]) -> Vec<&'a Route> {
+    routes
+        .iter()
+        .filter(|route| {
+            !route.is_default()
+                && (route.destination.contains(&subnet.network())
+                    || subnet.contains(&route.destination.network()))
+        })
+        .collect()
+}
+
+/// Fails if the UDP `port` can't be bound, usually because another process listens on it.
+pub fn check_port_free(port: u16) -> anyhow::Result<()> {
+    UdpSocket::bind(("0.0.0.0", port))
+        .map(|_| ())
+        .map_err(|err| anyhow!("can't bind udp port {}: {}", port, err))
+}
+
+/// Accepts an IP address or a hostname, which clients use to reach the server.
+pub fn validate_endpoint(endpoint: &str) -> anyhow::Result<()> {
+    if endpoint.parse::<std::net::IpAddr>().is_ok() {
+        return Ok(());
+    }
+    let valid_label = |label: &str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    let hostname = endpoint.strip_suffix('.').unwrap_or(endpoint);
+    if hostname.len() > 253
+        || !hostname.split('.').all(valid_label)
+        || hostname
+            .split('.')
+            .all(|label| label.chars().all(|c| c.is_ascii_digit()))
+    {
+        return Err(anyhow!(
+            "{} is neither an IP address nor a hostname",
+            endpoint
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Addresses as printed on a little-endian host.
+    const ROUTES: &str = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+eth0\t00000000\t0102A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0
+eth0\t0002A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0
+docker0\t000011AC\t00000000\t0001\t0\t0\t0\t0000FFFF\t0\t0\t0
+tun0\t0000000A\t00000000\t0000\t0\t0\t0\t000000FF\t0\t0\t0
+";
+
+    #[test]
+    fn parses_routes() {
+        let routes = parse_routes(ROUTES);
+        assert_eq!(routes.len(), 3);
+        assert_eq!(
+            routes[0],
+            Route {
+                interface: "eth0".to_string(),
+                destination: "0.0.0.0/0".parse().unwrap(),
+                gateway: Some(Ipv4Addr::new(192, 168, 2, 1)),
+                metric: 100,
+            }
+        );
+        assert!(routes[0].is_default());
+        assert_eq!(routes[1].destination, "192.168.2.0/24".parse().unwrap());
+        assert_eq!(routes[1].gateway, None);
+        assert_eq!(routes[2].destination, "172.17.0.0/16".parse().unwrap());
+    }
+
+    #[test]
+    fn parses_addresses_in_host_byte_order() {
+        let address = Ipv4Addr::new(192, 168, 2, 1);
+        let hex = format!("{:08X}", u32::from_ne_bytes(address.octets()));
+        assert_eq!(parse_address(&hex), Some(address));
+    }
+
+    #[test]
+    fn keeps_uplink_without_hardware_address() {
+        let interface = |name: &str, mac: Option<pnet_datalink::MacAddr>| NetworkInterface {
+            name: name.to_string(),
+            description: String::new(),
+            index: 0,
+            mac,
+            ips: Vec::new(),
+            flags: 1, // IFF_UP
+        };
+        let mac = Some(pnet_datalink::MacAddr::new(2, 0, 0, 0, 0, 1));
+        assert!(is_candidate(&interface("eth0", mac), Some("ppp0")));
+        assert!(is_candidate(&interface("ppp0", None), Some("ppp0")));
+        assert!(!is_candidate(&interface("wg0", None), Some("ppp0")));
+        assert!(!is_candidate(&interface("tun0", None), None));
+    }
+
+    #[test]
+    fn finds_overlapping_routes() {
+        let routes = parse_routes(ROUTES);
+        let overlapping = |subnet: &str| -> Vec<String> {
+            overlapping_routes(&subnet.parse().unwrap(), &routes)
+                .iter()
+                .map(|route| route.interface.clone())
+                .collect()
+        };
+        assert!(overlapping("10.0.0.0/24").is_empty());
+        assert_eq!(overlapping("192.168.2.128/25"), vec!["eth0"]);
+        assert_eq!(overlapping("172.0.0.0/8"), vec!["docker0"]);
+    }
+
+    fn address(interface: &str, address: &str) -> InterfaceAddress {
+        InterfaceAddress {
+            interface: interface.to_string(),
+            address: address.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn chooses_uplink() {
+        let mut routes = parse_routes(ROUTES);
+        let addresses = [
+            address("docker0", "172.17.0.1/16"),
+            address("br0", "203.0.113.10/24"),
+            address("eth0", "10.1.0.5/8"),
+            address("eth0", "192.168.2.10/24"),
+        ];
+        // The docker bridge is listed first but the default route goes through eth0, whose
+        // address in the subnet of the gateway wins.
+        let uplink = choose_uplink(&routes, &addresses).unwrap();
+        assert_eq!(uplink.interface, "eth0");
+        assert_eq!(uplink.address, Ipv4Addr::new(192, 168, 2, 10));
+
+        let mut with_public = addresses.to_vec();
+        with_public.push(address("eth0", "198.51.99.7/24"));
+        let uplink = choose_uplink(&routes, &with_public).unwrap();
+        assert_eq!(uplink.address, Ipv4Addr::new(198, 51, 99, 7));
+
+        // A second default route with a lower metric is preferred.
+        routes.push(Route {
+            interface: "br0".to_string(),
+            destination: "0.0.0.0/0".parse().unwrap(),
+            gateway: Some(Ipv4Addr::new(203, 0, 113, 1)),
+            metric: 50,
+        });
+        assert_eq!(choose_uplink(&routes, &addresses).unwrap().interface, "br0");
+
+        // Without a default route a global address is preferred over private ones.
+        let mut with_public = addresses[..1].to_vec();
+        with_public.push(address("eth1", "198.51.99.7/24"));
+        let uplink = choose_uplink(&[], &with_public).unwrap();
+        assert_eq!(uplink.interface, "eth1");
+        assert_eq!(choose_uplink(&[], &[]), None);
+
+        let mut candidates = with_public.clone();
+        sort_candidates(&parse_routes(ROUTES), &mut candidates);
+        assert_eq!(candidates[0].interface, "eth1");
+    }
+
+    #[test]
+    fn detects_global_addresses() {
+        for ip in ["8.8.8.8", "198.51.99.7", "100.128.0.1"] {
+            assert!(is_global(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "10.0.0.1",
+            "172.17.0.1",
+            "192.168.1.1",
+            "100.64.0.1",
+            "127.0.0.1",
+            "169.254.1.1",
+            "192.0.2.2",
+            "198.18.0.1",
+            "240.0.0.1",
+            "0.0.0.0",
+        ] {
+            assert!(!is_global(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn validates_endpoints() {
+        for endpoint in [
+            "203.0.113.1",
+            "2001:db8::1",
+            "vpn.example.com",
+            "vpn-1.example.com.",
+        ] {
+            assert!(validate_endpoint(endpoint).is_ok(), "{}", endpoint);
+        }
+        for endpoint in [
+            "",
+            "vpn..example.com",
+            "-vpn.example.com",
+            "vpn example",
+            "1.2.3.400",
+        ] {
+            assert!(validate_endpoint(endpoint).is_err(), "{}", endpoint);
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Async variants of [`actions`] for servers running on tokio. The blocking work runs on the
+//! blocking thread pool, reads of the same config run concurrently and mutations wait for all
+//! of them. The lock is per process and per config path as given, other processes like `wgtool`
+//! aren't serialized with it.
+
+use std::{
+    collections::BTreeMap,
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use tokio::sync::RwLock;
+
+use crate::{
+    actions,
+    audit::{Actor, AuditEntry, AuditFilter},
+    backup::Manifest,
+    export::{ExportFormat, ExportedFile},
+    history::{Bucket, TrafficPoint, TrafficTotal},
+    import::ClientSpec,
+    model::client::ClientUpdate,
+    plan::{Operation, Plan},
+    sessions::{Session, SessionEvent},
+    status::DeviceStatus,
+    sync::{Drift, PeerDiff, RepairDirection},
+    Client,
+};
+
+static LOCKS: Mutex<BTreeMap<PathBuf, Arc<RwLock<()>>>> = Mutex::new(BTreeMap::new());
+
+fn lock_for(config_path: &Path) -> Arc<RwLock<()>> {
+    LOCKS
+        .lock()
+        .unwrap()
+        .entry(config_path.to_path_buf())
+        .or_default()
+        .clone()
+}
+
+/// Runs `f` on the blocking thread pool while holding a shared lock of `config_path`.
+pub async fn read<T, F>(config_path: &Path, f: F) -> anyhow::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Path) -> anyhow::Result<T> + Send + 'static,
+{
+    let lock = lock_for(config_path);
+    let _guard = lock.read().await;
+    blocking(config_path, f).await
+}
+
+/// Runs `f` on the blocking thread pool while holding an exclusive lock of `config_path`.
+pub async fn write<T, F>(config_path: &Path, f: F) -> anyhow::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Path) -> anyhow::Result<T> + Send + 'static,
+{
+    let lock = lock_for(config_path);
+    let _guard = lock.write().await;
+    blocking(config_path, f).await
+}
+
+async fn blocking<T, F>(config_path: &Path, f: F) -> anyhow::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Path) -> anyhow::Result<T> + Send + 'static,
+{
+    let config_path = config_path.to_path_buf();
+    tokio::task::spawn_blocking(move || f(&config_path))
+        .await
+        .map_err(|err| anyhow!("blocking task failed: {}", err))?
+}
+
+pub async fn up(device: &str, config_path: &Path, actor: &Actor) -> anyhow::Result<()> {
+    let (device, actor) = (device.to_string(), actor.clone());
+    write(config_path, move |config_path| {
+        actions::up(&device, config_path, &actor)
+    })
+    .await
+}
+
+pub async fn down(device: &str, config_path: &Path, actor: &Actor) -> anyhow::Result<()> {
+    let (device, actor) = (device.to_string(), actor.clone());
+    write(config_path, move |config_path| {
+        actions::down(&device, config_path, &actor)
+    })
+    .await
+}
+
+pub async fn reboot(device: &str, config_path: &Path, actor: &Actor) -> anyhow::Result<()> {
+    let (device, actor) = (device.to_string(), actor.clone());
+    write(config_path, move |config_path| {
+        actions::reboot(&device, config_path, &actor)
+    })
+    .await
+}
+
+pub async fn reload(device: &str, config_path: &Path, actor: &Actor) -> anyhow::Result<PeerDiff> {
+    let (device, actor) = (device.to_string(), actor.clone());
+    write(config_path, move |config_path| {
+        actions::reload(&device, config_path, &actor)
+    })
+    .await
+}
+
+pub async fn dry_run(
+    device: &str,
+    config_path: &Path,
+    operation: Operation,
+) -> anyhow::Result<Plan> {
+    let device = device.to_string();
+    read(config_path, move |config_path| {
+        actions::dry_run(&device, config_path, operation)
+    })
+    .await
+}
+
+pub async fn get_drift(device: &str, config_path: &Path) -> anyhow::Result<Drift> {
+    let device = device.to_string();
+    read(config_path, move |config_path| {
+        actions::get_drift(&device, config_path)
+    })
+    .await
+}
+
+pub async fn repair_drift(
+    device: &str,
+    config_path: &Path,
+    direction: RepairDirection,
+    actor: &Actor,
+) -> anyhow::Result<Drift> {
+    let (device, actor) = (device.to_string(), actor.clone());
+    write(config_path, move |config_path| {
+        actions::repair_drift(&device, config_path, direction, &actor)
+    })
+    .await
+}
+
+pub async fn add_client(
+    device: &str,
+    config_path: &Path,
+    name: String,
+    tags: Vec<String>,
+    actor: &Actor,
+) -> anyhow::Result<usize> {
+    let (device, actor) = (device.to_string(), actor.clone());
+    write(config_path, move |config_path| {
+        actions::add_client(&device, config_path, name, tags, &actor)
+    })
+    .await
+}
+
+pub async fn remove_client(
+    device: &str,
+    config_path: &Path,
+    id: usize,
+    actor: &Actor,
+) -> anyhow::Result<()> {
+    let (device, actor) = (device.to_string(), actor.clone());
+    write(config_path, move |config_path| {
+        actions::remove_client(&device, config_path, id, &actor)
+    })
+    .await
+}
+
+pub async fn import_clients(
+    device: &str,
+    config_path: &Path,
+    specs: Vec<ClientSpec>,
+    actor: &Actor,
+) -> anyhow::Result<Vec<Client>> {
+    let (device, actor) = (device.to_string(), actor.clone());
+    write(config_path, move |config_path| {
+        actions::import_clients(&device, config_path, &specs, &actor)
+    })
+    .await
+}
+
+/// Returns the zip archive written by [`actions::export_configs_zip`].
+pub async fn export_configs_zip(config_path: &Path, ids: Vec<usize>) -> anyhow::Result<Vec<u8>> {
+    read(config_path, move |config_path| {
+        let mut archive = Cursor::new(Vec::new());
+        actions::export_configs_zip(config_path, &ids, &mut archive)?;
+        Ok(archive.into_inner())
+    })
+    .await
+}
+
+pub async fn edit_client(
+    device: &str,
+    config_path: &Path,
+    id: usize,
+    update: ClientUpdate,
+    actor: &Actor,
+) -> anyhow::Result<Client> {
+    let (device, actor) = (device.to_string(), actor.clone());
+    write(config_path, move |config_path| {
+        actions::edit_client(&device, config_path, id, update, &actor)
+    })
+    .await
+}
+
+/// Returns the manifest and the archive written by [`actions::backup`].
+pub async fn backup(
+    config_path: &Path,
+    history_path: &Path,
+) -> anyhow::Result<(Manifest, Vec<u8>)> {
+    let history_path = history_path.to_path_buf();
+    read(config_path, move |config_path| {
+        let mut archive = Vec::new();
+        let manifest = actions::backup(config_path, &history_path, &mut archive)?;
+        Ok((manifest, archive))
+    })
+    .await
+}
+
+pub async fn get_audit_log(
+    config_path: &Path,
+    filter: AuditFilter,
+) -> anyhow::Result<Vec<AuditEntry>> {
+    read(config_path, move |config_path| {
+        actions::get_audit_log(config_path, &filter)
+    })
+    .await
+}
+
+pub async fn get_status(device: &str, config_path: &Path) -> anyhow::Result<DeviceStatus> {
+    let device = device.to_string();
+    read(config_path, move |config_path| {
+        actions::get_status(&device, config_path)
+    })
+    .await
+}
+
+pub async fn list_clients(config_path: &Path) -> anyhow::Result<Vec<Client>> {
+    read(config_path, actions::list_clients).await
+}
+
+pub async fn get_client_wg_config(config_path: &Path, id: usize) -> anyhow::Result<String> {
+    read(config_path, move |config_path| {
+        actions::get_client_wg_config(config_path, id)
+    })
+    .await
+}
+
+pub async fn export_client_config(
+    config_path: &Path,
+    id: usize,
+    format: ExportFormat,
+) -> anyhow::Result<Vec<ExportedFile>> {
+    read(config_path, move |config_path| {
+        actions::export_client_config(config_path, id, format)
+    })
+    .await
+}
+
+pub async fn collect_history(
+    device: &str,
+    config_path: &Path,
+    history_path: &Path,
+) -> anyhow::Result<Vec<SessionEvent>> {
+    let (device, history_path) = (device.to_string(), history_path.to_path_buf());
+    read(config_path, move |config_path| {
+        actions::collect_history(&device, config_path, &history_path)
+    })
+    .await
+}
+
+pub async fn take_session_events(
+    config_path: &Path,
+    history_path: &Path,
+    consumer: &str,
+) -> anyhow::Result<Vec<SessionEvent>> {
+    let (history_path, consumer) = (history_path.to_path_buf(), consumer.to_string());
+    read(config_path, move |config_path| {
+        actions::take_session_events(config_path, &history_path, &consumer)
+    })
+    .await
+}
+
+/// History reads map public keys to client ids with the config, so they read it under the
+/// lock like other reads.
+pub async fn get_traffic_history(
+    config_path: &Path,
+    history_path: &Path,
+    client_id: Option<usize>,
+    from: u64,
+    to: u64,
+    bucket: Bucket,
+) -> anyhow::Result<Vec<TrafficPoint>> {
+    let history_path = history_path.to_path_buf();
+    read(config_path, move |config_path| {
+        actions::get_traffic_history(config_path, &history_path, client_id, from, to, bucket)
+    })
+    .await
+}
+
+pub async fn get_sessions(
+    config_path: &Path,
+    history_path: &Path,
+    client_id: usize,
+    limit: usize,
+) -> anyhow::Result<Vec<Session>> {
+    let history_path = history_path.to_path_buf();
+    read(config_path, move |config_path| {
+        actions::get_sessions(config_path, &history_path, client_id, limit)
+    })
+    .await
+}
+
+pub async fn get_traffic_totals(
+    config_path: &Path,
+    history_path: &Path,
+) -> anyhow::Result<Vec<TrafficTotal>> {
+    let history_path = history_path.to_path_buf();
+    read(config_path, move |config_path| {
+        actions::get_traffic_totals(config_path, &history_path)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::Server;
+
+    #[tokio::test]
+    async fn serializes_writes_with_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("wg");
+        let mut server = Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        server.add_client("laptop".to_string(), Vec::new()).unwrap();
+        server.dump_to_file(&config_path).unwrap();
+
+        let lock = lock_for(&config_path);
+        let guard = lock.read().await;
+        // Reads don't wait for each other.
+        assert_eq!(list_clients(&config_path).await.unwrap().len(), 1);
+
+        let writer = tokio::spawn({
+            let config_path = config_path.clone();
+            async move {
+                write(&config_path, |config_path| {
+                    let mut server = Server::load_from_file(config_path)?;
+                    server.add_client("phone".to_string(), Vec::new())?;
+                    server.dump_to_file(config_path)
+                })
+                .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!writer.is_finished());
+
+        drop(guard);
+        writer.await.unwrap().unwrap();
+        assert_eq!(list_clients(&config_path).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::anyhow;
+use ipnet::Ipv4Net;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use similar::TextDiff;
+use sysctl::Sysctl;
+
+use crate::{
+    actions,
+    import::ClientSpec,
+    model::{client::ClientUpdate, pool::AddressPool, server::ServerConfig},
+    service::ServiceOptions,
+    storage::StorageKind,
+    sync::RepairDirection,
+    Server,
+};
+
+/// A state-changing action which can be previewed with [`actions::dry_run`].
+#[derive(Clone)]
+pub enum Operation {
+    Up,
+    Down,
+    Reboot,
+    Reload,
+    RepairDrift(RepairDirection),
+    AddClient {
+        name: String,
+        tags: Vec<String>,
+    },
+    RemoveClient(usize),
+    EditClient(usize, ClientUpdate),
+    ImportClients(Vec<ClientSpec>),
+    ResizeSubnet(Ipv4Net),
+    SetAddressPool(AddressPool),
+    /// Restores the backup `archive`, see [`actions::restore`].
+    Restore {
+        archive: Vec<u8>,
+        history_path: PathBuf,
+        regenerate: bool,
+        restart: bool,
+    },
+    ConvertStorage {
+        output: PathBuf,
+        kind: StorageKind,
+    },
+    InstallService(ServiceOptions),
+    UninstallService,
+}
+
+/// What an operation would change. Diffs are in unified format and empty if the file stays the
+/// same, private keys are redacted from both of them.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Plan {
+    pub config_diff: String,
+    pub wg_conf_diff: String,
+    /// Commands in the order they would run.
+    pub commands: Vec<String>,
+    /// Other files which would be written or removed, in order.
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+
+/// Performs the side effects of actions or, in dry-run mode, only records them.
+pub(crate) struct Executor {
+    dry_run: bool,
+    config: Option<ServerConfig>,
+    wg_conf: Option<String>,
+    commands: Vec<String>,
+    files: Vec<String>,
+}
+
+impl Executor {
+    pub(crate) fn new(dry_run: bool) -> Self {
+        Self {
+            dry_run,
+            config: None,
+            wg_conf: None,
+            commands: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Runs `save` which stores `server`, or remembers `server` as the new config.
+    pub(crate) fn persist<T>(
+        &mut self,
+        server: &Server,
+        save: impl FnOnce() -> anyhow::Result<T>,
+    ) -> anyhow::Result<()> {
+        if self.dry_run {
+            self.config = Some(server.to_server_config());
+        } else {
+            save()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_wg_config(&mut self, server: &Server, device: &str) -> anyhow::Result<()> {
+        if self.dry_run {
+            self.wg_conf = Some(server.get_wg_config());
+            Ok(())
+        } else {
+            actions::dump_wg_config(server, device)
+        }
+    }
+
+    /// Runs `change` of a file other than the config and the wireguard config, or remembers
+    /// its `description`.
+    pub(crate) fn change_file(
+        &mut self,
+        description: String,
+        change: impl FnOnce() -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        if self.dry_run {
+            self.files.push(description);
+            Ok(())
+        } else {
+            change()
+        }
+    }
+
+    /// Runs `cmd`, writing `input` to its stdin if given.
+    pub(crate) fn run(&mut self, mut cmd: Command, input: Option<&[u8]>) -> anyhow::Result<()> {
+        if self.dry_run {
+            self.commands.push(format_command(&cmd, input.is_some()));
+            return Ok(());
+        }
+
+        let program = cmd.get_program().to_string_lossy().into_owned();
+        let mut child = cmd
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+            stdin.write_all(input)?;
+        }
+        let output = child.wait_with_output()?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match (output.status.success(), stderr.trim()) {
+            (true, _) => Ok(()),
+            (false, "") => Err(anyhow!(
+                "{} failed with exit code: {}",
+                program,
+                output.status
+            )),
+            (false, stderr) => Err(anyhow!(
+                "{} failed with exit code: {}: {}",
+                program,
+                output.status,
+                stderr
+            )),
+        }
+    }
+
+    pub(crate) fn set_sysctl(&mut self, name: &str, value: &str) -> anyhow::Result<()> {
+        if self.dry_run {
+            self.commands.push(format!("sysctl -w {}={}", name, value));
+        } else {
+            let ctl = sysctl::Ctl::new(name)?;
+            ctl.set_value(sysctl::CtlValue::String(value.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Compares what was recorded with `before`, `None` if there's no config yet, and the
+    /// current wireguard config of `device`.
+    pub(crate) fn into_plan(
+        self,
+        before: Option<&Server>,
+        config_path: &Path,
+        device: &str,
+    ) -> anyhow::Result<Plan> {
+        let config_diff = match &self.config {
+            Some(after) => unified_diff(
+                &match before {
+                    Some(before) => config_text(&before.to_server_config())?,
+                    None => String::new(),
+                },
+                &config_text(after)?,
+                &config_path.display().to_string(),
+            ),
+            None => String::new(),
+        };
+        let wg_conf_diff = match &self.wg_conf {
+            Some(after) => {
+                let path = actions::wg_config_path(device);
+                let current = match std::fs::read_to_string(&path) {
+                    Ok(current) => current,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+                    Err(err) => return Err(anyhow!("failed to read {}: {}", path.display(), err)),
+                };
+                unified_diff(
+                    &redact_wg_private_key(&current),
+                    &redact_wg_private_key(after),
+                    &path.display().to_string(),
+                )
+            }
+            None => String::new(),
+        };
+        Ok(Plan {
+            config_diff,
+            wg_conf_diff,
+            commands: self.commands,
+            files: self.files,
+        })
+    }
+}
+
+fn format_command(cmd: &Command, stdin: bool) -> String {
+    let mut line = PathBuf::from(cmd.get_program()).display().to_string();
+    for arg in cmd.get_args() {
+        line.push(' ');
+        line.push_str(&arg.to_string_lossy());
+    }
+    if stdin {
+        line.push_str(" < (stdin)");
+    }
+    line
+}
+
+/// Pretty JSON of the stored config with sorted keys, so unchanged clients don't show up.
+fn config_text(config: &ServerConfig) -> anyhow::Result<String> {
+    let mut value = serde_json::to_value(config)?;
+    redact_private_keys(&mut value);
+    Ok(serde_json::to_string_pretty(&value)? + "\n")
+}
+
+fn redact_private_keys(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if key == "private" && value.is_string() {
+                    *value = Value::String("<redacted>".to_string());
+                } else {
+                    redact_private_keys(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_private_keys),
+        _ => {}
+    }
+}
+
+fn redact_wg_private_key(conf: &str) -> String {
+    conf.lines()
+        .map(|line| {
+            if line.starts_with("PrivateKey") {
+                "PrivateKey = <redacted>\n".to_string()
+            } else {
+                format!("{}\n", line)
+            }
+        })
+        .collect()
+}
+
+fn unified_diff(old: &str, new: &str, name: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(name, name)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> Server {
+        Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        )
+    }
+
+    #[test]
+    fn records_instead_of_running() {
+        let before = server();
+        let mut after = Server::from_server_config(before.to_server_config()).unwrap();
+        after.add_client("laptop".to_string(), Vec::new()).unwrap();
+
+        let mut executor = Executor::new(true);
+        executor
+            .persist(&after, || -> anyhow::Result<()> {
+                panic!("dry run must not save")
+            })
+            .unwrap();
+        let mut cmd = Command::new("wg");
+        cmd.args(["set", "wg-test", "peer", "key", "remove"]);
+        executor.run(cmd, None).unwrap();
+        executor.set_sysctl("net.ipv4.ip_forward", "1").unwrap();
+        executor.write_wg_config(&after, "wg-test").unwrap();
+        executor
+            .change_file("write /tmp/wg.env".to_string(), || {
+                panic!("dry run must not write files")
+            })
+            .unwrap();
+
+        let plan = executor
+            .into_plan(Some(&before), Path::new("/tmp/wg"), "wg-test")
+            .unwrap();
+        assert_eq!(plan.files, vec!["write /tmp/wg.env"]);
+        assert_eq!(
+            plan.commands,
+            vec![
+                "wg set wg-test peer key remove",
+                "sysctl -w net.ipv4.ip_forward=1"
+            ]
+        );
+        assert!(plan.wg_conf_diff.contains("+PrivateKey = <redacted>\n"));
+        assert!(!plan.wg_conf_diff.contains(&after.keys.private));
+        assert!(plan.config_diff.starts_with("--- /tmp/wg\n+++ /tmp/wg\n"));
+        assert!(plan.config_diff.contains("+      \"name\": \"laptop\","));
+        assert!(!plan.config_diff.contains(&after.keys.private));
+        assert!(!plan
+            .config_diff
+            .contains(&after.get_client(0).unwrap().keys.private));
+    }
+
+    #[test]
+    fn empty_diff_for_same_text() {
+        assert_eq!(unified_diff("a\n", "a\n", "f"), "");
+        assert_eq!(
+            unified_diff("a\nb\n", "a\nc\n", "f"),
+            "--- f\n+++ f\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n"
+        );
+    }
+}
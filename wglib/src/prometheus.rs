@@ -0,0 +1,219 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::{
+    audit::ActionCounter,
+    metrics::{self, DeviceStats},
+    Client, Server,
+};
+
+/// Renders server state in the Prometheus text exposition format.
+///
+/// `stats` is `None` when the interface is down.
+pub fn render(
+    device: &str,
+    server: &Server,
+    stats: Option<&DeviceStats>,
+    counters: &BTreeMap<String, ActionCounter>,
+) -> anyhow::Result<String> {
+    let mut out = String::new();
+    let device_label = format!("device=\"{}\"", escape(device));
+
+    header(
+        &mut out,
+        "wg_interface_up",
+        "gauge",
+        "Whether the interface is up.",
+    );
+    writeln!(
+        out,
+        "wg_interface_up{{{}}} {}",
+        device_label,
+        stats.is_some() as u8
+    )?;
+
+    header(
+        &mut out,
+        "wg_clients",
+        "gauge",
+        "Number of configured clients.",
+    );
+    writeln!(
+        out,
+        "wg_clients{{{}}} {}",
+        device_label,
+        server.clients.len()
+    )?;
+
+    header(
+        &mut out,
+        "wg_clients_capacity",
+        "gauge",
+        "Maximum number of clients the subnet can hold.",
+    );
+    writeln!(
+        out,
+        "wg_clients_capacity{{{}}} {}",
+        device_label,
+        server.max_number_of_clients()
+    )?;
+
+    if let Some(stats) = stats {
+        let peers: Vec<(Client, &metrics::PeerStats)> = stats
+            .peers
+            .iter()
+            .filter_map(|peer| {
+                server
+                    .find_client_by_public_key(&peer.public_key)
+                    .map(|client| (client, peer))
+            })
+            .collect();
+
+        header(
+            &mut out,
+            "wg_client_received_bytes_total",
+            "counter",
+            "Bytes received from the client since the interface was started.",
+        );
+        for (client, peer) in &peers {
+            writeln!(
+                out,
+                "wg_client_received_bytes_total{{{}}} {}",
+                client_labels(&device_label, client),
+                peer.received_bytes
+            )?;
+        }
+
+        header(
+            &mut out,
+            "wg_client_sent_bytes_total",
+            "counter",
+            "Bytes sent to the client since the interface was started.",
+        );
+        for (client, peer) in &peers {
+            writeln!(
+                out,
+                "wg_client_sent_bytes_total{{{}}} {}",
+                client_labels(&device_label, client),
+                peer.sent_bytes
+            )?;
+        }
+
+        header(
+            &mut out,
+            "wg_client_last_handshake_seconds",
+            "gauge",
+            "Seconds since the latest handshake, absent if the client never connected.",
+        );
+        for (client, peer) in &peers {
+            if let Some(timestamp) = peer.latest_handshake {
+                writeln!(
+                    out,
+                    "wg_client_last_handshake_seconds{{{}}} {}",
+                    client_labels(&device_label, client),
+                    metrics::get_seconds_from_now(timestamp)?
+                )?;
+            }
+        }
+    }
+
+    header(
+        &mut out,
+        "wg_admin_actions_total",
+        "counter",
+        "Administrative actions recorded in the audit log.",
+    );
+    for (action, counter) in counters {
+        writeln!(
+            out,
+            "wg_admin_actions_total{{action=\"{}\",outcome=\"success\"}} {}",
+            escape(action),
+            counter.succeeded
+        )?;
+        writeln!(
+            out,
+            "wg_admin_actions_total{{action=\"{}\",outcome=\"failure\"}} {}",
+            escape(action),
+            counter.failed
+        )?;
+    }
+
+    Ok(out)
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    out.push_str(&format!(
+        "# HELP {} {}\n# TYPE {} {}\n",
+        name, help, name, kind
+    ));
+}
+
+fn client_labels(device_label: &str, client: &Client) -> String {
+    format!(
+        "{},id=\"{}\",name=\"{}\",tags=\"{}\"",
+        device_label,
+        client.id,
+        escape(&client.name),
+        escape(&client.tags.join(","))
+    )
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{InterfaceStats, PeerStats};
+
+    #[test]
+    fn renders_client_series() {
+        let mut server = Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        let id = server
+            .add_client("al\"ice".to_string(), vec!["staff".to_string()])
+            .unwrap();
+        let stats = DeviceStats {
+            interface: InterfaceStats {
+                public_key: server.keys.public.clone(),
+                listen_port: 51820,
+                fwmark: None,
+            },
+            peers: vec![PeerStats {
+                public_key: server.get_client(id).unwrap().keys.public,
+                endpoint: None,
+                allowed_ips: Vec::new(),
+                latest_handshake: None,
+                received_bytes: 42,
+                sent_bytes: 7,
+                persistent_keepalive: None,
+            }],
+        };
+        let mut counters = BTreeMap::new();
+        counters.insert(
+            "add_client".to_string(),
+            ActionCounter {
+                succeeded: 1,
+                failed: 0,
+            },
+        );
+
+        let text = render("wg0", &server, Some(&stats), &counters).unwrap();
+        assert!(text.contains("wg_interface_up{device=\"wg0\"} 1\n"));
+        assert!(text.contains("wg_clients_capacity{device=\"wg0\"} 253\n"));
+        assert!(text.contains(
+            "wg_client_received_bytes_total{device=\"wg0\",id=\"0\",name=\"al\\\"ice\",tags=\"staff\"} 42\n"
+        ));
+        assert!(!text.contains("wg_client_last_handshake_seconds{"));
+        assert!(
+            text.contains("wg_admin_actions_total{action=\"add_client\",outcome=\"success\"} 1\n")
+        );
+    }
+}
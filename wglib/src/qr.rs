@@ -0,0 +1,115 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use qrcode::{
+    render::{svg, unicode},
+    Color, QrCode,
+};
+use serde::Deserialize;
+
+/// Width of the light border around the code, in modules, as required by the QR spec.
+const QUIET_ZONE: usize = 4;
+
+/// Size of a QR code module in pixels for images sent to clients.
+pub const QR_SCALE: u32 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Png,
+    Svg,
+}
+
+impl FromStr for ImageFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "png" => Ok(ImageFormat::Png),
+            "svg" => Ok(ImageFormat::Svg),
+            _ => Err(anyhow!("unknown image format {}, expected png or svg", s)),
+        }
+    }
+}
+
+impl ImageFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Svg => "image/svg+xml",
+        }
+    }
+}
+
+/// Renders `data` as UTF-8 half blocks, two modules per character, for printing to a terminal.
+pub fn render_text(data: &str) -> anyhow::Result<String> {
+    let code = QrCode::new(data)?;
+    Ok(code
+        .render::<unicode::Dense1x2>()
+        // Colours are swapped so the code scans on terminals with a dark background.
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build())
+}
+
+/// Renders `data` as an image where every module is `scale` pixels wide.
+pub fn render_image(data: &str, format: ImageFormat, scale: u32) -> anyhow::Result<Vec<u8>> {
+    let code = QrCode::new(data)?;
+    match format {
+        ImageFormat::Png => render_png(&code, scale),
+        ImageFormat::Svg => Ok(code
+            .render::<svg::Color>()
+            .module_dimensions(scale, scale)
+            .build()
+            .into_bytes()),
+    }
+}
+
+fn render_png(code: &QrCode, scale: u32) -> anyhow::Result<Vec<u8>> {
+    let scale = scale.max(1) as usize;
+    let modules = code.width();
+    let colors = code.to_colors();
+    let size = (modules + 2 * QUIET_ZONE) * scale;
+
+    let mut pixels = vec![u8::MAX; size * size];
+    for (i, color) in colors.iter().enumerate() {
+        if *color != Color::Dark {
+            continue;
+        }
+        let x = (i % modules + QUIET_ZONE) * scale;
+        let y = (i / modules + QUIET_ZONE) * scale;
+        for row in y..y + scale {
+            pixels[row * size + x..row * size + x + scale].fill(0);
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, size as u32, size as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_png() {
+        let data = render_image("[Interface]", ImageFormat::Png, 2).unwrap();
+        let decoder = png::Decoder::new(data.as_slice());
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        // Version 1 code has 21 modules plus the quiet zone on both sides.
+        assert_eq!(info.width, (21 + 2 * 4) * 2);
+        assert_eq!(info.height, info.width);
+    }
+
+    #[test]
+    fn renders_text_and_svg() {
+        assert!(render_text("[Interface]").unwrap().contains('▀'));
+        let svg = render_image("[Interface]", ImageFormat::Svg, 8).unwrap();
+        assert!(String::from_utf8(svg).unwrap().contains("<svg"));
+    }
+}
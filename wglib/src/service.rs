@@ -0,0 +1,384 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::anyhow;
+
+use crate::{plan::Executor, Server};
+
+pub const SYSCTL_DROP_IN: &str = "/etc/sysctl.d/99-wgsuite.conf";
+const UNIT_DIR: &str = "/etc/systemd/system";
+const ENV_DIR: &str = "/etc/wgsuite";
+const WG_DIR: &str = "/etc/wireguard";
+
+/// Services managed by [`install`] and [`uninstall`].
+const SERVICES: [&str; 2] = ["wghttp", "wgbot"];
+
+/// What to install besides the sysctl drop-in and the `wg-quick@<device>` unit.
+#[derive(Clone)]
+pub struct ServiceOptions {
+    pub device: String,
+    pub config_path: PathBuf,
+    pub http: Option<HttpService>,
+    pub bot: Option<BotService>,
+}
+
+#[derive(Clone)]
+pub struct HttpService {
+    pub binary: PathBuf,
+    /// Bearer tokens in `name:secret` format.
+    pub tokens: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct BotService {
+    pub binary: PathBuf,
+    pub token: String,
+    pub admin_id: i64,
+}
+
+/// Looks for `name` next to the running executable, then in `PATH`.
+pub fn find_binary(name: &str) -> Option<PathBuf> {
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf));
+    let path_dirs = std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect::<Vec<_>>())
+        .unwrap_or_default();
+    exe_dir
+        .into_iter()
+        .chain(path_dirs)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Makes ip forwarding persistent and starts the device, and the enabled services, on boot.
+/// Returns the written files.
+pub(crate) fn install(
+    options: &ServiceOptions,
+    executor: &mut Executor,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let config_path = options.config_path.canonicalize().map_err(|err| {
+        anyhow!(
+            "failed to resolve {}: {}",
+            options.config_path.display(),
+            err
+        )
+    })?;
+    // wg-quick@ reads the config from /etc/wireguard, which only exists once the device was started.
+    let server = Server::load_from_file(&config_path)?;
+    executor.write_wg_config(&server, &options.device)?;
+
+    let mut written = vec![PathBuf::from(SYSCTL_DROP_IN)];
+    write_file(
+        executor,
+        Path::new(SYSCTL_DROP_IN),
+        &sysctl_drop_in(),
+        0o644,
+    )?;
+    let mut cmd = Command::new("sysctl");
+    cmd.args(["-p", SYSCTL_DROP_IN]);
+    executor.run(cmd, None)?;
+
+    let mut services = Vec::new();
+    if let Some(http) = &options.http {
+        validate_tokens(&http.tokens)?;
+        let env = format!("WGHTTP_TOKENS={}\n", env_quote(&token_args(&http.tokens)));
+        let unit = http_unit(&options.device, &config_path, &http.binary);
+        services.push(("wghttp", env, unit));
+    }
+    if let Some(bot) = &options.bot {
+        validate_tokens(std::slice::from_ref(&bot.token))?;
+        let env = format!("WGBOT_TOKEN={}\n", env_quote(&bot.token));
+        let unit = bot_unit(&options.device, &config_path, &bot.binary, bot.admin_id);
+        services.push(("wgbot", env, unit));
+    }
+    if !services.is_empty() && !Path::new(ENV_DIR).exists() {
+        executor.change_file(format!("create {}", ENV_DIR), || {
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(ENV_DIR)?;
+            Ok(())
+        })?;
+    }
+    for (name, env, unit) in &services {
+        // Units are world-readable, so secrets only go to the environment file.
+        write_file(executor, &env_path(name), env, 0o600)?;
+        write_file(executor, &unit_path(name), unit, 0o644)?;
+        written.extend([env_path(name), unit_path(name)]);
+    }
+
+    systemctl(executor, &["daemon-reload"])?;
+    systemctl(
+        executor,
+        &["enable", &format!("wg-quick@{}.service", options.device)],
+    )?;
+    for (name, _, _) in &services {
+        let unit = format!("{}.service", name);
+        systemctl(executor, &["enable", &unit])?;
+        // Restart instead of start so a reinstall applies changed options.
+        systemctl(executor, &["restart", &unit])?;
+    }
+    Ok(written)
+}
+
+/// Removes everything [`install`] added. The device and ip forwarding stay as they are until
+/// the next reboot. Returns the removed files.
+pub(crate) fn uninstall(device: &str, executor: &mut Executor) -> anyhow::Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    for name in SERVICES {
+        let unit = unit_path(name);
+        if unit.exists() {
+            systemctl(
+                executor,
+                &["disable", "--now", &format!("{}.service", name)],
+            )?;
+        }
+        for path in [unit, env_path(name)] {
+            if remove_file(executor, &path)? {
+                removed.push(path);
+            }
+        }
+    }
+    if Path::new(ENV_DIR).exists() {
+        executor.change_file(format!("remove {} if empty", ENV_DIR), || {
+            let _ = std::fs::remove_dir(ENV_DIR);
+            Ok(())
+        })?;
+    }
+
+    systemctl(
+        executor,
+        &["disable", &format!("wg-quick@{}.service", device)],
+    )?;
+    if remove_file(executor, Path::new(SYSCTL_DROP_IN))? {
+        removed.push(SYSCTL_DROP_IN.into());
+    }
+    systemctl(executor, &["daemon-reload"])?;
+    Ok(removed)
+}
+
+pub fn sysctl_drop_in() -> String {
+    "# Written by wgtool install-service, clients need it to reach anything behind the server.\n\
+     net.ipv4.ip_forward = 1\n"
+        .to_string()
+}
+
+pub fn http_unit(device: &str, config_path: &Path, binary: &Path) -> String {
+    let exec_start = format!(
+        "{} --device {} --config-path {} $WGHTTP_TOKENS",
+        quote(&binary.display().to_string()),
+        quote(device),
+        quote(&config_path.display().to_string())
+    );
+    unit(
+        &format!("WireGuard HTTP API for {}", device),
+        device,
+        config_path,
+        "wghttp",
+        &exec_start,
+    )
+}
+
+pub fn bot_unit(device: &str, config_path: &Path, binary: &Path, admin_id: i64) -> String {
+    let exec_start = format!(
+        "{} --token ${{WGBOT_TOKEN}} --device {} --config-path {} --admin-id {}",
+        quote(&binary.display().to_string()),
+        quote(device),
+        quote(&config_path.display().to_string()),
+        admin_id
+    );
+    unit(
+        &format!("WireGuard Telegram bot for {}", device),
+        device,
+        config_path,
+        "wgbot",
+        &exec_start,
+    )
+}
+
+/// Both services run as root since they call wg-quick and iptables, so the unit takes away
+/// everything they don't need. The kernel tunables stay writable for ip forwarding, and
+/// /run for the iptables lock.
+fn unit(
+    description: &str,
+    device: &str,
+    config_path: &Path,
+    name: &str,
+    exec_start: &str,
+) -> String {
+    // The history, audit log and rewritten config live next to the config.
+    let config_dir = config_path.parent().unwrap_or(Path::new("/"));
+    format!(
+        "\
+[Unit]
+Description={description}
+Wants=network-online.target
+After=network-online.target wg-quick@{device}.service
+
+[Service]
+Type=simple
+EnvironmentFile={env}
+ExecStart={exec_start}
+Restart=on-failure
+RestartSec=5
+UMask=0077
+NoNewPrivileges=yes
+ProtectSystem=strict
+ProtectHome=read-only
+ReadWritePaths={wg_dir} /run {config_dir}
+PrivateTmp=yes
+ProtectClock=yes
+ProtectHostname=yes
+ProtectKernelLogs=yes
+ProtectControlGroups=yes
+RestrictNamespaces=yes
+RestrictRealtime=yes
+RestrictSUIDSGID=yes
+LockPersonality=yes
+MemoryDenyWriteExecute=yes
+SystemCallArchitectures=native
+RestrictAddressFamilies=AF_UNIX AF_INET AF_INET6 AF_NETLINK
+CapabilityBoundingSet=CAP_NET_ADMIN CAP_NET_RAW CAP_SYS_MODULE CAP_DAC_OVERRIDE
+
+[Install]
+WantedBy=multi-user.target
+",
+        env = env_path(name).display(),
+        wg_dir = WG_DIR,
+        config_dir = quote(&config_dir.display().to_string()),
+    )
+}
+
+fn unit_path(name: &str) -> PathBuf {
+    Path::new(UNIT_DIR).join(format!("{}.service", name))
+}
+
+fn env_path(name: &str) -> PathBuf {
+    Path::new(ENV_DIR).join(format!("{}.env", name))
+}
+
+fn systemctl(executor: &mut Executor, args: &[&str]) -> anyhow::Result<()> {
+    let mut cmd = Command::new("systemctl");
+    cmd.args(args);
+    executor.run(cmd, None)
+}
+
+fn write_file(
+    executor: &mut Executor,
+    path: &Path,
+    contents: &str,
+    mode: u32,
+) -> anyhow::Result<()> {
+    executor.change_file(format!("write {}", path.display()), || {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(mode)
+            .open(path)
+            .map_err(|err| anyhow!("failed to write {}: {}", path.display(), err))?;
+        file.write_all(contents.as_bytes())?;
+        Ok(())
+    })
+}
+
+/// Returns whether the file existed.
+fn remove_file(executor: &mut Executor, path: &Path) -> anyhow::Result<bool> {
+    if !path.exists() {
+        return Ok(false);
+    }
+    executor.change_file(
+        format!("remove {}", path.display()),
+        || match std::fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(anyhow!("failed to remove {}: {}", path.display(), err)),
+        },
+    )?;
+    Ok(true)
+}
+
+/// Tokens end up as separate words of `ExecStart`, which systemd splits on whitespace.
+fn validate_tokens(tokens: &[String]) -> anyhow::Result<()> {
+    match tokens
+        .iter()
+        .find(|token| token.is_empty() || token.contains(char::is_whitespace))
+    {
+        Some(_) => Err(anyhow!("tokens must not be empty or contain whitespace")),
+        None => Ok(()),
+    }
+}
+
+fn token_args(tokens: &[String]) -> String {
+    tokens
+        .iter()
+        .map(|token| format!("--token {}", token))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quotes a value for an `EnvironmentFile`.
+fn env_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Quotes a word of a unit setting if it contains whitespace or quotes.
+fn quote(word: &str) -> String {
+    if word.is_empty() || word.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        env_quote(word)
+    } else {
+        word.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_hardened_units() {
+        let unit = http_unit(
+            "wg0",
+            Path::new("/root/.wg"),
+            Path::new("/usr/local/bin/wghttp"),
+        );
+        assert!(unit.contains(
+            "ExecStart=/usr/local/bin/wghttp --device wg0 --config-path /root/.wg $WGHTTP_TOKENS\n"
+        ));
+        assert!(unit.contains("After=network-online.target wg-quick@wg0.service\n"));
+        assert!(unit.contains("EnvironmentFile=/etc/wgsuite/wghttp.env\n"));
+        assert!(unit.contains("ProtectSystem=strict\n"));
+        assert!(unit.contains("ReadWritePaths=/etc/wireguard /run /root\n"));
+        assert!(unit.contains("NoNewPrivileges=yes\n"));
+
+        let unit = bot_unit(
+            "wg0",
+            Path::new("/srv/my vpn/config"),
+            Path::new("/usr/bin/wgbot"),
+            42,
+        );
+        assert!(unit.contains(
+            "ExecStart=/usr/bin/wgbot --token ${WGBOT_TOKEN} --device wg0 \
+             --config-path \"/srv/my vpn/config\" --admin-id 42\n"
+        ));
+        assert!(unit.contains("ReadWritePaths=/etc/wireguard /run \"/srv/my vpn\"\n"));
+    }
+
+    #[test]
+    fn keeps_tokens_out_of_units() {
+        let tokens = vec!["ci:s3cr\"et".to_string(), "ops:other".to_string()];
+        validate_tokens(&tokens).unwrap();
+        assert_eq!(
+            env_quote(&token_args(&tokens)),
+            "\"--token ci:s3cr\\\"et --token ops:other\""
+        );
+        assert!(validate_tokens(&["ci:has space".to_string()]).is_err());
+        assert!(validate_tokens(&[String::new()]).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use std::{fmt, net::IpAddr};
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+
+use crate::{metrics::PeerStats, Client, Server};
+
+/// A client is considered online while its latest handshake is younger than this. WireGuard
+/// renews handshakes every two minutes on active tunnels, so three minutes leaves some slack.
+pub const ONLINE_TIMEOUT: u64 = 180;
+
+pub(crate) const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    public_key TEXT NOT NULL,
+    endpoint TEXT,
+    started_at INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    ended_at INTEGER,
+    received INTEGER NOT NULL,
+    sent INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sessions_key ON sessions (public_key, started_at);
+CREATE TABLE IF NOT EXISTS session_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    public_key TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    endpoint TEXT,
+    timestamp INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS event_cursors (
+    consumer TEXT PRIMARY KEY,
+    last_event INTEGER NOT NULL
+);
+";
+
+#[derive(Serialize)]
+pub struct Session {
+    pub id: i64,
+    pub client_id: usize,
+    pub endpoint: Option<IpAddr>,
+    pub started_at: u64,
+    pub last_seen: u64,
+    /// `None` while the session is still active.
+    pub ended_at: Option<u64>,
+    pub received_bytes: u64,
+    pub sent_bytes: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionEventKind {
+    Connected,
+    Disconnected,
+}
+
+impl fmt::Display for SessionEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionEventKind::Connected => write!(f, "connected"),
+            SessionEventKind::Disconnected => write!(f, "disconnected"),
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct SessionEvent {
+    pub kind: SessionEventKind,
+    pub client_id: usize,
+    pub client_name: String,
+    pub endpoint: Option<IpAddr>,
+    pub timestamp: u64,
+}
+
+/// Updates the sessions of `client` with a new sample and returns the produced events. Events
+/// are also logged for [`take_events`].
+pub(crate) fn update(
+    conn: &Connection,
+    client: &Client,
+    peer: &PeerStats,
+    traffic: (i64, i64),
+    timestamp: u64,
+) -> anyhow::Result<Vec<SessionEvent>> {
+    let endpoint = peer.endpoint.map(|endpoint| endpoint.ip());
+    let handshake_recent = peer
+        .latest_handshake
+        .is_some_and(|handshake| timestamp.saturating_sub(handshake) <= ONLINE_TIMEOUT);
+    let active = handshake_recent || traffic.0 > 0 || traffic.1 > 0;
+
+    let open = conn
+        .query_row(
+            "SELECT * FROM sessions WHERE public_key = ?1 AND ended_at IS NULL",
+            params![client.keys.public],
+            |row| session_from_row(row, client.id),
+        )
+        .optional()?;
+
+    let event = |kind, endpoint| SessionEvent {
+        kind,
+        client_id: client.id,
+        client_name: client.name.clone(),
+        endpoint,
+        timestamp,
+    };
+
+    let mut events = Vec::new();
+    if let Some(session) = &open {
+        let roamed = active && endpoint.is_some() && session.endpoint != endpoint;
+        if !active || roamed {
+            conn.execute(
+                "UPDATE sessions SET ended_at = last_seen WHERE id = ?1",
+                params![session.id],
+            )?;
+            events.push(event(SessionEventKind::Disconnected, session.endpoint));
+        } else {
+            conn.execute(
+                "UPDATE sessions SET last_seen = ?2, received = received + ?3, sent = sent + ?4
+                 WHERE id = ?1",
+                params![session.id, timestamp as i64, traffic.0, traffic.1],
+            )?;
+            return Ok(events);
+        }
+    }
+
+    if active {
+        conn.execute(
+            "INSERT INTO sessions (public_key, endpoint, started_at, last_seen, received, sent)
+             VALUES (?1, ?2, ?3, ?3, ?4, ?5)",
+            params![
+                client.keys.public,
+                endpoint.map(|ip| ip.to_string()),
+                timestamp as i64,
+                traffic.0,
+                traffic.1
+            ],
+        )?;
+        events.push(event(SessionEventKind::Connected, endpoint));
+    }
+    for event in &events {
+        conn.execute(
+            "INSERT INTO session_events (public_key, kind, endpoint, timestamp)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                client.keys.public,
+                event.kind.to_string(),
+                event.endpoint.map(|ip| ip.to_string()),
+                event.timestamp as i64
+            ],
+        )?;
+    }
+    Ok(events)
+}
+
+/// Returns the events logged since the previous call with the same `consumer`, whichever
+/// process collected them. The first call of a consumer returns nothing, events of removed
+/// clients are left out.
+pub(crate) fn take_events(
+    conn: &Connection,
+    server: &Server,
+    consumer: &str,
+) -> anyhow::Result<Vec<SessionEvent>> {
+    let latest: i64 =
+        conn.query_row("SELECT IFNULL(MAX(id), 0) FROM session_events", [], |row| {
+            row.get(0)
+        })?;
+    let cursor: Option<i64> = conn
+        .query_row(
+            "SELECT last_event FROM event_cursors WHERE consumer = ?1",
+            params![consumer],
+            |row| row.get(0),
+        )
+        .optional()?;
+    conn.execute(
+        "INSERT INTO event_cursors (consumer, last_event) VALUES (?1, ?2)
+         ON CONFLICT (consumer) DO UPDATE SET last_event = ?2",
+        params![consumer, latest],
+    )?;
+    let cursor = match cursor {
+        Some(cursor) => cursor,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT public_key, kind, endpoint, timestamp FROM session_events
+         WHERE id > ?1 AND id <= ?2 ORDER BY id",
+    )?;
+    let rows = stmt
+        .query_map(params![cursor, latest], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(public_key, kind, endpoint, timestamp)| {
+            let client = server.find_client_by_public_key(&public_key)?;
+            let kind = match kind.as_str() {
+                "connected" => SessionEventKind::Connected,
+                _ => SessionEventKind::Disconnected,
+            };
+            Some(SessionEvent {
+                kind,
+                client_id: client.id,
+                client_name: client.name,
+                endpoint: endpoint.and_then(|endpoint| endpoint.parse().ok()),
+                timestamp: timestamp as u64,
+            })
+        })
+        .collect())
+}
+
+pub(crate) fn list(
+    conn: &Connection,
+    client: &Client,
+    limit: usize,
+) -> anyhow::Result<Vec<Session>> {
+    let mut stmt = conn.prepare(
+        "SELECT * FROM sessions WHERE public_key = ?1 ORDER BY started_at DESC, id DESC LIMIT ?2",
+    )?;
+    let sessions = stmt
+        .query_map(params![client.keys.public, limit as i64], |row| {
+            session_from_row(row, client.id)
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(sessions)
+}
+
+fn session_from_row(row: &Row, client_id: usize) -> rusqlite::Result<Session> {
+    let endpoint: Option<String> = row.get("endpoint")?;
+    Ok(Session {
+        id: row.get("id")?,
+        client_id,
+        endpoint: endpoint.and_then(|endpoint| endpoint.parse().ok()),
+        started_at: row.get::<_, i64>("started_at")? as u64,
+        last_seen: row.get::<_, i64>("last_seen")? as u64,
+        ended_at: row.get::<_, Option<i64>>("ended_at")?.map(|t| t as u64),
+        received_bytes: row.get::<_, i64>("received")? as u64,
+        sent_bytes: row.get::<_, i64>("sent")? as u64,
+    })
+}
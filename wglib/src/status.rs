@@ -0,0 +1,175 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{metrics::DeviceStats, Server};
+
+/// A client from the config joined with its live peer stats. Fields are only added, never
+/// renamed or removed, so the JSON output can be relied on by scripts.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientStatus {
+    pub id: usize,
+    pub name: String,
+    pub ip: Ipv4Addr,
+    pub public_key: String,
+    pub tags: Vec<String>,
+    pub owner: Option<String>,
+    pub disabled: bool,
+    pub endpoint: Option<SocketAddr>,
+    /// Unix timestamp of the latest handshake, `None` if the client never connected or the
+    /// device isn't running.
+    pub latest_handshake: Option<u64>,
+    /// Bytes received from the client.
+    pub received_bytes: u128,
+    /// Bytes sent to the client.
+    pub sent_bytes: u128,
+}
+
+/// State of the device and all of its clients.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeviceStatus {
+    pub device: String,
+    pub running: bool,
+    pub public_key: String,
+    pub listen_port: u16,
+    pub clients: Vec<ClientStatus>,
+}
+
+/// Bytes per second received from and sent to a client between two samples.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Throughput {
+    pub received: f64,
+    pub sent: f64,
+}
+
+impl Throughput {
+    /// Counters which went down, because the device was restarted, count as no traffic.
+    pub fn between(before: &ClientStatus, after: &ClientStatus, elapsed: Duration) -> Self {
+        let seconds = elapsed.as_secs_f64();
+        if seconds <= 0.0 {
+            return Self::default();
+        }
+        Self {
+            received: after.received_bytes.saturating_sub(before.received_bytes) as f64 / seconds,
+            sent: after.sent_bytes.saturating_sub(before.sent_bytes) as f64 / seconds,
+        }
+    }
+}
+
+/// Joins every client of `server` with its peer in `stats`, ordered by id. Clients without a
+/// peer, and all of them without `stats`, have no handshake and zero traffic.
+pub fn join(server: &Server, stats: Option<&DeviceStats>) -> Vec<ClientStatus> {
+    let mut clients: Vec<ClientStatus> = server
+        .clients
+        .values()
+        .map(|client| {
+            let peer = stats.and_then(|stats| {
+                stats
+                    .peers
+                    .iter()
+                    .find(|peer| peer.public_key == client.keys.public)
+            });
+            ClientStatus {
+                id: client.id,
+                name: client.name.clone(),
+                ip: client.get_ip_address(&server.subnet),
+                public_key: client.keys.public.clone(),
+                tags: client.tags.clone(),
+                owner: client.owner.clone(),
+                disabled: client.disabled,
+                endpoint: peer.and_then(|peer| peer.endpoint),
+                latest_handshake: peer.and_then(|peer| peer.latest_handshake),
+                received_bytes: peer.map_or(0, |peer| peer.received_bytes),
+                sent_bytes: peer.map_or(0, |peer| peer.sent_bytes),
+            }
+        })
+        .collect();
+    clients.sort_by_key(|client| client.id);
+    clients
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{InterfaceStats, PeerStats};
+
+    #[test]
+    fn joins_config_with_peers() {
+        let mut server = Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        let laptop = server.add_client("laptop".to_string(), Vec::new()).unwrap();
+        let phone = server.add_client("phone".to_string(), Vec::new()).unwrap();
+        let stats = DeviceStats {
+            interface: InterfaceStats {
+                public_key: server.keys.public.clone(),
+                listen_port: 51820,
+                fwmark: None,
+            },
+            peers: vec![PeerStats {
+                public_key: server.get_client(phone).unwrap().keys.public,
+                endpoint: Some("198.51.100.7:40000".parse().unwrap()),
+                allowed_ips: Vec::new(),
+                latest_handshake: Some(1_700_000_000),
+                received_bytes: 10,
+                sent_bytes: 20,
+                persistent_keepalive: None,
+            }],
+        };
+
+        let clients = join(&server, Some(&stats));
+        assert_eq!(
+            clients.iter().map(|client| client.id).collect::<Vec<_>>(),
+            vec![laptop, phone]
+        );
+        assert_eq!(clients[0].latest_handshake, None);
+        assert_eq!(clients[0].received_bytes, 0);
+        assert_eq!(clients[1].ip, "10.0.0.3".parse::<Ipv4Addr>().unwrap());
+        assert_eq!(clients[1].latest_handshake, Some(1_700_000_000));
+        assert_eq!((clients[1].received_bytes, clients[1].sent_bytes), (10, 20));
+
+        assert!(join(&server, None)
+            .iter()
+            .all(|client| client.endpoint.is_none() && client.sent_bytes == 0));
+    }
+
+    #[test]
+    fn computes_throughput() {
+        let mut server = Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        server.add_client("laptop".to_string(), Vec::new()).unwrap();
+        let before = join(&server, None).remove(0);
+        let after = ClientStatus {
+            received_bytes: 4096,
+            sent_bytes: 1024,
+            ..before.clone()
+        };
+
+        let throughput = Throughput::between(&before, &after, Duration::from_secs(2));
+        assert_eq!(
+            throughput,
+            Throughput {
+                received: 2048.0,
+                sent: 512.0
+            }
+        );
+        assert_eq!(
+            Throughput::between(&after, &before, Duration::from_secs(2)),
+            Throughput::default()
+        );
+        assert_eq!(
+            Throughput::between(&before, &after, Duration::ZERO),
+            Throughput::default()
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+
+use super::Storage;
+use crate::{model::server::ServerConfig, Client, Server};
+
+/// The whole server is stored as a single JSON file which is rewritten on every change.
+pub struct JsonStorage {
+    path: PathBuf,
+}
+
+impl JsonStorage {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load(&self) -> anyhow::Result<Server> {
+        let file = std::fs::File::open(&self.path)?;
+        let config: ServerConfig = serde_json::from_reader(file)?;
+        Server::from_server_config(config)
+    }
+
+    fn save(&mut self, server: &Server) -> anyhow::Result<()> {
+        let file = crate::fs::create_private_file(&self.path)?;
+        serde_json::to_writer(file, &server.to_server_config())?;
+        Ok(())
+    }
+
+    fn insert_clients(&mut self, clients: &[Client]) -> anyhow::Result<()> {
+        let mut server = self.load()?;
+        for client in clients {
+            if server.clients.insert(client.id, client.clone()).is_some() {
+                return Err(anyhow!("client with id {} already exists", client.id));
+            }
+        }
+        self.save(&server)
+    }
+
+    fn update_client(&mut self, client: &Client) -> anyhow::Result<()> {
+        let mut server = self.load()?;
+        match server.clients.get_mut(&client.id) {
+            Some(existing) => *existing = client.clone(),
+            None => return Err(anyhow!("client with id {} doesn't exist", client.id)),
+        }
+        self.save(&server)
+    }
+
+    fn remove_client(&mut self, id: usize) -> anyhow::Result<Client> {
+        let mut server = self.load()?;
+        let client = server.remove_client(id)?;
+        self.save(&server)?;
+        Ok(client)
+    }
+}
@@ -0,0 +1,181 @@
+pub mod json;
+pub mod sqlite;
+
+use std::{io::Read, path::Path, str::FromStr};
+
+use anyhow::anyhow;
+
+use crate::{Client, Server};
+
+pub use json::JsonStorage;
+pub use sqlite::SqliteStorage;
+
+pub(crate) const SQLITE_HEADER: &[u8] = b"SQLite format 3\0";
+
+/// Persistent storage of the server config and its clients.
+pub trait Storage {
+    fn load(&self) -> anyhow::Result<Server>;
+
+    /// Replaces the whole stored state with `server`.
+    fn save(&mut self, server: &Server) -> anyhow::Result<()>;
+
+    /// Atomically inserts a new client, failing if its id is already taken.
+    fn insert_client(&mut self, client: &Client) -> anyhow::Result<()> {
+        self.insert_clients(std::slice::from_ref(client))
+    }
+
+    /// Atomically inserts new clients. Either all of them are inserted or, if any id is already
+    /// taken, none.
+    fn insert_clients(&mut self, clients: &[Client]) -> anyhow::Result<()>;
+
+    /// Atomically replaces an existing client.
+    fn update_client(&mut self, client: &Client) -> anyhow::Result<()>;
+
+    /// Atomically removes a client and returns it.
+    fn remove_client(&mut self, id: usize) -> anyhow::Result<Client>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageKind {
+    Json,
+    Sqlite,
+}
+
+impl FromStr for StorageKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(StorageKind::Json),
+            "sqlite" => Ok(StorageKind::Sqlite),
+            _ => Err(anyhow!("unknown storage {}, expected json or sqlite", s)),
+        }
+    }
+}
+
+/// Opens the storage at `path`, detecting its kind with [`detect`].
+pub fn open(path: &Path) -> anyhow::Result<Box<dyn Storage>> {
+    open_as(path, detect(path)?)
+}
+
+pub fn open_as(path: &Path, kind: StorageKind) -> anyhow::Result<Box<dyn Storage>> {
+    Ok(match kind {
+        StorageKind::Json => Box::new(JsonStorage::new(path)),
+        StorageKind::Sqlite => Box::new(SqliteStorage::open(path)?),
+    })
+}
+
+/// Existing files are detected by their content. New files are SQLite databases if they have
+/// a `.db`, `.sqlite` or `.sqlite3` extension and JSON files otherwise.
+pub fn detect(path: &Path) -> anyhow::Result<StorageKind> {
+    match std::fs::File::open(path) {
+        Ok(file) => {
+            let mut header = Vec::with_capacity(SQLITE_HEADER.len());
+            file.take(SQLITE_HEADER.len() as u64)
+                .read_to_end(&mut header)?;
+            if header == SQLITE_HEADER {
+                Ok(StorageKind::Sqlite)
+            } else {
+                Ok(StorageKind::Json)
+            }
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("db" | "sqlite" | "sqlite3") => Ok(StorageKind::Sqlite),
+                _ => Ok(StorageKind::Json),
+            }
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Copies the state stored at `from` into a new storage of `kind` at `to`.
+pub fn convert(from: &Path, to: &Path, kind: StorageKind) -> anyhow::Result<()> {
+    if to.exists() {
+        return Err(anyhow!("{} already exists", to.display()));
+    }
+    let server = open(from)?.load()?;
+    open_as(to, kind)?.save(&server)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> Server {
+        let mut server = Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        server.add_client("alice".to_string(), Vec::new()).unwrap();
+        server
+            .add_client("bob".to_string(), vec!["staff".to_string()])
+            .unwrap();
+        server
+    }
+
+    fn check_client_updates(storage: &mut dyn Storage) {
+        let mut server = server();
+        storage.save(&server).unwrap();
+
+        let id = server.add_client("carol".to_string(), Vec::new()).unwrap();
+        storage
+            .insert_client(&server.get_client(id).unwrap())
+            .unwrap();
+        assert!(storage
+            .insert_client(&server.get_client(id).unwrap())
+            .is_err());
+
+        let batch = [
+            Client::new(7, "dave".to_string(), Vec::new()),
+            server.get_client(id).unwrap(),
+        ];
+        assert!(storage.insert_clients(&batch).is_err());
+        assert!(!storage.load().unwrap().clients.contains_key(&7));
+
+        let mut bob = server.get_client(1).unwrap();
+        bob.name = "robert".to_string();
+        storage.update_client(&bob).unwrap();
+
+        assert_eq!(storage.remove_client(0).unwrap().name, "alice");
+        assert!(storage.remove_client(0).is_err());
+
+        let loaded = storage.load().unwrap();
+        let mut names: Vec<_> = loaded.clients.values().map(|c| c.name.clone()).collect();
+        names.sort();
+        assert_eq!(names, vec!["carol", "robert"]);
+        assert_eq!(loaded.keys.public, server.keys.public);
+        assert_eq!(loaded.subnet, server.subnet);
+    }
+
+    #[test]
+    fn json_client_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = JsonStorage::new(&dir.path().join("wg"));
+        check_client_updates(&mut storage);
+    }
+
+    #[test]
+    fn sqlite_client_updates() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = SqliteStorage::open(&dir.path().join("wg.db")).unwrap();
+        check_client_updates(&mut storage);
+    }
+
+    #[test]
+    fn converts_and_detects() {
+        let dir = tempfile::tempdir().unwrap();
+        let json_path = dir.path().join("wg");
+        let sqlite_path = dir.path().join("wg-copy");
+
+        open(&json_path).unwrap().save(&server()).unwrap();
+        convert(&json_path, &sqlite_path, StorageKind::Sqlite).unwrap();
+
+        assert_eq!(detect(&json_path).unwrap(), StorageKind::Json);
+        assert_eq!(detect(&sqlite_path).unwrap(), StorageKind::Sqlite);
+        assert_eq!(open(&sqlite_path).unwrap().load().unwrap().clients.len(), 2);
+        assert!(convert(&json_path, &sqlite_path, StorageKind::Sqlite).is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::Storage;
+use crate::{model::server::ServerConfig, Client, Server};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS server (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS clients (
+    id INTEGER PRIMARY KEY,
+    public_key TEXT NOT NULL UNIQUE,
+    data TEXT NOT NULL
+);
+";
+
+/// Server settings and every client are stored in separate rows, so client changes only
+/// touch a single row inside a transaction.
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        // SQLite would create the database readable by everyone.
+        crate::fs::ensure_private_file(path)?;
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&self) -> anyhow::Result<Server> {
+        let data: Option<String> = self
+            .conn
+            .query_row("SELECT data FROM server WHERE id = 0", [], |row| row.get(0))
+            .optional()?;
+        let mut config: ServerConfig = match data {
+            Some(data) => serde_json::from_str(&data)?,
+            None => return Err(anyhow!("server is not initialized")),
+        };
+
+        let mut stmt = self.conn.prepare("SELECT data FROM clients")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        for data in rows {
+            let client: Client = serde_json::from_str(&data?)?;
+            config.clients.insert(client.id, client);
+        }
+        Server::from_server_config(config)
+    }
+
+    fn save(&mut self, server: &Server) -> anyhow::Result<()> {
+        let mut config = server.to_server_config();
+        let clients = std::mem::take(&mut config.clients);
+
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO server (id, data) VALUES (0, ?1)",
+            params![serde_json::to_string(&config)?],
+        )?;
+        tx.execute("DELETE FROM clients", [])?;
+        for client in clients.values() {
+            insert(&tx, client)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn insert_clients(&mut self, clients: &[Client]) -> anyhow::Result<()> {
+        let tx = self.conn.transaction()?;
+        for client in clients {
+            insert(&tx, client)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn update_client(&mut self, client: &Client) -> anyhow::Result<()> {
+        let tx = self.conn.transaction()?;
+        let updated = tx.execute(
+            "UPDATE clients SET public_key = ?2, data = ?3 WHERE id = ?1",
+            params![
+                client.id as i64,
+                client.keys.public,
+                serde_json::to_string(client)?
+            ],
+        )?;
+        if updated == 0 {
+            return Err(anyhow!("client with id {} doesn't exist", client.id));
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn remove_client(&mut self, id: usize) -> anyhow::Result<Client> {
+        let tx = self.conn.transaction()?;
+        let data: Option<String> = tx
+            .query_row(
+                "SELECT data FROM clients WHERE id = ?1",
+                params![id as i64],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let client = match data {
+            Some(data) => serde_json::from_str(&data)?,
+            None => return Err(anyhow!("client with id {} doesn't exist", id)),
+        };
+        tx.execute("DELETE FROM clients WHERE id = ?1", params![id as i64])?;
+        tx.commit()?;
+        Ok(client)
+    }
+}
+
+fn insert(conn: &Connection, client: &Client) -> anyhow::Result<()> {
+    conn.execute(
+        "INSERT INTO clients (id, public_key, data) VALUES (?1, ?2, ?3)",
+        params![
+            client.id as i64,
+            client.keys.public,
+            serde_json::to_string(client)?
+        ],
+    )
+    .map_err(|err| anyhow!("failed to insert client with id {}: {}", client.id, err))?;
+    Ok(())
+}
@@ -0,0 +1,346 @@
+use std::{collections::BTreeMap, process::Command, str::FromStr};
+
+use anyhow::anyhow;
+use ipnet::{IpNet, Ipv4Net};
+use serde::{Deserialize, Serialize};
+
+use crate::{metrics::DeviceStats, plan::Executor, Client, Server};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Peer {
+    pub public_key: String,
+    pub allowed_ips: Vec<IpNet>,
+}
+
+/// Difference between the desired state of an interface and its live state, in the same way
+/// `wg syncconf` computes it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PeerDiff {
+    /// Peers which are only in the desired state.
+    pub added: Vec<Peer>,
+    /// Peers which are only on the live interface.
+    pub removed: Vec<Peer>,
+    /// Peers with different allowed ips, in their desired state.
+    pub updated: Vec<Peer>,
+    /// New listen port if it differs from the live one.
+    pub listen_port: Option<u16>,
+    /// Whether the live interface uses a different private key.
+    pub private_key_changed: bool,
+}
+
+impl PeerDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.updated.is_empty()
+            && self.listen_port.is_none()
+            && !self.private_key_changed
+    }
+}
+
+/// Peers on which the config and the live interface disagree.
+#[derive(Debug, Default, Serialize)]
+pub struct Drift {
+    pub config_only: Vec<DriftedPeer>,
+    pub interface_only: Vec<DriftedPeer>,
+    pub mismatched: Vec<DriftedPeer>,
+}
+
+impl Drift {
+    pub fn is_empty(&self) -> bool {
+        self.config_only.is_empty() && self.interface_only.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DriftedPeer {
+    pub public_key: String,
+    /// `None` for peers which aren't in the config.
+    pub client_id: Option<usize>,
+    pub client_name: Option<String>,
+    pub config_allowed_ips: Vec<IpNet>,
+    pub interface_allowed_ips: Vec<IpNet>,
+}
+
+/// Which side is considered correct when repairing drift.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RepairDirection {
+    /// Change the live interface to match the config.
+    Interface,
+    /// Change the config to match the live interface.
+    Config,
+}
+
+impl FromStr for RepairDirection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interface" => Ok(RepairDirection::Interface),
+            "config" => Ok(RepairDirection::Config),
+            _ => Err(anyhow!(
+                "unknown repair direction {}, expected interface or config",
+                s
+            )),
+        }
+    }
+}
+
+pub fn desired_peers(server: &Server) -> Vec<Peer> {
+    server
+        .clients
+        .values()
+        .filter(|client| !client.disabled)
+        .map(|client| peer(client, server))
+        .collect()
+}
+
+/// Returns the peer of `client` as it should be on the interface.
+pub fn peer(client: &Client, server: &Server) -> Peer {
+    Peer {
+        public_key: client.keys.public.clone(),
+        allowed_ips: vec![IpNet::V4(Ipv4Net::from(
+            client.get_ip_address(&server.subnet),
+        ))],
+    }
+}
+
+pub fn live_peers(stats: &DeviceStats) -> Vec<Peer> {
+    stats
+        .peers
+        .iter()
+        .map(|peer| Peer {
+            public_key: peer.public_key.clone(),
+            allowed_ips: peer.allowed_ips.clone(),
+        })
+        .collect()
+}
+
+pub fn diff(server: &Server, stats: &DeviceStats) -> PeerDiff {
+    let mut result = diff_peers(&desired_peers(server), &live_peers(stats));
+    if stats.interface.listen_port != server.port {
+        result.listen_port = Some(server.port);
+    }
+    result.private_key_changed = stats.interface.public_key != server.keys.public;
+    result
+}
+
+pub fn drift(server: &Server, stats: &DeviceStats) -> Drift {
+    let live = live_peers(stats);
+    let diff = diff_peers(&desired_peers(server), &live);
+
+    let interface_allowed_ips = |public_key: &str| {
+        live.iter()
+            .find(|peer| peer.public_key == public_key)
+            .map(|peer| peer.allowed_ips.clone())
+            .unwrap_or_default()
+    };
+    let drifted = |peer: Peer, config: bool| {
+        let client = server.find_client_by_public_key(&peer.public_key);
+        DriftedPeer {
+            client_id: client.as_ref().map(|client| client.id),
+            client_name: client.map(|client| client.name),
+            interface_allowed_ips: interface_allowed_ips(&peer.public_key),
+            config_allowed_ips: if config { peer.allowed_ips } else { Vec::new() },
+            public_key: peer.public_key,
+        }
+    };
+
+    Drift {
+        config_only: diff
+            .added
+            .into_iter()
+            .map(|peer| drifted(peer, true))
+            .collect(),
+        interface_only: diff
+            .removed
+            .into_iter()
+            .map(|peer| drifted(peer, false))
+            .collect(),
+        mismatched: diff
+            .updated
+            .into_iter()
+            .map(|peer| drifted(peer, true))
+            .collect(),
+    }
+}
+
+pub fn diff_peers(desired: &[Peer], live: &[Peer]) -> PeerDiff {
+    let mut live: BTreeMap<&str, &Peer> = live
+        .iter()
+        .map(|peer| (peer.public_key.as_str(), peer))
+        .collect();
+
+    let mut result = PeerDiff::default();
+    for peer in desired {
+        match live.remove(peer.public_key.as_str()) {
+            Some(live_peer) => {
+                if sorted(&live_peer.allowed_ips) != sorted(&peer.allowed_ips) {
+                    result.updated.push(peer.clone());
+                }
+            }
+            None => result.added.push(peer.clone()),
+        }
+    }
+    result.removed = live.into_values().cloned().collect();
+    result
+}
+
+/// Returns the arguments of a single `wg set` invocation applying the diff. Private key
+/// changes read the key from stdin.
+pub fn wg_set_args(device: &str, diff: &PeerDiff) -> Vec<String> {
+    let mut args = vec!["set".to_string(), device.to_string()];
+    if let Some(port) = diff.listen_port {
+        args.extend(["listen-port".to_string(), port.to_string()]);
+    }
+    if diff.private_key_changed {
+        args.extend(["private-key".to_string(), "/dev/stdin".to_string()]);
+    }
+    for peer in &diff.removed {
+        args.extend([
+            "peer".to_string(),
+            peer.public_key.clone(),
+            "remove".to_string(),
+        ]);
+    }
+    for peer in diff.updated.iter().chain(&diff.added) {
+        args.extend([
+            "peer".to_string(),
+            peer.public_key.clone(),
+            "allowed-ips".to_string(),
+            join(&peer.allowed_ips),
+        ]);
+    }
+    args
+}
+
+/// Applies the diff to the live interface without tearing it down.
+pub fn apply(device: &str, server: &Server, diff: &PeerDiff) -> anyhow::Result<()> {
+    apply_with(&mut Executor::new(false), device, server, diff)
+}
+
+pub(crate) fn apply_with(
+    executor: &mut Executor,
+    device: &str,
+    server: &Server,
+    diff: &PeerDiff,
+) -> anyhow::Result<()> {
+    if diff.is_empty() {
+        return Ok(());
+    }
+    let mut cmd = Command::new("wg");
+    cmd.args(wg_set_args(device, diff));
+    let private_key = diff
+        .private_key_changed
+        .then_some(server.keys.private.as_bytes());
+    executor.run(cmd, private_key)
+}
+
+fn sorted(nets: &[IpNet]) -> Vec<IpNet> {
+    let mut nets = nets.to_vec();
+    nets.sort();
+    nets
+}
+
+fn join(nets: &[IpNet]) -> String {
+    nets.iter()
+        .map(|net| net.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{InterfaceStats, PeerStats};
+
+    fn peer(public_key: &str, allowed_ips: &[&str]) -> Peer {
+        Peer {
+            public_key: public_key.to_string(),
+            allowed_ips: allowed_ips.iter().map(|ip| ip.parse().unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn diffs_peers() {
+        let desired = vec![
+            peer("a", &["10.0.0.2/32"]),
+            peer("b", &["10.0.0.3/32"]),
+            peer("c", &["10.0.0.4/32", "fd00::4/128"]),
+        ];
+        let live = vec![
+            peer("b", &["10.0.0.9/32"]),
+            peer("c", &["fd00::4/128", "10.0.0.4/32"]),
+            peer("d", &["10.0.0.5/32"]),
+        ];
+
+        let diff = diff_peers(&desired, &live);
+        assert_eq!(diff.added, vec![peer("a", &["10.0.0.2/32"])]);
+        assert_eq!(diff.updated, vec![peer("b", &["10.0.0.3/32"])]);
+        assert_eq!(diff.removed, vec![peer("d", &["10.0.0.5/32"])]);
+
+        assert_eq!(
+            wg_set_args("wg0", &diff).join(" "),
+            "set wg0 peer d remove peer b allowed-ips 10.0.0.3/32 peer a allowed-ips 10.0.0.2/32"
+        );
+    }
+
+    #[test]
+    fn reports_drift() {
+        let mut server = Server::new(
+            "10.0.0.0/24".parse().unwrap(),
+            "203.0.113.1".to_string(),
+            51820,
+            "eth0".to_string(),
+        );
+        let missing = server
+            .add_client("missing".to_string(), Vec::new())
+            .unwrap();
+        let moved = server.add_client("moved".to_string(), Vec::new()).unwrap();
+        let live_peer = |public_key: String, allowed_ip: &str| PeerStats {
+            public_key,
+            endpoint: None,
+            allowed_ips: vec![allowed_ip.parse().unwrap()],
+            latest_handshake: None,
+            received_bytes: 0,
+            sent_bytes: 0,
+            persistent_keepalive: None,
+        };
+        let stats = DeviceStats {
+            interface: InterfaceStats {
+                public_key: server.keys.public.clone(),
+                listen_port: 51820,
+                fwmark: None,
+            },
+            peers: vec![
+                live_peer(server.get_client(moved).unwrap().keys.public, "10.0.0.9/32"),
+                live_peer("manual".to_string(), "10.0.0.10/32"),
+            ],
+        };
+
+        let drift = drift(&server, &stats);
+        assert_eq!(drift.config_only.len(), 1);
+        assert_eq!(drift.config_only[0].client_id, Some(missing));
+        assert!(drift.config_only[0].interface_allowed_ips.is_empty());
+        assert_eq!(drift.interface_only.len(), 1);
+        assert_eq!(drift.interface_only[0].public_key, "manual");
+        assert_eq!(drift.interface_only[0].client_id, None);
+        assert_eq!(drift.mismatched.len(), 1);
+        assert_eq!(drift.mismatched[0].client_name.as_deref(), Some("moved"));
+        assert_eq!(
+            drift.mismatched[0].interface_allowed_ips,
+            vec!["10.0.0.9/32".parse::<IpNet>().unwrap()]
+        );
+        assert_eq!(
+            drift.mismatched[0].config_allowed_ips,
+            vec!["10.0.0.3/32".parse::<IpNet>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn identical_state_has_no_diff() {
+        let peers = vec![peer("a", &["10.0.0.2/32"])];
+        assert!(diff_peers(&peers, &peers).is_empty());
+    }
+}
@@ -1,27 +1,73 @@
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    io::{IsTerminal, Write},
+    net::{IpAddr, Ipv4Addr},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use anyhow::anyhow;
+use ipnet::Ipv4Net;
 use wglib::{
-    actions::{dump_wg_config, restart_wg, start_wg, stop_wg},
+    audit::{Actor, AuditFilter},
+    context::{Context, ContextConfig},
+    doctor::CheckStatus,
+    export::{self, ExportFormat},
+    history::{self, Bucket},
+    import,
+    model::{
+        client::{ClientDefaults, ClientUpdate, Routing},
+        pool::AddressRange,
+    },
+    plan::Operation,
+    service::{self, BotService, HttpService, ServiceOptions},
+    status::{ClientStatus, Throughput},
+    storage::{self, StorageKind},
+    sync::RepairDirection,
     Server,
 };
 
-use crate::{defaults, utils};
+use crate::{
+    defaults,
+    output::{self, OutputFormat},
+    utils, wizard,
+};
 
+/// Settings of `init` given on the command line.
+pub struct InitOptions {
+    pub subnet: Option<String>,
+    pub endpoint: Option<String>,
+    pub port: Option<u16>,
+    pub network_interface: Option<String>,
+    pub dns: Vec<IpAddr>,
+    pub routing: Option<Routing>,
+}
+
+/// Asks for the settings missing from `options` in a terminal, otherwise uses the defaults.
 pub fn init(
-    subnet: Option<String>,
-    endpoint: Option<String>,
-    port: Option<u16>,
-    network_interface: Option<String>,
+    options: InitOptions,
     output: Option<PathBuf>,
+    non_interactive: bool,
 ) -> anyhow::Result<()> {
-    let subnet = defaults::prepare_subnet(subnet)?;
-    let endpoint = defaults::prepare_endpoint(endpoint)?;
-    let port = defaults::prepare_port(port);
-    let network_interface = defaults::prepare_network_interface(network_interface)?;
     let output = defaults::prepare_config_path(output)?;
+    let interactive =
+        !non_interactive && std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
 
-    let server = Server::new(subnet, endpoint, port, network_interface);
+    let server = if interactive {
+        wizard::ask(options, &output)?
+    } else {
+        let subnet = defaults::prepare_subnet(options.subnet)?;
+        let endpoint = defaults::prepare_endpoint(options.endpoint)?;
+        let port = defaults::prepare_port(options.port);
+        let network_interface = defaults::prepare_network_interface(options.network_interface)?;
+        let mut server = Server::new(subnet, endpoint, port, network_interface);
+        server.client_defaults = ClientDefaults {
+            dns: (!options.dns.is_empty()).then_some(options.dns),
+            routing: options.routing.unwrap_or_default(),
+        };
+        server
+    };
+    server.validate()?;
     server.dump_to_file(&output)?;
 
     println!("Created config at: {:?}", output);
@@ -30,31 +76,200 @@ pub fn init(
 
 pub fn add_client(
     name: String,
+    tags: Vec<String>,
     device: Option<String>,
     config_path: Option<PathBuf>,
+    dry_run: bool,
 ) -> anyhow::Result<()> {
     let device = defaults::prepare_device(device);
     let config_path = defaults::prepare_config_path(config_path)?;
 
     let config_path = utils::get_config_path_with_sudo(config_path)?;
+    if dry_run {
+        return preview(&device, &config_path, Operation::AddClient { name, tags });
+    }
 
-    let id = wglib::actions::add_client(&device, &config_path, name)?;
+    let id = wglib::actions::add_client(&device, &config_path, name, tags, &Actor::current_user())?;
 
     println!("Created client with id: {id}");
     Ok(())
 }
 
+pub fn resize_subnet(
+    subnet: Ipv4Net,
+    output: PathBuf,
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let config_path = defaults::prepare_config_path(config_path)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+    if dry_run {
+        return preview(&device, &config_path, Operation::ResizeSubnet(subnet));
+    }
+
+    let renumbered =
+        wglib::actions::resize_subnet(&device, &config_path, subnet, &Actor::current_user())?;
+
+    println!("Server moved to {}", subnet.trunc());
+    if renumbered.is_empty() {
+        println!("No client configs changed, existing configs keep working");
+        return Ok(());
+    }
+
+    let ids: Vec<usize> = renumbered.iter().map(|client| client.id).collect();
+    wglib::actions::export_configs_zip(
+        &config_path,
+        &ids,
+        wglib::fs::create_private_file(&output)?,
+    )?;
+    println!("These clients must download a new config:");
+    for client in &renumbered {
+        utils::print_renumbered(client);
+    }
+    println!("New configs written to {:?}", output);
+    Ok(())
+}
+
+/// Changes to the address pool requested on the command line.
+pub struct PoolChanges {
+    pub start: Option<Ipv4Addr>,
+    pub end: Option<Ipv4Addr>,
+    pub reset_range: bool,
+    pub reserve: Vec<AddressRange>,
+    pub unreserve: Vec<AddressRange>,
+    pub clear_reserved: bool,
+}
+
+pub fn pool(
+    changes: PoolChanges,
+    config_path: Option<PathBuf>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let config_path = defaults::prepare_config_path(config_path)?;
+    let server = Server::load_from_file(&config_path)?;
+
+    let mut pool = server.pool.clone();
+    if changes.reset_range {
+        pool.start = None;
+        pool.end = None;
+    }
+    pool.start = changes.start.or(pool.start);
+    pool.end = changes.end.or(pool.end);
+    if changes.clear_reserved {
+        pool.reserved.clear();
+    }
+    for range in &changes.unreserve {
+        if !pool.reserved.contains(range) {
+            return Err(anyhow!("{} is not reserved", range));
+        }
+        pool.reserved.retain(|reserved| reserved != range);
+    }
+    pool.reserved.extend(changes.reserve);
+
+    let server = if pool != server.pool {
+        let config_path = utils::get_config_path_with_sudo(config_path)?;
+        if dry_run {
+            let device = defaults::prepare_device(None);
+            return preview(&device, &config_path, Operation::SetAddressPool(pool));
+        }
+        wglib::actions::set_address_pool(&config_path, pool, &Actor::current_user())?;
+        Server::load_from_file(&config_path)?
+    } else {
+        server
+    };
+
+    match server.pool.client_range(&server.subnet) {
+        Ok((start, end)) => println!("Clients: {start}-{end}"),
+        Err(err) => println!("Clients: none, {err}"),
+    }
+    for range in &server.pool.reserved {
+        println!("Reserved: {range}");
+    }
+    println!(
+        "Used: {} of {}",
+        server.clients.len(),
+        server.max_number_of_clients()
+    );
+    Ok(())
+}
+
+pub fn import_clients(
+    file: PathBuf,
+    output: PathBuf,
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let config_path = defaults::prepare_config_path(config_path)?;
+    let specs = import::parse_file(&file)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+    if dry_run {
+        return preview(&device, &config_path, Operation::ImportClients(specs));
+    }
+
+    let clients =
+        wglib::actions::import_clients(&device, &config_path, &specs, &Actor::current_user())?;
+    let ids: Vec<usize> = clients.iter().map(|client| client.id).collect();
+    wglib::actions::export_configs_zip(
+        &config_path,
+        &ids,
+        wglib::fs::create_private_file(&output)?,
+    )?;
+
+    for client in &clients {
+        utils::print_client(client);
+    }
+    println!(
+        "Created {} clients, configs written to {:?}",
+        clients.len(),
+        output
+    );
+    Ok(())
+}
+
+pub fn edit_client(
+    id: usize,
+    update: ClientUpdate,
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let config_path = defaults::prepare_config_path(config_path)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+    if dry_run {
+        return preview(&device, &config_path, Operation::EditClient(id, update));
+    }
+
+    let client =
+        wglib::actions::edit_client(&device, &config_path, id, update, &Actor::current_user())?;
+
+    println!("Updated client:");
+    utils::print_client(&client);
+    Ok(())
+}
+
 pub fn remove_client(
     id: usize,
     device: Option<String>,
     config_path: Option<PathBuf>,
+    dry_run: bool,
 ) -> anyhow::Result<()> {
     let device = defaults::prepare_device(device);
     let config_path = defaults::prepare_config_path(config_path)?;
 
     let config_path = utils::get_config_path_with_sudo(config_path)?;
+    if dry_run {
+        return preview(&device, &config_path, Operation::RemoveClient(id));
+    }
 
-    wglib::actions::remove_client(&device, &config_path, id)?;
+    wglib::actions::remove_client(&device, &config_path, id, &Actor::current_user())?;
 
     println!("Removed client with id: {id}");
     Ok(())
@@ -68,65 +283,652 @@ pub fn server_conf(config_path: Option<PathBuf>) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub fn client_conf(id: usize, config_path: Option<PathBuf>) -> anyhow::Result<()> {
+pub fn client_conf(
+    id: usize,
+    qr: bool,
+    format: ExportFormat,
+    output_dir: Option<PathBuf>,
+    config_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
     let config_path = defaults::prepare_config_path(config_path)?;
-    let server = Server::load_from_file(&config_path)?;
-    let conf = server.get_client_wg_config(id)?;
-    print!("{conf}");
+    if qr {
+        let server = Server::load_from_file(&config_path)?;
+        let conf = server.get_client_wg_config(id)?;
+        print!("{}", wglib::qr::render_text(&conf)?);
+        return Ok(());
+    }
+
+    let files = wglib::actions::export_client_config(&config_path, id, format)?;
+    match output_dir {
+        Some(output_dir) => {
+            std::fs::create_dir_all(&output_dir)?;
+            for file in files {
+                let path = output_dir.join(&file.name);
+                wglib::fs::create_private_file(&path)?.write_all(file.contents.as_bytes())?;
+                println!("Config written to {:?}", path);
+            }
+        }
+        None => print!("{}", export::join(&files)),
+    }
     Ok(())
 }
 
-pub fn list_clients(name: Option<String>, config_path: Option<PathBuf>) -> anyhow::Result<()> {
+pub fn list_clients(
+    name: Option<String>,
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    format: Option<OutputFormat>,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
     let config_path = defaults::prepare_config_path(config_path)?;
-    let server = Server::load_from_file(&config_path)?;
-    match name {
-        Some(name) => {
-            for client in server.clients.values().filter(|client| client.name == name) {
-                utils::print_client(client);
-            }
+    let format = defaults::prepare_output(format)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+
+    let mut clients = wglib::actions::get_status(&device, &config_path)?.clients;
+    if let Some(name) = name {
+        clients.retain(|client| client.name == name);
+    }
+    output::print_clients(format, &clients)
+}
+
+pub fn show_client(
+    id: usize,
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    format: Option<OutputFormat>,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let config_path = defaults::prepare_config_path(config_path)?;
+    let format = defaults::prepare_output(format)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+
+    let client = wglib::actions::get_client_status(&device, &config_path, id)?;
+    output::print_client(format, &client)
+}
+
+pub fn status(
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    format: Option<OutputFormat>,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let config_path = defaults::prepare_config_path(config_path)?;
+    let format = defaults::prepare_output(format)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+
+    let status = wglib::actions::get_status(&device, &config_path)?;
+    output::print_status(format, &status)
+}
+
+pub fn tui(device: Option<String>, config_path: Option<PathBuf>) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let config_path = defaults::prepare_config_path(config_path)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+
+    crate::tui::run(device, config_path)
+}
+
+/// Redraws the status every `interval` seconds until interrupted.
+pub fn watch_status(
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    interval: u64,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let config_path = defaults::prepare_config_path(config_path)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+
+    let interval = Duration::from_secs(interval.max(1));
+    let mut previous: Option<(Instant, BTreeMap<usize, ClientStatus>)> = None;
+    loop {
+        let status = wglib::actions::get_status(&device, &config_path)?;
+        let now = Instant::now();
+        let throughput = match &previous {
+            Some((sampled_at, before)) => status
+                .clients
+                .iter()
+                .filter_map(|client| {
+                    let before = before.get(&client.id)?;
+                    let throughput = Throughput::between(before, client, now - *sampled_at);
+                    Some((client.id, throughput))
+                })
+                .collect(),
+            None => BTreeMap::new(),
+        };
+
+        // Clear the screen and move the cursor to the top left corner.
+        print!("\x1b[2J\x1b[H");
+        output::print_live_status(&status, &throughput);
+        println!();
+        println!(
+            "Refreshing every {}s, press Ctrl-C to exit",
+            interval.as_secs()
+        );
+        std::io::stdout().flush()?;
+
+        let clients = status
+            .clients
+            .into_iter()
+            .map(|client| (client.id, client))
+            .collect();
+        previous = Some((now, clients));
+        std::thread::sleep(interval);
+    }
+}
+
+pub fn start(
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let config_path = defaults::prepare_config_path(config_path)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+    if dry_run {
+        return preview(&device, &config_path, Operation::Up);
+    }
+
+    wglib::actions::up(&device, &config_path, &Actor::current_user())?;
+
+    println!("Device {} started", device);
+    Ok(())
+}
+
+pub fn stop(
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let config_path = defaults::prepare_config_path(config_path)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+    if dry_run {
+        return preview(&device, &config_path, Operation::Down);
+    }
+
+    wglib::actions::down(&device, &config_path, &Actor::current_user())?;
+
+    println!("Device {} stopped", device);
+    Ok(())
+}
+
+pub fn restart(
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let config_path = defaults::prepare_config_path(config_path)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+    if dry_run {
+        return preview(&device, &config_path, Operation::Reboot);
+    }
+
+    wglib::actions::reboot(&device, &config_path, &Actor::current_user())?;
+
+    println!("Device {} restarted", device);
+    Ok(())
+}
+
+pub fn reload(
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let config_path = defaults::prepare_config_path(config_path)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+    if dry_run {
+        return preview(&device, &config_path, Operation::Reload);
+    }
+
+    let diff = wglib::actions::reload(&device, &config_path, &Actor::current_user())?;
+
+    if diff.is_empty() {
+        println!("Device {} is up to date", device);
+    } else {
+        println!(
+            "Device {} reloaded: {} added, {} removed, {} updated",
+            device,
+            diff.added.len(),
+            diff.removed.len(),
+            diff.updated.len()
+        );
+    }
+    Ok(())
+}
+
+pub fn diff(
+    repair: Option<RepairDirection>,
+    check: bool,
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    dry_run: bool,
+    yes: bool,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let config_path = defaults::prepare_config_path(config_path)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+
+    let drift = match repair {
+        Some(direction) if dry_run => {
+            return preview(&device, &config_path, Operation::RepairDrift(direction));
         }
-        None => {
-            for client in server.clients.values() {
-                utils::print_client(client);
+        Some(direction) => {
+            if direction == RepairDirection::Config && !yes {
+                confirm_config_repair(&device, &config_path)?;
             }
+            let drift = wglib::actions::repair_drift(
+                &device,
+                &config_path,
+                direction,
+                &Actor::current_user(),
+            )?;
+            if !drift.is_empty() {
+                println!(
+                    "Some differences couldn't be repaired towards the {:?}:",
+                    direction
+                );
+            }
+            drift
         }
+        None => wglib::actions::get_drift(&device, &config_path)?,
+    };
+
+    if drift.is_empty() {
+        println!("Config and device {} are in sync", device);
+        return Ok(());
+    }
+    utils::print_drift(&drift);
+    if check {
+        return Err(anyhow!("config and device {} have drifted", device));
+    }
+    Ok(())
+}
+
+/// Repairing towards the interface removes clients from the config, so they're listed and the
+/// user has to agree first.
+fn confirm_config_repair(device: &str, config_path: &Path) -> anyhow::Result<()> {
+    let drift = wglib::actions::get_drift(device, config_path)?;
+    let clients: Vec<_> = drift
+        .config_only
+        .iter()
+        .filter_map(|peer| Some((peer.client_id?, peer.client_name.as_deref()?)))
+        .collect();
+    if clients.is_empty() {
+        return Ok(());
+    }
+    println!(
+        "These clients are missing from device {} and will be removed:",
+        device
+    );
+    for (id, name) in clients {
+        println!("{}\t{}", id, name);
+    }
+    if !utils::confirm("Remove them from the config? (y/n)")? {
+        return Err(anyhow!(
+            "Nothing was repaired, pass --yes to repair without asking"
+        ));
     }
     Ok(())
 }
 
-pub fn start(device: Option<String>, config_path: Option<PathBuf>) -> anyhow::Result<()> {
+pub fn doctor(device: Option<String>, config_path: Option<PathBuf>) -> anyhow::Result<()> {
     let device = defaults::prepare_device(device);
     let config_path = defaults::prepare_config_path(config_path)?;
 
     let config_path = utils::get_config_path_with_sudo(config_path)?;
 
-    let server = Server::load_from_file(&config_path)?;
-    dump_wg_config(&server, &device)?;
-    start_wg(&device)?;
+    let checks = wglib::doctor::run(&device, &config_path);
+    for check in &checks {
+        utils::print_check(check);
+    }
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Fail)
+        .count();
+    if failed > 0 {
+        return Err(anyhow!("{} of {} checks failed", failed, checks.len()));
+    }
+    Ok(())
+}
 
-    println!("Device {} started", device);
+pub fn install_service(
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    http_tokens: Option<Vec<String>>,
+    bot: Option<(String, i64)>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let config_path = defaults::prepare_config_path(config_path)?;
+
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+
+    let http = match http_tokens {
+        Some(tokens) => Some(HttpService {
+            binary: find_binary("wghttp")?,
+            tokens,
+        }),
+        None => None,
+    };
+    let bot = match bot {
+        Some((token, admin_id)) => Some(BotService {
+            binary: find_binary("wgbot")?,
+            token,
+            admin_id,
+        }),
+        None => None,
+    };
+    let options = ServiceOptions {
+        device,
+        config_path,
+        http,
+        bot,
+    };
+    if dry_run {
+        let (device, config_path) = (options.device.clone(), options.config_path.clone());
+        return preview(&device, &config_path, Operation::InstallService(options));
+    }
+    for path in wglib::actions::install_service(&options, &Actor::current_user())? {
+        println!("Wrote {}", path.display());
+    }
+    println!("{} starts on boot", options.device);
     Ok(())
 }
 
-pub fn stop(device: Option<String>) -> anyhow::Result<()> {
+pub fn uninstall_service(
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
     let device = defaults::prepare_device(device);
-    sudo::escalate_if_needed().map_err(|err| anyhow!("{err}"))?;
-    stop_wg(&device)?;
+    let config_path = defaults::prepare_config_path(config_path)?;
 
-    println!("Device {} stopped", device);
+    let config_path = utils::get_config_path_with_sudo(config_path)?;
+    if dry_run {
+        return preview(&device, &config_path, Operation::UninstallService);
+    }
+
+    let removed = wglib::actions::uninstall_service(&device, &config_path, &Actor::current_user())?;
+    for path in removed {
+        println!("Removed {}", path.display());
+    }
+    println!(
+        "{} no longer starts on boot, it keeps running until stopped",
+        device
+    );
     Ok(())
 }
 
-pub fn restart(device: Option<String>, config_path: Option<PathBuf>) -> anyhow::Result<()> {
+fn find_binary(name: &str) -> anyhow::Result<PathBuf> {
+    service::find_binary(name)
+        .ok_or_else(|| anyhow!("{} not found, install it next to wgtool or in PATH", name))
+}
+
+pub fn collect(
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    history_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
     let device = defaults::prepare_device(device);
     let config_path = defaults::prepare_config_path(config_path)?;
 
     let config_path = utils::get_config_path_with_sudo(config_path)?;
+    let history_path = defaults::prepare_history_path(history_path, &config_path);
 
-    let server = Server::load_from_file(&config_path)?;
-    dump_wg_config(&server, &device)?;
-    restart_wg(&device)?;
+    let events = wglib::actions::collect_history(&device, &config_path, &history_path)?;
+    for event in events {
+        println!("{}\t{}\t{}", event.client_id, event.client_name, event.kind);
+    }
+    Ok(())
+}
 
-    println!("Device {} restarted", device);
+pub fn history(
+    client: Option<usize>,
+    bucket: Bucket,
+    hours: u64,
+    config_path: Option<PathBuf>,
+    history_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let config_path = defaults::prepare_config_path(config_path)?;
+    let history_path = defaults::prepare_history_path(history_path, &config_path);
+
+    let to = history::unix_now()?;
+    let from = to.saturating_sub(hours * 60 * 60);
+    let points =
+        wglib::actions::get_traffic_history(&config_path, &history_path, client, from, to, bucket)?;
+    for point in points {
+        utils::print_traffic_point(&point);
+    }
+    Ok(())
+}
+
+pub fn sessions(
+    id: usize,
+    limit: usize,
+    config_path: Option<PathBuf>,
+    history_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let config_path = defaults::prepare_config_path(config_path)?;
+    let history_path = defaults::prepare_history_path(history_path, &config_path);
+
+    let sessions = wglib::actions::get_sessions(&config_path, &history_path, id, limit)?;
+    for session in sessions {
+        utils::print_session(&session);
+    }
+    Ok(())
+}
+
+pub fn audit(
+    client: Option<usize>,
+    action: Option<String>,
+    hours: Option<u64>,
+    limit: Option<usize>,
+    config_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let config_path = defaults::prepare_config_path(config_path)?;
+
+    let since = match hours {
+        Some(hours) => Some(history::unix_now()?.saturating_sub(hours * 60 * 60)),
+        None => None,
+    };
+    let filter = AuditFilter {
+        action,
+        client_id: client,
+        since,
+        limit,
+    };
+    for entry in wglib::actions::get_audit_log(&config_path, &filter)? {
+        utils::print_audit_entry(&entry);
+    }
+    Ok(())
+}
+
+pub fn convert_storage(
+    output: PathBuf,
+    format: Option<StorageKind>,
+    config_path: Option<PathBuf>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let config_path = defaults::prepare_config_path(config_path)?;
+    let format = match format {
+        Some(format) => format,
+        None => storage::detect(&output)?,
+    };
+    if dry_run {
+        let operation = Operation::ConvertStorage {
+            output,
+            kind: format,
+        };
+        return preview(&defaults::prepare_device(None), &config_path, operation);
+    }
+
+    wglib::actions::convert_storage(&config_path, &output, format)?;
+
+    println!("Converted {:?} to {:?} ({:?})", config_path, output, format);
+    Ok(())
+}
+
+pub fn backup(
+    output: Option<PathBuf>,
+    config_path: Option<PathBuf>,
+    history_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let config_path = defaults::prepare_config_path(config_path)?;
+    let history_path = defaults::prepare_history_path(history_path, &config_path);
+    let output = match output {
+        Some(output) => output,
+        None => format!("wgsuite-backup-{}.tar.gz", history::unix_now()?).into(),
+    };
+
+    let file = wglib::fs::create_private_file(&output)?;
+    let manifest = wglib::actions::backup(&config_path, &history_path, file)?;
+
+    for file in manifest.files {
+        println!("{}\t{}\t{}", file.name, file.size, file.sha256);
+    }
+    println!("Created backup at: {:?}", output);
+    Ok(())
+}
+
+/// Flags of `wgtool restore`.
+pub struct RestoreOptions {
+    pub regenerate: bool,
+    pub restart: bool,
+    pub dry_run: bool,
+}
+
+pub fn restore(
+    archive: PathBuf,
+    options: RestoreOptions,
+    device: Option<String>,
+    config_path: Option<PathBuf>,
+    history_path: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let device = defaults::prepare_device(device);
+    let mut config_path = defaults::prepare_config_path(config_path)?;
+    let archive = std::fs::canonicalize(archive)?;
+
+    if options.regenerate || options.restart {
+        config_path = utils::get_config_path_with_sudo(config_path)?;
+    }
+    let history_path = defaults::prepare_history_path(history_path, &config_path);
+
+    if options.dry_run {
+        let operation = Operation::Restore {
+            archive: std::fs::read(&archive)?,
+            history_path,
+            regenerate: options.regenerate,
+            restart: options.restart,
+        };
+        return preview(&device, &config_path, operation);
+    }
+    let file = std::fs::File::open(&archive)?;
+    let manifest = wglib::actions::restore(
+        &device,
+        &config_path,
+        &history_path,
+        file,
+        options.regenerate,
+        options.restart,
+        &Actor::current_user(),
+    )?;
+
+    println!(
+        "Restored backup created at {}",
+        utils::format_timestamp(manifest.created_at)
+    );
+    Ok(())
+}
+
+pub fn use_context(name: String) -> anyhow::Result<()> {
+    let path = defaults::context_file_path()?;
+    let mut config = ContextConfig::load(&path)?;
+    config.set_current(&name)?;
+    config.save(&path)?;
+    println!("Switched to context {}", name);
+    Ok(())
+}
+
+pub fn list_contexts() -> anyhow::Result<()> {
+    let path = defaults::context_file_path()?;
+    let config = ContextConfig::load(&path)?;
+    if config.contexts.is_empty() {
+        println!("No contexts in {:?}", path);
+        return Ok(());
+    }
+    for (name, context) in &config.contexts {
+        let marker = if config.current.as_ref() == Some(name) {
+            "*"
+        } else {
+            " "
+        };
+        let target = match &context.url {
+            Some(url) => url.clone(),
+            None => format!(
+                "device {}, config {}",
+                context.device.as_deref().unwrap_or("default"),
+                context
+                    .config_path
+                    .as_ref()
+                    .map_or("default".to_string(), |path| path.display().to_string())
+            ),
+        };
+        println!("{} {}  {}", marker, name, target);
+    }
+    Ok(())
+}
+
+/// Creates the context `name` or overwrites the values given in `changes`.
+pub fn set_context(name: String, changes: Context) -> anyhow::Result<()> {
+    let path = defaults::context_file_path()?;
+    let mut config = ContextConfig::load(&path)?;
+    let created = !config.contexts.contains_key(&name);
+    let context = config.contexts.entry(name.clone()).or_default();
+    context.device = changes.device.or(context.device.take());
+    context.config_path = changes.config_path.or(context.config_path.take());
+    context.output = changes.output.or(context.output.take());
+    context.url = changes.url.or(context.url.take());
+    context.token = changes.token.or(context.token.take());
+    context
+        .validate()
+        .map_err(|err| anyhow!("context {}: {}", name, err))?;
+    config.save(&path)?;
+
+    if created {
+        println!("Created context {} in {:?}", name, path);
+    } else {
+        println!("Updated context {}", name);
+    }
+    Ok(())
+}
+
+pub fn remove_context(name: String) -> anyhow::Result<()> {
+    let path = defaults::context_file_path()?;
+    let mut config = ContextConfig::load(&path)?;
+    config.get(&name)?;
+    config.contexts.remove(&name);
+    if config.current.as_ref() == Some(&name) {
+        config.current = None;
+    }
+    config.save(&path)?;
+    println!("Removed context {}", name);
+    Ok(())
+}
+
+fn preview(device: &str, config_path: &Path, operation: Operation) -> anyhow::Result<()> {
+    let plan = wglib::actions::dry_run(device, config_path, operation)?;
+    utils::print_plan(&plan);
     Ok(())
 }
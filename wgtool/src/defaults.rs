@@ -1,13 +1,28 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
 
 use anyhow::anyhow;
 use ipnet::Ipv4Net;
-use pnet::datalink::NetworkInterface;
+use wglib::{
+    context::{Context, ContextConfig},
+    net,
+};
+
+use crate::output::OutputFormat;
 
 const DEFAULT_DEVICE: &str = "wg0";
 const DEFAULT_PORT: u16 = 51820;
 const DEFAULT_SUBNET: &str = "10.0.0.0/24";
 const DEFAULT_CONFIG_FILENAME: &str = ".wg";
+const CONTEXT_FILENAME: &str = "wgsuite/config.toml";
+/// Overrides the location of the context file. It's also set to the file in use, so commands
+/// re-run with sudo keep the contexts of the user.
+pub const CONTEXT_FILE_ENV: &str = "WGSUITE_CONFIG";
+
+/// Context whose values are used instead of the built-in defaults.
+static CONTEXT: OnceLock<Context> = OnceLock::new();
 
 pub fn prepare_subnet(subnet: Option<String>) -> anyhow::Result<Ipv4Net> {
     Ok(match subnet {
@@ -22,19 +37,19 @@ pub fn prepare_subnet(subnet: Option<String>) -> anyhow::Result<Ipv4Net> {
 pub fn prepare_endpoint(endpoint: Option<String>) -> anyhow::Result<String> {
     Ok(match endpoint {
         Some(endpoint) => endpoint,
-        None => match get_default_interface() {
-            Some(network_interface) => {
-                match network_interface.ips.iter().find(|&addr| addr.is_ipv4()) {
-                    Some(net) => {
-                        let addr = net.ip().to_string();
-                        println!("Using default public ip address: {}", addr);
-                        addr
-                    }
-                    None => return Err(anyhow!("Failed to get public ip address")),
-                }
+        None => {
+            let uplink = net::detect_uplink()
+                .map_err(|err| anyhow!("Failed to get public ip address: {}", err))?;
+            if net::is_global(uplink.address) {
+                println!("Using default public ip address: {}", uplink.address);
+            } else {
+                println!(
+                    "Using default ip address: {}, it's private so clients outside of its network need port forwarding or --endpoint",
+                    uplink.address
+                );
             }
-            None => return Err(anyhow!("Failed to get public ip address")),
-        },
+            uplink.address.to_string()
+        }
     })
 }
 
@@ -51,28 +66,24 @@ pub fn prepare_port(port: Option<u16>) -> u16 {
 pub fn prepare_network_interface(network_interface: Option<String>) -> anyhow::Result<String> {
     Ok(match network_interface {
         Some(network_interface) => network_interface,
-        None => match get_default_interface() {
-            Some(network_interface) => {
-                println!(
-                    "Using default network interface: {}",
-                    network_interface.name
-                );
-                network_interface.name
-            }
-            None => return Err(anyhow!("Failed to get default network interface")),
-        },
+        None => {
+            let uplink = net::detect_uplink()
+                .map_err(|err| anyhow!("Failed to get default network interface: {}", err))?;
+            println!("Using default network interface: {}", uplink.interface);
+            uplink.interface
+        }
     })
 }
 
 pub fn prepare_device(device: Option<String>) -> String {
-    match device {
+    match device.or_else(|| CONTEXT.get()?.device.clone()) {
         Some(device) => device,
         None => DEFAULT_DEVICE.to_string(),
     }
 }
 
 pub fn prepare_config_path(config_path: Option<PathBuf>) -> anyhow::Result<PathBuf> {
-    match config_path {
+    match config_path.or_else(|| CONTEXT.get()?.config_path.clone()) {
         Some(path) => Ok(path),
         None => {
             let home_path = dirs::home_dir().ok_or_else(|| {
@@ -83,12 +94,49 @@ pub fn prepare_config_path(config_path: Option<PathBuf>) -> anyhow::Result<PathB
     }
 }
 
-fn get_default_interface() -> Option<NetworkInterface> {
-    pnet::datalink::interfaces().into_iter().find(|e| {
-        if !e.is_up() || e.is_loopback() || e.mac.is_none() {
-            return false;
-        }
+pub fn prepare_output(output: Option<OutputFormat>) -> anyhow::Result<OutputFormat> {
+    match output {
+        Some(output) => Ok(output),
+        None => match CONTEXT.get().and_then(|context| context.output.as_ref()) {
+            Some(output) => output.parse(),
+            None => Ok(OutputFormat::Table),
+        },
+    }
+}
 
-        e.ips.iter().any(|i| i.is_ipv4())
-    })
+pub fn context_file_path() -> anyhow::Result<PathBuf> {
+    if let Some(path) = std::env::var_os(CONTEXT_FILE_ENV) {
+        return Ok(path.into());
+    }
+    let config_dir = dirs::config_dir().ok_or_else(|| {
+        anyhow!("Failed to get config path, make sure the $HOME env variable is set")
+    })?;
+    Ok(config_dir.join(CONTEXT_FILENAME))
+}
+
+/// Loads the context named `name`, or the current one, and makes it the defaults of all
+/// `prepare_*` functions. Returns its name and values, `None` if no context is used.
+pub fn load_context(name: Option<&str>) -> anyhow::Result<Option<(String, Context)>> {
+    let path = context_file_path()?;
+    std::env::set_var(CONTEXT_FILE_ENV, &path);
+    let config = ContextConfig::load(&path)?;
+    let Some((name, context)) = config.resolve(name)? else {
+        return Ok(None);
+    };
+    if let Some(output) = &context.output {
+        output
+            .parse::<OutputFormat>()
+            .map_err(|err| anyhow!("context {}: {}", name, err))?;
+    }
+    CONTEXT
+        .set(context.clone())
+        .map_err(|_| anyhow!("A context is already loaded"))?;
+    Ok(Some((name.to_string(), context.clone())))
+}
+
+pub fn prepare_history_path(history_path: Option<PathBuf>, config_path: &Path) -> PathBuf {
+    match history_path {
+        Some(path) => path,
+        None => wglib::history::default_path(config_path),
+    }
 }
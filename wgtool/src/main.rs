@@ -1,13 +1,37 @@
 mod commands;
 mod defaults;
+mod output;
+mod remote;
+mod tui;
 mod utils;
+mod wizard;
 
-use std::path::PathBuf;
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+};
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use ipnet::{IpNet, Ipv4Net};
+use wglib::{
+    context::Context,
+    export::ExportFormat,
+    history::Bucket,
+    model::{
+        client::{ClientUpdate, Routing},
+        pool::AddressRange,
+    },
+    storage::StorageKind,
+    sync::RepairDirection,
+};
+
+use output::OutputFormat;
 
 #[derive(Parser)]
 struct Cli {
+    /// Context of ~/.config/wgsuite/config.toml to use instead of the current one
+    #[arg(long, global = true)]
+    context: Option<String>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -23,15 +47,93 @@ enum Commands {
         port: Option<u16>,
         #[arg(long)]
         interface: Option<String>,
+        /// DNS servers of clients, 8.8.8.8 by default
+        #[arg(long)]
+        dns: Vec<IpAddr>,
+        /// Route all traffic of clients through the VPN (full) or only the VPN subnet (split)
+        #[arg(long)]
+        routing: Option<Routing>,
         #[arg(short, long)]
         output: Option<PathBuf>,
+        /// Don't ask for missing settings even in a terminal
+        #[arg(long)]
+        non_interactive: bool,
     },
     AddClient {
         name: String,
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    ResizeSubnet {
+        /// New subnet, e.g. 10.0.0.0/23
+        subnet: Ipv4Net,
+        /// Zip archive to write the new configs of renumbered clients to
+        #[arg(short, long, default_value = "renumbered.zip")]
+        output: PathBuf,
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    Pool {
+        /// First address given to clients
+        #[arg(long)]
+        start: Option<Ipv4Addr>,
+        /// Last address given to clients
+        #[arg(long)]
+        end: Option<Ipv4Addr>,
+        /// Give out the whole subnet again
+        #[arg(long, conflicts_with_all = ["start", "end"])]
+        reset_range: bool,
+        /// Keep an address or range from clients, e.g. 10.0.0.10-10.0.0.20 or 10.0.0.16/28
+        #[arg(long = "reserve")]
+        reserve: Vec<AddressRange>,
+        /// Remove a previously reserved address or range
+        #[arg(long = "unreserve")]
+        unreserve: Vec<AddressRange>,
+        #[arg(long)]
+        clear_reserved: bool,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    ImportClients {
+        /// CSV file with `name,owner,tags,ip` columns, or a JSON array of such objects
+        file: PathBuf,
+        /// Zip archive to write the configs of the new clients to
+        #[arg(short, long, default_value = "clients.zip")]
+        output: PathBuf,
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    EditClient {
+        id: usize,
+        #[command(flatten)]
+        update: ClientUpdateArgs,
         #[arg(long)]
         device: Option<String>,
         #[arg(long)]
         config_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
     },
     RemoveClient {
         id: usize,
@@ -39,6 +141,9 @@ enum Commands {
         device: Option<String>,
         #[arg(long)]
         config_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
     },
     ServerConf {
         #[arg(long)]
@@ -46,12 +151,57 @@ enum Commands {
     },
     ClientConf {
         id: usize,
+        /// Print the config as a QR code for scanning with the mobile app
+        #[arg(long, conflicts_with_all = ["format", "output_dir"])]
+        qr: bool,
+        /// wg-quick, nmconnection, networkd, openwrt, mikrotik or json
+        #[arg(long, default_value = "wg-quick")]
+        format: ExportFormat,
+        /// Write the exported files into this directory instead of printing them
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
         #[arg(long)]
         config_path: Option<PathBuf>,
     },
     ListClients {
         #[arg(long)]
         name: Option<String>,
+        /// table, json or csv, table by default
+        #[arg(long)]
+        output: Option<OutputFormat>,
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+    },
+    ShowClient {
+        id: usize,
+        /// table, json or csv, table by default
+        #[arg(long)]
+        output: Option<OutputFormat>,
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+    },
+    Status {
+        /// table, json or csv, table by default
+        #[arg(long)]
+        output: Option<OutputFormat>,
+        /// Refresh the table periodically and show current throughput
+        #[arg(long, conflicts_with = "output")]
+        watch: bool,
+        /// Seconds between refreshes
+        #[arg(long, default_value_t = 2, requires = "watch")]
+        interval: u64,
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+    },
+    Tui {
+        #[arg(long)]
+        device: Option<String>,
         #[arg(long)]
         config_path: Option<PathBuf>,
     },
@@ -60,51 +210,502 @@ enum Commands {
         device: Option<String>,
         #[arg(long)]
         config_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
     },
     Stop {
         #[arg(long)]
         device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
     },
     Restart {
         #[arg(long)]
         device: Option<String>,
         #[arg(long)]
         config_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    Reload {
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    Diff {
+        /// Make the `interface` match the config or the `config` match the interface
+        #[arg(long)]
+        repair: Option<RepairDirection>,
+        /// Exit with an error if the config and the interface differ
+        #[arg(long)]
+        check: bool,
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long, requires = "repair")]
+        dry_run: bool,
+        /// Remove clients missing from the interface without asking
+        #[arg(long, requires = "repair")]
+        yes: bool,
+    },
+    Doctor {
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
     },
+    InstallService {
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        /// Also install a wghttp service
+        #[arg(long)]
+        http: bool,
+        /// Bearer token of the wghttp service in `name:secret` format, can be repeated
+        #[arg(long, requires = "http")]
+        http_token: Vec<String>,
+        /// Install a wgbot service with this Telegram token
+        #[arg(long, requires = "bot_admin_id")]
+        bot_token: Option<String>,
+        /// Telegram id of the wgbot admin
+        #[arg(long, requires = "bot_token")]
+        bot_admin_id: Option<i64>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    UninstallService {
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    Collect {
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        #[arg(long)]
+        history_path: Option<PathBuf>,
+    },
+    History {
+        #[arg(long)]
+        client: Option<usize>,
+        #[arg(long, default_value = "hour")]
+        bucket: Bucket,
+        #[arg(long, default_value_t = 24)]
+        hours: u64,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        #[arg(long)]
+        history_path: Option<PathBuf>,
+    },
+    Audit {
+        #[arg(long)]
+        client: Option<usize>,
+        #[arg(long)]
+        action: Option<String>,
+        #[arg(long)]
+        hours: Option<u64>,
+        #[arg(long)]
+        limit: Option<usize>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+    },
+    Backup {
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        #[arg(long)]
+        history_path: Option<PathBuf>,
+    },
+    Restore {
+        archive: PathBuf,
+        /// Rewrite /etc/wireguard/<device>.conf from the restored config
+        #[arg(long)]
+        regenerate: bool,
+        /// Regenerate the wireguard config and (re)start the interface
+        #[arg(long)]
+        restart: bool,
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        #[arg(long)]
+        history_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    ConvertStorage {
+        output: PathBuf,
+        #[arg(long)]
+        format: Option<StorageKind>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    Sessions {
+        id: usize,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        #[arg(long)]
+        history_path: Option<PathBuf>,
+    },
+    Context {
+        #[command(subcommand)]
+        command: ContextCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum ContextCommands {
+    /// Make a context the current one
+    Use {
+        name: String,
+    },
+    /// List all contexts, the current one is marked with `*`
+    List,
+    /// Create a context or change the given values of an existing one
+    Set {
+        name: String,
+        #[arg(long)]
+        device: Option<String>,
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+        /// table, json or csv
+        #[arg(long)]
+        output: Option<OutputFormat>,
+        /// URL of a wghttp server to run commands through
+        #[arg(long)]
+        url: Option<String>,
+        /// Bearer token of the wghttp server
+        #[arg(long)]
+        token: Option<String>,
+    },
+    Remove {
+        name: String,
+    },
+}
+
+/// Changes of `edit-client`.
+#[derive(Args)]
+struct ClientUpdateArgs {
+    #[arg(long)]
+    name: Option<String>,
+    /// Replace the tags of the client
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+    #[arg(long, conflicts_with = "tags")]
+    clear_tags: bool,
+    /// Person the client was issued to, an empty string removes it
+    #[arg(long)]
+    owner: Option<String>,
+    /// DNS servers of the client
+    #[arg(long)]
+    dns: Vec<IpAddr>,
+    /// Go back to the default DNS server
+    #[arg(long, conflicts_with = "dns")]
+    reset_dns: bool,
+    /// Networks routed through the tunnel by the client
+    #[arg(long)]
+    allowed_ips: Vec<IpNet>,
+    /// Go back to routing everything through the tunnel
+    #[arg(long, conflicts_with = "allowed_ips")]
+    reset_allowed_ips: bool,
+    /// Keepalive interval in seconds, 0 disables it
+    #[arg(long)]
+    keepalive: Option<u16>,
+    /// Remove the client from the interface, keeping its keys
+    #[arg(long)]
+    disable: bool,
+    #[arg(long, conflicts_with = "disable")]
+    enable: bool,
+}
+
+impl ClientUpdateArgs {
+    fn into_update(self) -> ClientUpdate {
+        ClientUpdate {
+            name: self.name,
+            tags: (self.clear_tags || !self.tags.is_empty()).then_some(self.tags),
+            owner: self.owner,
+            dns: (self.reset_dns || !self.dns.is_empty()).then_some(self.dns),
+            allowed_ips: (self.reset_allowed_ips || !self.allowed_ips.is_empty())
+                .then_some(self.allowed_ips),
+            persistent_keepalive: self.keepalive,
+            disabled: (self.disable || self.enable).then_some(self.disable),
+        }
+    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
+    if !matches!(cli.command, Commands::Context { .. }) {
+        if let Some((name, context)) = defaults::load_context(cli.context.as_deref())? {
+            if let Some(remote) = remote::Remote::new(&name, &context) {
+                return Ok(remote::run(&remote, cli.command)?);
+            }
+        }
+    }
     match cli.command {
         Commands::Init {
             subnet,
             endpoint,
             port,
             interface,
+            dns,
+            routing,
             output,
-        } => commands::init(subnet, endpoint, port, interface, output)?,
+            non_interactive,
+        } => {
+            let options = commands::InitOptions {
+                subnet,
+                endpoint,
+                port,
+                network_interface: interface,
+                dns,
+                routing,
+            };
+            commands::init(options, output, non_interactive)?
+        }
         Commands::AddClient {
             name,
+            tags,
             device,
             config_path,
-        } => commands::add_client(name, device, config_path)?,
+            dry_run,
+        } => commands::add_client(name, tags, device, config_path, dry_run)?,
+        Commands::ResizeSubnet {
+            subnet,
+            output,
+            device,
+            config_path,
+            dry_run,
+        } => commands::resize_subnet(subnet, output, device, config_path, dry_run)?,
+        Commands::Pool {
+            start,
+            end,
+            reset_range,
+            reserve,
+            unreserve,
+            clear_reserved,
+            config_path,
+            dry_run,
+        } => {
+            let changes = commands::PoolChanges {
+                start,
+                end,
+                reset_range,
+                reserve,
+                unreserve,
+                clear_reserved,
+            };
+            commands::pool(changes, config_path, dry_run)?
+        }
+        Commands::ImportClients {
+            file,
+            output,
+            device,
+            config_path,
+            dry_run,
+        } => commands::import_clients(file, output, device, config_path, dry_run)?,
+        Commands::EditClient {
+            id,
+            update,
+            device,
+            config_path,
+            dry_run,
+        } => commands::edit_client(id, update.into_update(), device, config_path, dry_run)?,
         Commands::RemoveClient {
             id,
             device,
             config_path,
-        } => commands::remove_client(id, device, config_path)?,
+            dry_run,
+        } => commands::remove_client(id, device, config_path, dry_run)?,
         Commands::ServerConf { config_path } => commands::server_conf(config_path)?,
-        Commands::ClientConf { id, config_path } => commands::client_conf(id, config_path)?,
-        Commands::ListClients { name, config_path } => commands::list_clients(name, config_path)?,
+        Commands::ClientConf {
+            id,
+            qr,
+            format,
+            output_dir,
+            config_path,
+        } => commands::client_conf(id, qr, format, output_dir, config_path)?,
+        Commands::ListClients {
+            name,
+            output,
+            device,
+            config_path,
+        } => commands::list_clients(name, device, config_path, output)?,
+        Commands::ShowClient {
+            id,
+            output,
+            device,
+            config_path,
+        } => commands::show_client(id, device, config_path, output)?,
+        Commands::Status {
+            output,
+            watch,
+            interval,
+            device,
+            config_path,
+        } => {
+            if watch {
+                commands::watch_status(device, config_path, interval)?
+            } else {
+                commands::status(device, config_path, output)?
+            }
+        }
+        Commands::Tui {
+            device,
+            config_path,
+        } => commands::tui(device, config_path)?,
         Commands::Start {
             device,
             config_path,
-        } => commands::start(device, config_path)?,
-        Commands::Stop { device } => commands::stop(device)?,
+            dry_run,
+        } => commands::start(device, config_path, dry_run)?,
+        Commands::Stop {
+            device,
+            config_path,
+            dry_run,
+        } => commands::stop(device, config_path, dry_run)?,
         Commands::Restart {
             device,
             config_path,
-        } => commands::restart(device, config_path)?,
+            dry_run,
+        } => commands::restart(device, config_path, dry_run)?,
+        Commands::Reload {
+            device,
+            config_path,
+            dry_run,
+        } => commands::reload(device, config_path, dry_run)?,
+        Commands::Diff {
+            repair,
+            check,
+            device,
+            config_path,
+            dry_run,
+            yes,
+        } => commands::diff(repair, check, device, config_path, dry_run, yes)?,
+        Commands::Doctor {
+            device,
+            config_path,
+        } => commands::doctor(device, config_path)?,
+        Commands::InstallService {
+            device,
+            config_path,
+            http,
+            http_token,
+            bot_token,
+            bot_admin_id,
+            dry_run,
+        } => {
+            let http_tokens = http.then_some(http_token);
+            let bot = bot_token.zip(bot_admin_id);
+            commands::install_service(device, config_path, http_tokens, bot, dry_run)?
+        }
+        Commands::UninstallService {
+            device,
+            config_path,
+            dry_run,
+        } => commands::uninstall_service(device, config_path, dry_run)?,
+        Commands::Collect {
+            device,
+            config_path,
+            history_path,
+        } => commands::collect(device, config_path, history_path)?,
+        Commands::History {
+            client,
+            bucket,
+            hours,
+            config_path,
+            history_path,
+        } => commands::history(client, bucket, hours, config_path, history_path)?,
+        Commands::Audit {
+            client,
+            action,
+            hours,
+            limit,
+            config_path,
+        } => commands::audit(client, action, hours, limit, config_path)?,
+        Commands::Backup {
+            output,
+            config_path,
+            history_path,
+        } => commands::backup(output, config_path, history_path)?,
+        Commands::Restore {
+            archive,
+            regenerate,
+            restart,
+            device,
+            config_path,
+            history_path,
+            dry_run,
+        } => commands::restore(
+            archive,
+            commands::RestoreOptions {
+                regenerate,
+                restart,
+                dry_run,
+            },
+            device,
+            config_path,
+            history_path,
+        )?,
+        Commands::ConvertStorage {
+            output,
+            format,
+            config_path,
+            dry_run,
+        } => commands::convert_storage(output, format, config_path, dry_run)?,
+        Commands::Sessions {
+            id,
+            limit,
+            config_path,
+            history_path,
+        } => commands::sessions(id, limit, config_path, history_path)?,
+        Commands::Context { command } => match command {
+            ContextCommands::Use { name } => commands::use_context(name)?,
+            ContextCommands::List => commands::list_contexts()?,
+            ContextCommands::Set {
+                name,
+                device,
+                config_path,
+                output,
+                url,
+                token,
+            } => {
+                let changes = Context {
+                    device,
+                    config_path,
+                    output: output.map(|output| output.to_string()),
+                    url,
+                    token,
+                };
+                commands::set_context(name, changes)?
+            }
+            ContextCommands::Remove { name } => commands::remove_context(name)?,
+        },
     };
     Ok(())
 }
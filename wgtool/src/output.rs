@@ -0,0 +1,247 @@
+use std::{collections::BTreeMap, fmt, io::Write, str::FromStr};
+
+use anyhow::anyhow;
+use serde::Serialize;
+use wglib::status::{ClientStatus, DeviceStatus, Throughput};
+
+use crate::utils;
+
+const CLIENT_COLUMNS: [&str; 11] = [
+    "id",
+    "name",
+    "owner",
+    "ip",
+    "public_key",
+    "tags",
+    "disabled",
+    "endpoint",
+    "latest_handshake",
+    "received_bytes",
+    "sent_bytes",
+];
+
+/// Format of read commands, selected with their `--output` option.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Self::Table),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => Err(anyhow!(
+                "unknown output format {}, expected table, json or csv",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Table => "table",
+            Self::Json => "json",
+            Self::Csv => "csv",
+        };
+        f.write_str(name)
+    }
+}
+
+pub fn print_clients(format: OutputFormat, clients: &[ClientStatus]) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Table => {
+            let rows: Vec<Vec<String>> = clients.iter().map(table_row).collect();
+            print_table(&CLIENT_COLUMNS, &rows);
+            Ok(())
+        }
+        OutputFormat::Json => print_json(&clients),
+        OutputFormat::Csv => print_csv(clients),
+    }
+}
+
+/// Prints one client, as `field: value` lines in table format.
+pub fn print_client(format: OutputFormat, client: &ClientStatus) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Table => {
+            let width = CLIENT_COLUMNS
+                .iter()
+                .map(|name| name.len())
+                .max()
+                .unwrap_or(0);
+            for (name, value) in CLIENT_COLUMNS.iter().zip(table_row(client)) {
+                println!(
+                    "{:width$}  {}",
+                    format!("{}:", name),
+                    value,
+                    width = width + 1
+                );
+            }
+            Ok(())
+        }
+        OutputFormat::Json => print_json(client),
+        OutputFormat::Csv => print_csv(std::slice::from_ref(client)),
+    }
+}
+
+/// Prints the device and its clients, CSV only has the clients.
+pub fn print_status(format: OutputFormat, status: &DeviceStatus) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Table => {
+            print_status_table(status, None);
+            Ok(())
+        }
+        OutputFormat::Json => print_json(status),
+        OutputFormat::Csv => print_csv(&status.clients),
+    }
+}
+
+/// Prints the status table with the current throughput of each client by id, clients without
+/// one are shown as idle.
+pub fn print_live_status(status: &DeviceStatus, throughput: &BTreeMap<usize, Throughput>) {
+    print_status_table(status, Some(throughput));
+}
+
+fn print_status_table(status: &DeviceStatus, throughput: Option<&BTreeMap<usize, Throughput>>) {
+    let state = if status.running { "running" } else { "stopped" };
+    println!("Device: {} ({})", status.device, state);
+    println!("Public key: {}", status.public_key);
+    println!("Listen port: {}", status.listen_port);
+    let online = status
+        .clients
+        .iter()
+        .filter(|client| utils::is_online(client))
+        .count();
+    println!("Clients: {} of {} online", online, status.clients.len());
+    println!();
+
+    let mut headers = vec![
+        "id",
+        "name",
+        "ip",
+        "endpoint",
+        "handshake",
+        "received",
+        "sent",
+    ];
+    if throughput.is_some() {
+        headers.extend(["rx/s", "tx/s"]);
+    }
+    let rows: Vec<Vec<String>> = status
+        .clients
+        .iter()
+        .map(|client| {
+            let name = if client.disabled {
+                format!("{} (disabled)", client.name)
+            } else {
+                client.name.clone()
+            };
+            let mut row = vec![
+                client.id.to_string(),
+                name,
+                client.ip.to_string(),
+                or_dash(client.endpoint.map(|e| e.to_string()).unwrap_or_default()),
+                utils::format_handshake(client.latest_handshake),
+                utils::format_bytes(client.received_bytes),
+                utils::format_bytes(client.sent_bytes),
+            ];
+            if let Some(throughput) = throughput {
+                let current = throughput.get(&client.id).copied().unwrap_or_default();
+                row.push(utils::format_rate(current.received));
+                row.push(utils::format_rate(current.sent));
+            }
+            row
+        })
+        .collect();
+    print_table(&headers, &rows);
+}
+
+fn table_row(client: &ClientStatus) -> Vec<String> {
+    vec![
+        client.id.to_string(),
+        client.name.clone(),
+        or_dash(client.owner.clone().unwrap_or_default()),
+        client.ip.to_string(),
+        client.public_key.clone(),
+        or_dash(client.tags.join(",")),
+        if client.disabled { "yes" } else { "no" }.to_string(),
+        or_dash(client.endpoint.map(|e| e.to_string()).unwrap_or_default()),
+        match client.latest_handshake {
+            Some(timestamp) => utils::format_timestamp(timestamp),
+            None => "never".to_string(),
+        },
+        client.received_bytes.to_string(),
+        client.sent_bytes.to_string(),
+    ]
+}
+
+fn or_dash(value: String) -> String {
+    if value.is_empty() {
+        "-".to_string()
+    } else {
+        value
+    }
+}
+
+/// Left-aligned columns separated by two spaces.
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let headers: Vec<String> = headers.iter().map(|header| header.to_uppercase()).collect();
+    print_row(&headers, &widths);
+    for row in rows {
+        print_row(row, &widths);
+    }
+}
+
+fn print_row(cells: &[String], widths: &[usize]) {
+    let line: Vec<String> = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| format!("{:width$}", cell, width = width))
+        .collect();
+    println!("{}", line.join("  ").trim_end());
+}
+
+fn print_json(value: &impl Serialize) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Raw values, with tags separated by `;` like `import-clients` expects them.
+fn print_csv(clients: &[ClientStatus]) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_writer(std::io::stdout());
+    writer.write_record(CLIENT_COLUMNS)?;
+    for client in clients {
+        writer.write_record([
+            client.id.to_string(),
+            client.name.clone(),
+            client.owner.clone().unwrap_or_default(),
+            client.ip.to_string(),
+            client.public_key.clone(),
+            client.tags.join(";"),
+            client.disabled.to_string(),
+            client.endpoint.map(|e| e.to_string()).unwrap_or_default(),
+            client
+                .latest_handshake
+                .map(|timestamp| timestamp.to_string())
+                .unwrap_or_default(),
+            client.received_bytes.to_string(),
+            client.sent_bytes.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    std::io::stdout().flush()?;
+    Ok(())
+}
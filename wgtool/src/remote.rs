@@ -0,0 +1,248 @@
+//! Runs commands through the API of a wghttp server for contexts with a `url`.
+
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use serde_json::{json, Value};
+use wglib::{context::Context, plan::Plan, status::DeviceStatus, sync::PeerDiff};
+
+use crate::{defaults, output, utils, Commands};
+
+pub struct Remote {
+    name: String,
+    url: String,
+    token: Option<String>,
+}
+
+impl Remote {
+    /// Returns `None` for local contexts.
+    pub fn new(name: &str, context: &Context) -> Option<Self> {
+        Some(Self {
+            name: name.to_string(),
+            url: context.url.as_ref()?.trim_end_matches('/').to_string(),
+            token: context.token.clone(),
+        })
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        path: &str,
+        dry_run: bool,
+        body: Option<Value>,
+    ) -> anyhow::Result<ureq::Response> {
+        let mut request = ureq::request(method, &format!("{}{}", self.url, path));
+        if dry_run {
+            request = request.query("dry_run", "true");
+        }
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        let result = match body {
+            Some(body) => request.send_json(body),
+            None => request.call(),
+        };
+        match result {
+            Ok(response) => Ok(response),
+            Err(ureq::Error::Status(code, response)) => {
+                let url = response.get_url().to_string();
+                let message = response.into_string().unwrap_or_default();
+                Err(anyhow!(
+                    "{} {} failed with {}: {}",
+                    method,
+                    url,
+                    code,
+                    message
+                ))
+            }
+            Err(err) => Err(anyhow!("{}", err)),
+        }
+    }
+
+    fn preview(&self, method: &str, path: &str, body: Option<Value>) -> anyhow::Result<()> {
+        let plan: Plan = self.send(method, path, true, body)?.into_json()?;
+        utils::print_plan(&plan);
+        Ok(())
+    }
+
+    /// Runs a command which only calls a state-changing endpoint.
+    fn post(&self, path: &str, dry_run: bool, done: &str) -> anyhow::Result<()> {
+        if dry_run {
+            return self.preview("POST", path, None);
+        }
+        self.send("POST", path, false, None)?;
+        println!("Device of context {} {}", self.name, done);
+        Ok(())
+    }
+}
+
+/// Runs `command` on the server of `remote`. Only commands with a wghttp endpoint are supported.
+pub fn run(remote: &Remote, command: Commands) -> anyhow::Result<()> {
+    match command {
+        Commands::AddClient {
+            name,
+            tags,
+            device,
+            config_path,
+            dry_run,
+        } => {
+            local_only(&device, &config_path)?;
+            let body = json!({ "name": name, "tags": tags });
+            if dry_run {
+                return remote.preview("POST", "/clients", Some(body));
+            }
+            let id: usize = remote
+                .send("POST", "/clients", false, Some(body))?
+                .into_json()?;
+            println!("Created client with id: {id}");
+        }
+        Commands::EditClient {
+            id,
+            update,
+            device,
+            config_path,
+            dry_run,
+        } => {
+            local_only(&device, &config_path)?;
+            let path = format!("/clients/{}", id);
+            let body = serde_json::to_value(update.into_update())?;
+            if dry_run {
+                return remote.preview("PATCH", &path, Some(body));
+            }
+            remote.send("PATCH", &path, false, Some(body))?;
+            println!("Updated client with id: {id}");
+        }
+        Commands::RemoveClient {
+            id,
+            device,
+            config_path,
+            dry_run,
+        } => {
+            local_only(&device, &config_path)?;
+            let path = format!("/clients/{}", id);
+            if dry_run {
+                return remote.preview("DELETE", &path, None);
+            }
+            remote.send("DELETE", &path, false, None)?;
+            println!("Removed client with id: {id}");
+        }
+        Commands::ClientConf {
+            id,
+            qr,
+            format,
+            output_dir,
+            config_path,
+        } => {
+            local_only(&None, &config_path)?;
+            if output_dir.is_some() {
+                return Err(anyhow!("--output-dir is not supported by remote contexts"));
+            }
+            // QR codes are always made of the wg-quick config.
+            let path = if qr {
+                format!("/config/{}", id)
+            } else {
+                format!("/config/{}?format={}", id, format.name())
+            };
+            let conf = remote.send("GET", &path, false, None)?.into_string()?;
+            if qr {
+                print!("{}", wglib::qr::render_text(&conf)?);
+            } else {
+                print!("{}", conf);
+            }
+        }
+        Commands::ListClients {
+            name,
+            output,
+            device,
+            config_path,
+        } => {
+            local_only(&device, &config_path)?;
+            let format = defaults::prepare_output(output)?;
+            let status: DeviceStatus = remote.send("GET", "/status", false, None)?.into_json()?;
+            let mut clients = status.clients;
+            if let Some(name) = name {
+                clients.retain(|client| client.name == name);
+            }
+            output::print_clients(format, &clients)?;
+        }
+        Commands::Status {
+            output,
+            watch,
+            device,
+            config_path,
+            ..
+        } => {
+            local_only(&device, &config_path)?;
+            if watch {
+                return Err(anyhow!("--watch is not supported by remote contexts"));
+            }
+            let format = defaults::prepare_output(output)?;
+            let status: DeviceStatus = remote.send("GET", "/status", false, None)?.into_json()?;
+            output::print_status(format, &status)?;
+        }
+        Commands::Start {
+            device,
+            config_path,
+            dry_run,
+        } => {
+            local_only(&device, &config_path)?;
+            remote.post("/up", dry_run, "started")?;
+        }
+        Commands::Stop {
+            device,
+            config_path,
+            dry_run,
+        } => {
+            local_only(&device, &config_path)?;
+            remote.post("/down", dry_run, "stopped")?;
+        }
+        Commands::Restart {
+            device,
+            config_path,
+            dry_run,
+        } => {
+            local_only(&device, &config_path)?;
+            remote.post("/reboot", dry_run, "restarted")?;
+        }
+        Commands::Reload {
+            device,
+            config_path,
+            dry_run,
+        } => {
+            local_only(&device, &config_path)?;
+            if dry_run {
+                return remote.preview("POST", "/reload", None);
+            }
+            let diff: PeerDiff = remote.send("POST", "/reload", false, None)?.into_json()?;
+            if diff.is_empty() {
+                println!("Device of context {} is up to date", remote.name);
+            } else {
+                println!(
+                    "Device of context {} reloaded: {} added, {} removed, {} updated",
+                    remote.name,
+                    diff.added.len(),
+                    diff.removed.len(),
+                    diff.updated.len()
+                );
+            }
+        }
+        _ => {
+            return Err(anyhow!(
+                "context {} is remote, which only supports add-client, edit-client, \
+                 remove-client, client-conf, list-clients, status, start, stop, restart and reload",
+                remote.name
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// The device and config of a remote context are chosen by its wghttp server.
+fn local_only(device: &Option<String>, config_path: &Option<PathBuf>) -> anyhow::Result<()> {
+    if device.is_some() || config_path.is_some() {
+        return Err(anyhow!(
+            "--device and --config-path can't be used with remote contexts"
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,363 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::PathBuf,
+    time::Instant,
+};
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent};
+use wglib::{
+    audit::Actor,
+    model::client::ClientUpdate,
+    status::{ClientStatus, DeviceStatus, Throughput},
+};
+
+/// Number of throughput samples kept per client for sparklines.
+pub const HISTORY_LEN: usize = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    Id,
+    Name,
+    Handshake,
+    Traffic,
+    Rate,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            Self::Id => Self::Name,
+            Self::Name => Self::Handshake,
+            Self::Handshake => Self::Traffic,
+            Self::Traffic => Self::Rate,
+            Self::Rate => Self::Id,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Name => "name",
+            Self::Handshake => "handshake",
+            Self::Traffic => "traffic",
+            Self::Rate => "rate",
+        }
+    }
+}
+
+/// What the keyboard currently controls.
+pub enum Mode {
+    Normal,
+    /// Typing the name of a new client.
+    AddClient(String),
+    ConfirmRemove {
+        id: usize,
+        name: String,
+    },
+    ConfirmStop,
+    /// A read-only popup, e.g. a client config or QR code.
+    Popup {
+        title: String,
+        text: String,
+        /// QR codes can't be wrapped, they are only shown if they fit.
+        qr: bool,
+    },
+}
+
+pub struct App {
+    device: String,
+    config_path: PathBuf,
+    actor: Actor,
+    pub status: Option<DeviceStatus>,
+    pub throughput: BTreeMap<usize, Throughput>,
+    /// Total throughput samples per client, oldest first.
+    pub history: BTreeMap<usize, VecDeque<u64>>,
+    previous: Option<(Instant, BTreeMap<usize, ClientStatus>)>,
+    /// Selection by client id, so it stays on the same client when rows are resorted.
+    pub selected: Option<usize>,
+    pub sort: SortKey,
+    pub reverse: bool,
+    pub mode: Mode,
+    /// Result of the last action or refresh error, shown in the status bar.
+    pub message: Option<String>,
+    quit: bool,
+}
+
+impl App {
+    pub fn new(device: String, config_path: PathBuf) -> Self {
+        Self {
+            device,
+            config_path,
+            actor: Actor::current_user(),
+            status: None,
+            throughput: BTreeMap::new(),
+            history: BTreeMap::new(),
+            previous: None,
+            selected: None,
+            sort: SortKey::Id,
+            reverse: false,
+            mode: Mode::Normal,
+            message: None,
+            quit: false,
+        }
+    }
+
+    pub fn should_quit(&self) -> bool {
+        self.quit
+    }
+
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    pub fn refresh(&mut self) {
+        let status = match wglib::actions::get_status(&self.device, &self.config_path) {
+            Ok(status) => status,
+            Err(err) => {
+                self.message = Some(format!("refresh failed: {}", err));
+                return;
+            }
+        };
+        let now = Instant::now();
+        if let Some((sampled_at, before)) = &self.previous {
+            let elapsed = now - *sampled_at;
+            self.throughput = status
+                .clients
+                .iter()
+                .filter_map(|client| {
+                    let throughput = Throughput::between(before.get(&client.id)?, client, elapsed);
+                    Some((client.id, throughput))
+                })
+                .collect();
+        }
+        for client in &status.clients {
+            let samples = self.history.entry(client.id).or_default();
+            let current = self.throughput.get(&client.id).copied().unwrap_or_default();
+            samples.push_back((current.received + current.sent).round() as u64);
+            if samples.len() > HISTORY_LEN {
+                samples.pop_front();
+            }
+        }
+        self.history
+            .retain(|id, _| status.clients.iter().any(|client| client.id == *id));
+
+        self.previous = Some((
+            now,
+            status
+                .clients
+                .iter()
+                .map(|client| (client.id, client.clone()))
+                .collect(),
+        ));
+        self.status = Some(status);
+        let rows = self.rows();
+        if !rows.iter().any(|client| Some(client.id) == self.selected) {
+            self.selected = rows.first().map(|client| client.id);
+        }
+    }
+
+    /// Clients in display order.
+    pub fn rows(&self) -> Vec<&ClientStatus> {
+        let mut rows: Vec<&ClientStatus> = match &self.status {
+            Some(status) => status.clients.iter().collect(),
+            None => Vec::new(),
+        };
+        let rate = |client: &ClientStatus| {
+            let current = self.throughput.get(&client.id).copied().unwrap_or_default();
+            current.received + current.sent
+        };
+        match self.sort {
+            SortKey::Id => rows.sort_by_key(|client| client.id),
+            SortKey::Name => rows.sort_by(|a, b| a.name.cmp(&b.name)),
+            // Most recent first, never connected last.
+            SortKey::Handshake => {
+                rows.sort_by_key(|client| std::cmp::Reverse(client.latest_handshake))
+            }
+            SortKey::Traffic => rows
+                .sort_by_key(|client| std::cmp::Reverse(client.received_bytes + client.sent_bytes)),
+            SortKey::Rate => rows.sort_by(|a, b| rate(b).total_cmp(&rate(a))),
+        }
+        if self.reverse {
+            rows.reverse();
+        }
+        rows
+    }
+
+    fn selected_client(&self) -> Option<ClientStatus> {
+        let status = self.status.as_ref()?;
+        let id = self.selected?;
+        status
+            .clients
+            .iter()
+            .find(|client| client.id == id)
+            .cloned()
+    }
+
+    fn move_selection(&mut self, offset: isize) {
+        let rows: Vec<usize> = self.rows().iter().map(|client| client.id).collect();
+        if rows.is_empty() {
+            return;
+        }
+        let current = rows
+            .iter()
+            .position(|&id| Some(id) == self.selected)
+            .unwrap_or(0);
+        let index = (current as isize + offset).clamp(0, rows.len() as isize - 1);
+        self.selected = Some(rows[index as usize]);
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Normal => self.handle_normal_key(key),
+            Mode::AddClient(mut name) => match key.code {
+                KeyCode::Enter if !name.trim().is_empty() => self.add_client(name.trim()),
+                KeyCode::Esc => {}
+                KeyCode::Backspace => {
+                    name.pop();
+                    self.mode = Mode::AddClient(name);
+                }
+                KeyCode::Char(c) => {
+                    name.push(c);
+                    self.mode = Mode::AddClient(name);
+                }
+                _ => self.mode = Mode::AddClient(name),
+            },
+            Mode::ConfirmRemove { id, name } => match key.code {
+                KeyCode::Char('y') => self.remove_client(id, &name),
+                KeyCode::Char('n') | KeyCode::Esc => {}
+                _ => self.mode = Mode::ConfirmRemove { id, name },
+            },
+            Mode::ConfirmStop => match key.code {
+                KeyCode::Char('y') => self.stop(),
+                KeyCode::Char('n') | KeyCode::Esc => {}
+                _ => self.mode = Mode::ConfirmStop,
+            },
+            Mode::Popup { title, text, qr } => match key.code {
+                KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q') => {}
+                _ => self.mode = Mode::Popup { title, text, qr },
+            },
+        }
+    }
+
+    fn handle_normal_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Home | KeyCode::Char('g') => self.move_selection(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX / 2),
+            KeyCode::Char('s') => self.sort = self.sort.next(),
+            KeyCode::Char('r') => self.reverse = !self.reverse,
+            KeyCode::Char('a') => self.mode = Mode::AddClient(String::new()),
+            KeyCode::Char('d') | KeyCode::Delete => {
+                if let Some(client) = self.selected_client() {
+                    self.mode = Mode::ConfirmRemove {
+                        id: client.id,
+                        name: client.name,
+                    };
+                }
+            }
+            KeyCode::Char('p') => self.toggle_suspended(),
+            KeyCode::Char('c') => self.show_config(false),
+            KeyCode::Char('Q') => self.show_config(true),
+            KeyCode::Char('u') => self.start(),
+            KeyCode::Char('x') => self.mode = Mode::ConfirmStop,
+            _ => {}
+        }
+    }
+
+    fn add_client(&mut self, name: &str) {
+        let result = wglib::actions::add_client(
+            &self.device,
+            &self.config_path,
+            name.to_string(),
+            Vec::new(),
+            &self.actor,
+        );
+        match result {
+            Ok(id) => {
+                self.message = Some(format!("added client {} with id {}", name, id));
+                self.refresh();
+                self.selected = Some(id);
+                self.show_config(false);
+            }
+            Err(err) => self.message = Some(format!("failed to add {}: {}", name, err)),
+        }
+    }
+
+    fn remove_client(&mut self, id: usize, name: &str) {
+        let result =
+            wglib::actions::remove_client(&self.device, &self.config_path, id, &self.actor);
+        self.message = Some(match result {
+            Ok(()) => format!("removed client {}", name),
+            Err(err) => format!("failed to remove {}: {}", name, err),
+        });
+        self.refresh();
+    }
+
+    fn toggle_suspended(&mut self) {
+        let Some(client) = self.selected_client() else {
+            return;
+        };
+        let update = ClientUpdate {
+            disabled: Some(!client.disabled),
+            ..Default::default()
+        };
+        let result = wglib::actions::edit_client(
+            &self.device,
+            &self.config_path,
+            client.id,
+            update,
+            &self.actor,
+        );
+        self.message = Some(match result {
+            Ok(updated) if updated.disabled => format!("suspended {}", updated.name),
+            Ok(updated) => format!("resumed {}", updated.name),
+            Err(err) => format!("failed to update {}: {}", client.name, err),
+        });
+        self.refresh();
+    }
+
+    fn show_config(&mut self, qr: bool) {
+        let Some(client) = self.selected_client() else {
+            return;
+        };
+        let result =
+            wglib::actions::get_client_wg_config(&self.config_path, client.id).and_then(|config| {
+                if qr {
+                    wglib::qr::render_text(&config)
+                } else {
+                    Ok(config)
+                }
+            });
+        match result {
+            Ok(text) => {
+                let kind = if qr { "QR code" } else { "config" };
+                self.mode = Mode::Popup {
+                    title: format!("{} of {}", kind, client.name),
+                    text,
+                    qr,
+                };
+            }
+            Err(err) => self.message = Some(format!("failed to get config: {}", err)),
+        }
+    }
+
+    fn start(&mut self) {
+        let result = wglib::actions::up(&self.device, &self.config_path, &self.actor);
+        self.message = Some(match result {
+            Ok(()) => format!("{} started", self.device),
+            Err(err) => format!("failed to start {}: {}", self.device, err),
+        });
+        self.refresh();
+    }
+
+    fn stop(&mut self) {
+        let result = wglib::actions::down(&self.device, &self.config_path, &self.actor);
+        self.message = Some(match result {
+            Ok(()) => format!("{} stopped", self.device),
+            Err(err) => format!("failed to stop {}: {}", self.device, err),
+        });
+        self.refresh();
+    }
+}
@@ -0,0 +1,49 @@
+mod app;
+mod ui;
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyEventKind},
+    DefaultTerminal,
+};
+
+use app::App;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Runs the dashboard until the user quits. Actions run on the calling thread, so the screen
+/// freezes while e.g. the interface is restarted.
+pub fn run(device: String, config_path: PathBuf) -> anyhow::Result<()> {
+    let mut app = App::new(device, config_path);
+    app.refresh();
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> anyhow::Result<()> {
+    let mut refreshed_at = Instant::now();
+    while !app.should_quit() {
+        terminal.draw(|frame| ui::draw(frame, app))?;
+
+        let timeout = REFRESH_INTERVAL.saturating_sub(refreshed_at.elapsed());
+        if event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    app.handle_key(key);
+                }
+            }
+        }
+        if refreshed_at.elapsed() >= REFRESH_INTERVAL {
+            app.refresh();
+            refreshed_at = Instant::now();
+        }
+    }
+    Ok(())
+}
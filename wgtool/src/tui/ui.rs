@@ -0,0 +1,254 @@
+use std::collections::VecDeque;
+
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Cell, Clear, Paragraph, Row, Sparkline, Table, TableState, Wrap},
+    Frame,
+};
+
+use super::app::{App, Mode, HISTORY_LEN};
+use crate::utils;
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Samples shown in the activity column of the table.
+const ROW_SPARKLINE_LEN: usize = 12;
+
+const HELP: &str = "q quit  j/k move  s sort  r reverse  a add  d remove  p suspend  \
+                    c config  Q qr  u start  x stop";
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [header, table, activity, footer] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(5),
+        Constraint::Length(6),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    draw_header(frame, app, header);
+    draw_table(frame, app, table);
+    draw_activity(frame, app, activity);
+    let footer_text = app.message.as_deref().unwrap_or(HELP);
+    frame.render_widget(
+        Paragraph::new(footer_text).style(Style::default().fg(Color::DarkGray)),
+        footer,
+    );
+
+    match &app.mode {
+        Mode::Normal => {}
+        Mode::AddClient(name) => draw_popup(
+            frame,
+            "Add client",
+            &format!("Name: {}_\n\nEnter to add, Esc to cancel", name),
+        ),
+        Mode::ConfirmRemove { name, .. } => {
+            draw_popup(frame, "Remove client", &format!("Remove {}? (y/n)", name))
+        }
+        Mode::ConfirmStop => draw_popup(
+            frame,
+            "Stop interface",
+            &format!("Stop {}? All clients are disconnected. (y/n)", app.device()),
+        ),
+        Mode::Popup { title, text, qr } => {
+            let title = format!("{} (Esc to close)", title);
+            if *qr && !fits(frame.area(), text) {
+                draw_popup(frame, &title, "terminal too small for QR code");
+            } else {
+                draw_popup(frame, &title, text);
+            }
+        }
+    }
+}
+
+fn draw_header(frame: &mut Frame, app: &App, area: Rect) {
+    let lines = match &app.status {
+        Some(status) => {
+            let (state, color) = if status.running {
+                ("running", Color::Green)
+            } else {
+                ("stopped", Color::Red)
+            };
+            let online = status
+                .clients
+                .iter()
+                .filter(|client| utils::is_online(client))
+                .count();
+            vec![
+                Line::styled(
+                    format!("{} {} on port {}", status.device, state, status.listen_port),
+                    Style::default().fg(color).add_modifier(Modifier::BOLD),
+                ),
+                Line::raw(format!("Public key: {}", status.public_key)),
+                Line::raw(format!(
+                    "Clients: {} of {} online, sorted by {}{}",
+                    online,
+                    status.clients.len(),
+                    app.sort.label(),
+                    if app.reverse { " (reversed)" } else { "" }
+                )),
+            ]
+        }
+        None => vec![Line::raw(format!("Loading {}...", app.device()))],
+    };
+    frame.render_widget(Paragraph::new(lines), area);
+}
+
+fn draw_table(frame: &mut Frame, app: &App, area: Rect) {
+    let rows = app.rows();
+    let header = Row::new([
+        "ID",
+        "NAME",
+        "IP",
+        "ENDPOINT",
+        "HANDSHAKE",
+        "RECEIVED",
+        "SENT",
+        "RX/S",
+        "TX/S",
+        "ACTIVITY",
+    ])
+    .style(Style::default().add_modifier(Modifier::BOLD));
+    let table_rows = rows.iter().map(|client| {
+        let throughput = app.throughput.get(&client.id).copied().unwrap_or_default();
+        let activity = app
+            .history
+            .get(&client.id)
+            .map(|samples| sparkline(samples, ROW_SPARKLINE_LEN))
+            .unwrap_or_default();
+        let style = if client.disabled {
+            Style::default().fg(Color::DarkGray)
+        } else {
+            Style::default()
+        };
+        let name = if client.disabled {
+            format!("{} (suspended)", client.name)
+        } else {
+            client.name.clone()
+        };
+        Row::new([
+            Cell::from(client.id.to_string()),
+            Cell::from(name),
+            Cell::from(client.ip.to_string()),
+            Cell::from(
+                client
+                    .endpoint
+                    .map(|endpoint| endpoint.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            ),
+            Cell::from(utils::format_handshake(client.latest_handshake)),
+            Cell::from(utils::format_bytes(client.received_bytes)),
+            Cell::from(utils::format_bytes(client.sent_bytes)),
+            Cell::from(utils::format_rate(throughput.received)),
+            Cell::from(utils::format_rate(throughput.sent)),
+            Cell::from(activity).style(Style::default().fg(Color::Cyan)),
+        ])
+        .style(style)
+    });
+    let widths = [
+        Constraint::Length(4),
+        Constraint::Min(12),
+        Constraint::Length(15),
+        Constraint::Length(21),
+        Constraint::Length(10),
+        Constraint::Length(10),
+        Constraint::Length(10),
+        Constraint::Length(12),
+        Constraint::Length(12),
+        Constraint::Length(ROW_SPARKLINE_LEN as u16),
+    ];
+    let table = Table::new(table_rows, widths)
+        .header(header)
+        .block(Block::bordered().title(" Clients "))
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut state = TableState::default();
+    state.select(
+        rows.iter()
+            .position(|client| Some(client.id) == app.selected),
+    );
+    frame.render_stateful_widget(table, area, &mut state);
+}
+
+/// Throughput history of the selected client.
+fn draw_activity(frame: &mut Frame, app: &App, area: Rect) {
+    let selected = app.selected.and_then(|id| {
+        let client = app.rows().into_iter().find(|client| client.id == id)?;
+        Some((client.name.clone(), app.history.get(&id)?))
+    });
+    let (title, data): (String, Vec<u64>) = match selected {
+        Some((name, samples)) => {
+            let peak = samples.iter().copied().max().unwrap_or(0);
+            (
+                format!(
+                    " {} throughput, peak {} ",
+                    name,
+                    utils::format_rate(peak as f64)
+                ),
+                samples.iter().copied().collect(),
+            )
+        }
+        None => (" Throughput ".to_string(), Vec::new()),
+    };
+    // Keep the newest samples when the area is narrower than the history.
+    let width = area.width.saturating_sub(2) as usize;
+    let data = &data[data.len().saturating_sub(width.min(HISTORY_LEN))..];
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::bordered().title(title))
+            .data(data)
+            .style(Style::default().fg(Color::Cyan)),
+        area,
+    );
+}
+
+/// Whether `text` fits into a bordered popup without wrapping or cutting lines.
+fn fits(area: Rect, text: &str) -> bool {
+    let width = text.lines().map(|line| line.chars().count()).max();
+    width.unwrap_or(0) + 2 <= area.width as usize
+        && text.lines().count() + 2 <= area.height as usize
+}
+
+fn draw_popup(frame: &mut Frame, title: &str, text: &str) {
+    let area = frame.area();
+    let text_width = text
+        .lines()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let width = (text_width as u16 + 4).max(30).min(area.width);
+    let height = (text.lines().count() as u16 + 2).min(area.height);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(text)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(format!(" {} ", title))),
+        popup,
+    );
+}
+
+/// Draws the last `len` samples with block characters scaled to their maximum.
+fn sparkline(samples: &VecDeque<u64>, len: usize) -> String {
+    let recent: Vec<u64> = samples
+        .iter()
+        .skip(samples.len().saturating_sub(len))
+        .copied()
+        .collect();
+    let max = recent.iter().copied().max().unwrap_or(0);
+    recent
+        .iter()
+        .map(|&sample| {
+            let level = (sample * (BARS.len() as u64 - 1))
+                .checked_div(max)
+                .unwrap_or(0);
+            BARS[level as usize]
+        })
+        .collect()
+}
@@ -1,12 +1,228 @@
-use std::path::PathBuf;
+use std::{io::Write, path::PathBuf};
 
 use anyhow::anyhow;
-use wglib::Client;
+use wglib::{
+    audit::{AuditEntry, Outcome},
+    doctor::{Check, CheckStatus},
+    history::TrafficPoint,
+    model::server::Renumbered,
+    plan::Plan,
+    sessions::{Session, ONLINE_TIMEOUT},
+    status::ClientStatus,
+    sync::{Drift, DriftedPeer},
+    Client,
+};
+
+use crate::defaults;
 
 const WG_CONFIG_PATH_ENV: &str = "WG_CONFIG_PATH";
 
+/// Asks a yes/no question on stdin. No answer, e.g. when stdin isn't a terminal, means no.
+pub fn confirm(question: &str) -> anyhow::Result<bool> {
+    print!("{} ", question);
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    Ok(matches!(line.trim(), "y" | "yes"))
+}
+
 pub fn print_client(client: &Client) {
-    println!("{}\t{}", client.id, client.name);
+    if client.disabled {
+        println!("{}\t{}\tdisabled", client.id, client.name);
+    } else {
+        println!("{}\t{}", client.id, client.name);
+    }
+}
+
+pub fn print_renumbered(client: &Renumbered) {
+    let id = if client.id == client.old_id {
+        client.id.to_string()
+    } else {
+        format!("{} (was {})", client.id, client.old_id)
+    };
+    if client.address == client.old_address {
+        println!("{}\t{}\tnew allowed IPs", id, client.name);
+    } else {
+        println!(
+            "{}\t{}\t{} -> {}",
+            id, client.name, client.old_address, client.address
+        );
+    }
+}
+
+pub fn print_traffic_point(point: &TrafficPoint) {
+    println!(
+        "{}\t{}\t{}\t{}",
+        format_timestamp(point.start),
+        point.client_id,
+        point.received_bytes,
+        point.sent_bytes
+    );
+}
+
+pub fn print_session(session: &Session) {
+    let endpoint = match session.endpoint {
+        Some(endpoint) => endpoint.to_string(),
+        None => "-".to_string(),
+    };
+    let ended_at = match session.ended_at {
+        Some(ended_at) => format_timestamp(ended_at),
+        None => "online".to_string(),
+    };
+    println!(
+        "{}\t{}\t{}\t{}\t{}",
+        format_timestamp(session.started_at),
+        ended_at,
+        endpoint,
+        session.received_bytes,
+        session.sent_bytes
+    );
+}
+
+pub fn print_plan(plan: &Plan) {
+    if plan.config_diff.is_empty() {
+        println!("Config: no changes");
+    } else {
+        print!("{}", plan.config_diff);
+    }
+    if plan.wg_conf_diff.is_empty() {
+        println!("Wireguard config: no changes");
+    } else {
+        print!("{}", plan.wg_conf_diff);
+    }
+    if plan.commands.is_empty() {
+        println!("Commands: none");
+    } else {
+        println!("Commands:");
+        for command in &plan.commands {
+            println!("  {}", command);
+        }
+    }
+    if !plan.files.is_empty() {
+        println!("Files:");
+        for file in &plan.files {
+            println!("  {}", file);
+        }
+    }
+    println!("Dry run, nothing was changed");
+}
+
+pub fn print_check(check: &Check) {
+    let status = match check.status {
+        CheckStatus::Pass => "PASS",
+        CheckStatus::Warn => "WARN",
+        CheckStatus::Fail => "FAIL",
+    };
+    println!("[{}] {}: {}", status, check.name, check.detail);
+    if let Some(hint) = &check.hint {
+        println!("       {}", hint);
+    }
+}
+
+pub fn print_drift(drift: &Drift) {
+    for peer in &drift.config_only {
+        print_drifted_peer("config-only", peer);
+    }
+    for peer in &drift.interface_only {
+        print_drifted_peer("interface-only", peer);
+    }
+    for peer in &drift.mismatched {
+        print_drifted_peer("mismatched", peer);
+    }
+}
+
+fn print_drifted_peer(kind: &str, peer: &DriftedPeer) {
+    let id = match peer.client_id {
+        Some(id) => id.to_string(),
+        None => "-".to_string(),
+    };
+    let join = |ips: &[ipnet::IpNet]| {
+        if ips.is_empty() {
+            "-".to_string()
+        } else {
+            ips.iter()
+                .map(|ip| ip.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+    };
+    println!(
+        "{}\t{}\t{}\t{}\tconfig: {}\tinterface: {}",
+        kind,
+        id,
+        peer.client_name.as_deref().unwrap_or("-"),
+        peer.public_key,
+        join(&peer.config_allowed_ips),
+        join(&peer.interface_allowed_ips)
+    );
+}
+
+pub fn print_audit_entry(entry: &AuditEntry) {
+    let client = match entry.client_id {
+        Some(id) => id.to_string(),
+        None => "-".to_string(),
+    };
+    let outcome = match &entry.outcome {
+        Outcome::Success => "ok".to_string(),
+        Outcome::Failure { error } => format!("failed: {}", error),
+    };
+    println!(
+        "{}\t{}\t{}\t{}\t{}",
+        format_timestamp(entry.timestamp),
+        entry.actor,
+        entry.action,
+        client,
+        outcome
+    );
+}
+
+/// Returns whether the latest handshake of `client` is recent enough to count it as online.
+pub fn is_online(client: &ClientStatus) -> bool {
+    client.latest_handshake.is_some_and(|timestamp| {
+        wglib::metrics::get_seconds_from_now(timestamp)
+            .is_ok_and(|seconds| seconds < ONLINE_TIMEOUT)
+    })
+}
+
+/// Formats a handshake timestamp relative to now, e.g. `5m ago`.
+pub fn format_handshake(timestamp: Option<u64>) -> String {
+    let seconds = match timestamp.map(wglib::metrics::get_seconds_from_now) {
+        Some(Ok(seconds)) => seconds,
+        Some(Err(_)) => return "-".to_string(),
+        None => return "never".to_string(),
+    };
+    match seconds {
+        0..=59 => format!("{}s ago", seconds),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+/// Formats a byte count with binary units, e.g. `1.5 MiB`.
+pub fn format_bytes(bytes: u128) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+pub fn format_rate(bytes_per_second: f64) -> String {
+    format!("{}/s", format_bytes(bytes_per_second.round() as u128))
+}
+
+pub fn format_timestamp(timestamp: u64) -> String {
+    match chrono::DateTime::from_timestamp(timestamp as i64, 0) {
+        Some(datetime) => datetime.format("%Y-%m-%d %H:%M").to_string(),
+        None => timestamp.to_string(),
+    }
 }
 
 pub fn get_config_path_with_sudo(mut config_path: PathBuf) -> anyhow::Result<PathBuf> {
@@ -14,7 +230,8 @@ pub fn get_config_path_with_sudo(mut config_path: PathBuf) -> anyhow::Result<Pat
         config_path = path.into();
     } else {
         std::env::set_var(WG_CONFIG_PATH_ENV, &config_path);
-        sudo::with_env(&[WG_CONFIG_PATH_ENV]).map_err(|err| anyhow!("{}", err))?;
+        sudo::with_env(&[WG_CONFIG_PATH_ENV, defaults::CONTEXT_FILE_ENV])
+            .map_err(|err| anyhow!("{}", err))?;
     }
     Ok(config_path)
 }
@@ -0,0 +1,245 @@
+//! Questions of `wgtool init` when it runs in a terminal.
+
+use std::{
+    io::Write,
+    net::{IpAddr, Ipv4Addr},
+    path::Path,
+};
+
+use anyhow::anyhow;
+use ipnet::Ipv4Net;
+use wglib::{
+    model::{
+        client::{ClientDefaults, Routing, DEFAULT_DNS},
+        pool::AddressPool,
+    },
+    net::{self, InterfaceAddress, Route},
+    Server,
+};
+
+use crate::commands::InitOptions;
+
+const DEFAULT_PORT: u16 = 51820;
+
+/// Asks for every setting not given in `options`, validating the answers, and returns the
+/// resulting server once it's confirmed to be written to `output`.
+pub fn ask(options: InitOptions, output: &Path) -> anyhow::Result<Server> {
+    let routes = match net::read_routes() {
+        Ok(routes) => routes,
+        Err(err) => {
+            println!("Subnets aren't checked against local routes, {}", err);
+            Vec::new()
+        }
+    };
+    // The uplink with its global addresses comes first and is the default.
+    let mut candidates = net::interface_addresses(&routes);
+    net::sort_candidates(&routes, &mut candidates);
+
+    // Values given as flags go through the same checks as the answers.
+    let endpoint = match options.endpoint {
+        Some(endpoint) => {
+            net::validate_endpoint(&endpoint)?;
+            endpoint
+        }
+        None => ask_endpoint(&candidates)?,
+    };
+    let port = match options.port {
+        Some(port) => check_port(port)?,
+        None => prompt("Listen port", Some(DEFAULT_PORT.to_string()), |answer| {
+            check_port(
+                answer
+                    .parse()
+                    .map_err(|_| anyhow!("{} is not a port number", answer))?,
+            )
+        })?,
+    };
+    let subnet = match options.subnet {
+        Some(subnet) => parse_subnet(&subnet, &routes)?,
+        None => prompt(
+            "VPN subnet",
+            free_subnet(&routes).map(|subnet| subnet.to_string()),
+            |answer| parse_subnet(answer, &routes),
+        )?,
+    };
+    let network_interface = match options.network_interface {
+        Some(network_interface) => network_interface,
+        None => {
+            // The interface of the endpoint address if it's a local one, otherwise the uplink.
+            let default = candidates
+                .iter()
+                .find(|candidate| candidate.address.addr().to_string() == endpoint)
+                .or(candidates.first())
+                .map(|candidate| candidate.interface.clone());
+            prompt("Network interface for NAT", default, |answer| {
+                if candidates
+                    .iter()
+                    .any(|candidate| candidate.interface == answer)
+                {
+                    Ok(answer.to_string())
+                } else {
+                    Err(anyhow!(
+                        "{} is not an interface with an IPv4 address",
+                        answer
+                    ))
+                }
+            })?
+        }
+    };
+    let dns = if options.dns.is_empty() {
+        prompt(
+            "DNS servers of clients",
+            Some(DEFAULT_DNS.to_string()),
+            |answer| {
+                answer
+                    .split([',', ' '])
+                    .filter(|server| !server.is_empty())
+                    .map(|server| {
+                        server
+                            .parse::<IpAddr>()
+                            .map_err(|_| anyhow!("{} is not an IP address", server))
+                    })
+                    .collect::<anyhow::Result<Vec<IpAddr>>>()
+            },
+        )?
+    } else {
+        options.dns
+    };
+    let routing = match options.routing {
+        Some(routing) => routing,
+        None => prompt(
+            "Route all traffic of clients (full) or only the VPN subnet (split)",
+            Some("full".to_string()),
+            |answer| answer.parse::<Routing>(),
+        )?,
+    };
+
+    let mut server = Server::new(subnet, endpoint, port, network_interface);
+    server.client_defaults = ClientDefaults {
+        dns: (dns != [DEFAULT_DNS]).then_some(dns.clone()),
+        routing,
+    };
+
+    println!();
+    println!("Endpoint: {}", server.endpoint_address());
+    println!(
+        "Subnet: {}, NAT through {}",
+        server.subnet, server.network_interface
+    );
+    println!(
+        "Clients: DNS {}, {} routing",
+        dns.iter()
+            .map(|server| server.to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        routing
+    );
+    let confirmed = prompt(
+        &format!("Create config at {:?}? (y/n)", output),
+        Some("y".to_string()),
+        |answer| match answer {
+            "y" | "yes" => Ok(true),
+            "n" | "no" => Ok(false),
+            _ => Err(anyhow!("answer y or n")),
+        },
+    )?;
+    if !confirmed {
+        return Err(anyhow!("Nothing was created"));
+    }
+    Ok(server)
+}
+
+fn ask_endpoint(candidates: &[InterfaceAddress]) -> anyhow::Result<String> {
+    println!("Addresses of this host:");
+    for (i, candidate) in candidates.iter().enumerate() {
+        let ip = candidate.address.addr();
+        let kind = if net::is_global(ip) {
+            ""
+        } else {
+            " (private, reachable from the internet only through port forwarding)"
+        };
+        println!("  {}) {} on {}{}", i + 1, ip, candidate.interface, kind);
+    }
+    let default = (!candidates.is_empty()).then(|| "1".to_string());
+    prompt(
+        "Endpoint, a number from the list or a hostname or IP address",
+        default,
+        |answer| match answer.parse::<usize>() {
+            Ok(number) => candidates
+                .get(number.wrapping_sub(1))
+                .map(|candidate| candidate.address.addr().to_string())
+                .ok_or_else(|| anyhow!("choose a number from 1 to {}", candidates.len())),
+            Err(_) => {
+                net::validate_endpoint(answer)?;
+                Ok(answer.to_string())
+            }
+        },
+    )
+}
+
+fn check_port(port: u16) -> anyhow::Result<u16> {
+    if port == 0 {
+        return Err(anyhow!("port must not be 0"));
+    }
+    net::check_port_free(port)?;
+    Ok(port)
+}
+
+fn parse_subnet(subnet: &str, routes: &[Route]) -> anyhow::Result<Ipv4Net> {
+    let subnet: Ipv4Net = subnet
+        .parse()
+        .map_err(|_| anyhow!("{} is not a subnet like 10.0.0.0/24", subnet))?;
+    check_subnet(&subnet.trunc(), routes)?;
+    Ok(subnet.trunc())
+}
+
+/// The subnet must leave room for clients and must not be routed anywhere else already.
+fn check_subnet(subnet: &Ipv4Net, routes: &[Route]) -> anyhow::Result<()> {
+    AddressPool::default().client_range(subnet)?;
+    let overlapping: Vec<String> = net::overlapping_routes(subnet, routes)
+        .iter()
+        .map(|route| format!("{} on {}", route.destination, route.interface))
+        .collect();
+    if !overlapping.is_empty() {
+        return Err(anyhow!(
+            "{} overlaps with local routes: {}",
+            subnet,
+            overlapping.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// The first of 10.0.0.0/24, 10.0.1.0/24, ... which no local route overlaps.
+fn free_subnet(routes: &[Route]) -> Option<Ipv4Net> {
+    (0..=255)
+        .map(|i| Ipv4Net::new(Ipv4Addr::new(10, 0, i, 0), 24).unwrap())
+        .find(|subnet| check_subnet(subnet, routes).is_ok())
+}
+
+/// Repeats the question until `parse` accepts the answer. An empty answer takes `default`.
+fn prompt<T>(
+    question: &str,
+    default: Option<String>,
+    parse: impl Fn(&str) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    loop {
+        match &default {
+            Some(default) => print!("{} [{}]: ", question, default),
+            None => print!("{}: ", question),
+        }
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            return Err(anyhow!("No answer, nothing was created"));
+        }
+        let answer = match (line.trim(), &default) {
+            ("", Some(default)) => default.as_str(),
+            ("", None) => continue,
+            (answer, _) => answer,
+        };
+        match parse(answer) {
+            Ok(value) => return Ok(value),
+            Err(err) => println!("  {}", err),
+        }
+    }
+}